@@ -4,3 +4,28 @@ DROP TABLE "group_characters";
 DROP TABLE "series";
 DROP TABLE "users";
 DROP TABLE "user_characters";
+DROP TABLE "owned_characters";
+DROP TABLE "events";
+DROP TABLE "wallets";
+DROP TABLE "transactions";
+DROP TABLE "daily_streaks";
+DROP TABLE "inventories";
+DROP TABLE "auctions";
+DROP TABLE "duels";
+DROP TABLE "duel_records";
+DROP TABLE "quizzes";
+DROP TABLE "quiz_scores";
+DROP TABLE "sync_changes";
+DROP TABLE "tags";
+DROP TABLE "tag_names";
+DROP TABLE "characters_tags";
+DROP TABLE "groups_tags";
+DROP TABLE "characters_series";
+DROP TABLE "groups_series";
+DROP TABLE "likes";
+DROP TABLE "raid_participants";
+DROP TABLE "character_variants";
+DROP TABLE "images";
+DROP TABLE "photo_references";
+DROP TABLE "characters_search";
+DROP TABLE "series_search";
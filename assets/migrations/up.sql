@@ -1,12 +1,11 @@
 CREATE TABLE "characters" (
 	"id"	INTEGER UNIQUE,
 	"name"	TEXT NOT NULL,
-	"image"	TEXT,
+	"image_hash"	TEXT,
 	"stars"	INTEGER NOT NULL DEFAULT 1,
 	"gender"	TEXT NOT NULL,
 	"artist"	TEXT NOT NULL DEFAULT 'Artist',
 	"aliases"	TEXT NOT NULL DEFAULT '[]',
-	"liked_by"	TEXT NOT NULL DEFAULT '[]',
 	"series_id"	INTEGER NOT NULL DEFAULT 0,
 	"image_link"	TEXT NOT NULL DEFAULT '.',
 	"anilist_id"	INTEGER,
@@ -17,6 +16,7 @@ CREATE TABLE "groups" (
 	"id"	INTEGER UNIQUE,
 	"title"	TEXT NOT NULL,
 	"username"	TEXT UNIQUE,
+	"utc_offset"	INTEGER,
 	"language_code"	TEXT NOT NULL,
 	PRIMARY KEY("id")
 );
@@ -25,8 +25,12 @@ CREATE TABLE "group_characters" (
 	"id"	INTEGER UNIQUE,
 	"group_id"	INTEGER NOT NULL,
 	"character_id"	INTEGER NOT NULL,
+	"variant_id"	INTEGER,
 	"last_message_id"	INTEGER NOT NULL,
 	"available"	INTEGER NOT NULL,
+	"kind"	TEXT NOT NULL DEFAULT 'normal',
+	"required"	INTEGER NOT NULL DEFAULT 0,
+	"expires_at"	INTEGER NOT NULL DEFAULT 0,
 	PRIMARY KEY("id" AUTOINCREMENT)
 );
 
@@ -34,11 +38,12 @@ CREATE TABLE "series" (
 	"id"	INTEGER NOT NULL,
 	"title"	TEXT NOT NULL,
 	"artist"	TEXT NOT NULL DEFAULT 'Artist',
-	"banner"	TEXT,
+	"banner_hash"	TEXT,
 	"aliases"	TEXT NOT NULL DEFAULT '[]',
-	"liked_by"	TEXT NOT NULL DEFAULT '[]',
 	"image_link"	TEXT NOT NULL DEFAULT '.',
 	"media_type"	TEXT NOT NULL DEFAULT 'unknown',
+	"parent_id"	INTEGER,
+	"anilist_id"	INTEGER,
 	PRIMARY KEY("id" AUTOINCREMENT)
 );
 
@@ -46,6 +51,7 @@ CREATE TABLE "users" (
 	"id"	INTEGER UNIQUE,
 	"username"	TEXT UNIQUE,
 	"full_name"	TEXT NOT NULL,
+	"utc_offset"	INTEGER,
 	"language_code"	TEXT NOT NULL,
 	PRIMARY KEY("id")
 );
@@ -57,3 +63,304 @@ CREATE TABLE "user_characters" (
 	"characters_id"	TEXT NOT NULL DEFAULT '[]',
 	PRIMARY KEY("id" AUTOINCREMENT)
 );
+
+CREATE TABLE "owned_characters" (
+	"id"	INTEGER UNIQUE,
+	"user_id"	INTEGER NOT NULL,
+	"group_id"	INTEGER NOT NULL,
+	"character_id"	INTEGER NOT NULL,
+	"variant_id"	INTEGER,
+	"level"	INTEGER NOT NULL DEFAULT 1,
+	"xp"	INTEGER NOT NULL DEFAULT 0,
+	"obtained_at"	INTEGER NOT NULL,
+	PRIMARY KEY("id" AUTOINCREMENT)
+);
+
+CREATE TABLE "events" (
+	"id"	INTEGER UNIQUE,
+	"name"	TEXT NOT NULL,
+	"boost"	INTEGER NOT NULL DEFAULT 50,
+	"ends_at"	INTEGER NOT NULL,
+	"starts_at"	INTEGER NOT NULL,
+	"spawn_text"	TEXT NOT NULL DEFAULT '',
+	"boosted_series"	TEXT NOT NULL DEFAULT '[]',
+	"boosted_characters"	TEXT NOT NULL DEFAULT '[]',
+	"exclusive_characters"	TEXT NOT NULL DEFAULT '[]',
+	PRIMARY KEY("id" AUTOINCREMENT)
+);
+
+CREATE TABLE "wallets" (
+	"user_id"	INTEGER UNIQUE,
+	"balance"	INTEGER NOT NULL DEFAULT 0,
+	PRIMARY KEY("user_id")
+);
+
+CREATE TABLE "transactions" (
+	"id"	INTEGER UNIQUE,
+	"user_id"	INTEGER NOT NULL,
+	"amount"	INTEGER NOT NULL,
+	"reason"	TEXT NOT NULL,
+	"created_at"	INTEGER NOT NULL,
+	PRIMARY KEY("id" AUTOINCREMENT)
+);
+
+CREATE TABLE "daily_streaks" (
+	"user_id"	INTEGER UNIQUE,
+	"streak"	INTEGER NOT NULL DEFAULT 0,
+	"last_period"	INTEGER NOT NULL DEFAULT 0,
+	PRIMARY KEY("user_id")
+);
+
+CREATE TABLE "inventories" (
+	"user_id"	INTEGER UNIQUE,
+	"hint_tokens"	INTEGER NOT NULL DEFAULT 0,
+	"extra_slots"	INTEGER NOT NULL DEFAULT 0,
+	PRIMARY KEY("user_id")
+);
+
+CREATE TABLE "auctions" (
+	"id"	INTEGER UNIQUE,
+	"chat"	BLOB NOT NULL,
+	"group_id"	INTEGER NOT NULL,
+	"seller_id"	INTEGER NOT NULL,
+	"character_id"	INTEGER NOT NULL,
+	"message_id"	INTEGER NOT NULL,
+	"bid"	INTEGER NOT NULL,
+	"bidder_id"	INTEGER,
+	"ends_at"	INTEGER NOT NULL,
+	PRIMARY KEY("id")
+);
+
+CREATE TABLE "duels" (
+	"id"	INTEGER UNIQUE,
+	"group_id"	INTEGER NOT NULL,
+	"challenger_id"	INTEGER NOT NULL,
+	"opponent_id"	INTEGER NOT NULL,
+	"stake"	INTEGER NOT NULL DEFAULT 0,
+	"seed"	INTEGER NOT NULL,
+	"challenger_character"	INTEGER,
+	"opponent_character"	INTEGER,
+	"created_at"	INTEGER NOT NULL,
+	PRIMARY KEY("id")
+);
+
+CREATE TABLE "duel_records" (
+	"user_id"	INTEGER UNIQUE,
+	"wins"	INTEGER NOT NULL DEFAULT 0,
+	"losses"	INTEGER NOT NULL DEFAULT 0,
+	PRIMARY KEY("user_id")
+);
+
+CREATE TABLE "quizzes" (
+	"group_id"	INTEGER UNIQUE,
+	"series_id"	INTEGER NOT NULL,
+	"message_id"	INTEGER NOT NULL,
+	"expires_at"	INTEGER NOT NULL,
+	PRIMARY KEY("group_id")
+);
+
+CREATE TABLE "quiz_scores" (
+	"group_id"	INTEGER NOT NULL,
+	"user_id"	INTEGER NOT NULL,
+	"score"	INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE "sync_changes" (
+	"id"	INTEGER UNIQUE,
+	"kind"	TEXT NOT NULL,
+	"target_id"	INTEGER NOT NULL,
+	"field"	TEXT NOT NULL,
+	"old_value"	TEXT NOT NULL,
+	"new_value"	TEXT NOT NULL,
+	"created_at"	INTEGER NOT NULL,
+	PRIMARY KEY("id")
+);
+
+CREATE TABLE "tags" (
+	"id"	INTEGER UNIQUE,
+	"name"	TEXT NOT NULL UNIQUE,
+	PRIMARY KEY("id" AUTOINCREMENT)
+);
+
+CREATE TABLE "tag_names" (
+	"tag_id"	INTEGER NOT NULL,
+	"language_code"	TEXT NOT NULL,
+	"name"	TEXT NOT NULL,
+	PRIMARY KEY("tag_id","language_code")
+);
+
+CREATE TABLE "characters_tags" (
+	"character_id"	INTEGER NOT NULL,
+	"tag_id"	INTEGER NOT NULL,
+	PRIMARY KEY("character_id","tag_id")
+);
+
+CREATE TABLE "groups_tags" (
+	"group_id"	INTEGER NOT NULL,
+	"tag_id"	INTEGER NOT NULL,
+	PRIMARY KEY("group_id","tag_id")
+);
+
+CREATE TABLE "characters_series" (
+	"character_id"	INTEGER NOT NULL,
+	"series_id"	INTEGER NOT NULL,
+	PRIMARY KEY("character_id","series_id")
+);
+
+CREATE TABLE "likes" (
+	"user_id"	INTEGER NOT NULL,
+	"kind"	TEXT NOT NULL,
+	"target_id"	INTEGER NOT NULL,
+	"created_at"	INTEGER NOT NULL,
+	PRIMARY KEY("user_id","kind","target_id")
+);
+
+CREATE INDEX "likes_target" ON "likes" ("kind", "target_id");
+
+CREATE UNIQUE INDEX "owned_characters_user_character" ON "owned_characters" ("user_id", "group_id", "character_id");
+
+CREATE TABLE "raid_participants" (
+	"group_id"	INTEGER NOT NULL,
+	"message_id"	INTEGER NOT NULL,
+	"user_id"	INTEGER NOT NULL,
+	"guess"	TEXT NOT NULL,
+	"joined_at"	INTEGER NOT NULL,
+	PRIMARY KEY("group_id","message_id","user_id")
+);
+
+CREATE TABLE "groups_series" (
+	"group_id"	INTEGER NOT NULL,
+	"series_id"	INTEGER NOT NULL,
+	PRIMARY KEY("group_id","series_id")
+);
+
+CREATE TABLE "character_variants" (
+	"id"	INTEGER UNIQUE,
+	"character_id"	INTEGER NOT NULL,
+	"position"	INTEGER NOT NULL DEFAULT 0,
+	"image_hash"	TEXT,
+	"artist"	TEXT NOT NULL,
+	"image_link"	TEXT NOT NULL,
+	"rarity"	INTEGER,
+	PRIMARY KEY("id" AUTOINCREMENT)
+);
+
+CREATE TABLE "images" (
+	"hash"	TEXT NOT NULL UNIQUE,
+	"size"	INTEGER NOT NULL,
+	"mime_type"	TEXT NOT NULL,
+	"phash"	INTEGER,
+	"created_at"	INTEGER NOT NULL,
+	PRIMARY KEY("hash")
+);
+
+CREATE TABLE "photo_references" (
+	"key"	TEXT NOT NULL UNIQUE,
+	"photo_id"	INTEGER NOT NULL,
+	"access_hash"	INTEGER NOT NULL,
+	"file_reference"	BLOB NOT NULL,
+	PRIMARY KEY("key")
+);
+
+CREATE VIRTUAL TABLE "characters_search" USING fts5("name", "aliases", "series", tokenize = 'unicode61 remove_diacritics 2');
+
+CREATE VIRTUAL TABLE "series_search" USING fts5("title", "aliases", tokenize = 'unicode61 remove_diacritics 2');
+
+CREATE TRIGGER "characters_search_insert" AFTER INSERT ON "characters" BEGIN
+	DELETE FROM "characters_search" WHERE "rowid" = new."id";
+	INSERT INTO "characters_search" ("rowid", "name", "aliases", "series") SELECT "id", "name", CASE WHEN json_valid("characters"."aliases") THEN (SELECT group_concat("value", ' ') FROM json_each("characters"."aliases")) ELSE "characters"."aliases" END, (SELECT group_concat("series"."title", ' ') FROM "characters_series" JOIN "series" ON "series"."id" = "characters_series"."series_id" WHERE "characters_series"."character_id" = "characters"."id") FROM "characters" WHERE "id" = new."id";
+END;
+
+CREATE TRIGGER "characters_search_update" AFTER UPDATE OF "id", "name", "aliases", "series_id" ON "characters" BEGIN
+	DELETE FROM "characters_search" WHERE "rowid" = old."id";
+	DELETE FROM "characters_search" WHERE "rowid" = new."id";
+	INSERT INTO "characters_search" ("rowid", "name", "aliases", "series") SELECT "id", "name", CASE WHEN json_valid("characters"."aliases") THEN (SELECT group_concat("value", ' ') FROM json_each("characters"."aliases")) ELSE "characters"."aliases" END, (SELECT group_concat("series"."title", ' ') FROM "characters_series" JOIN "series" ON "series"."id" = "characters_series"."series_id" WHERE "characters_series"."character_id" = "characters"."id") FROM "characters" WHERE "id" = new."id";
+END;
+
+CREATE TRIGGER "characters_search_delete" AFTER DELETE ON "characters" BEGIN
+	DELETE FROM "characters_search" WHERE "rowid" = old."id";
+END;
+
+CREATE TRIGGER "characters_series_search_insert" AFTER INSERT ON "characters_series" BEGIN
+	DELETE FROM "characters_search" WHERE "rowid" = new."character_id";
+	INSERT INTO "characters_search" ("rowid", "name", "aliases", "series") SELECT "id", "name", CASE WHEN json_valid("characters"."aliases") THEN (SELECT group_concat("value", ' ') FROM json_each("characters"."aliases")) ELSE "characters"."aliases" END, (SELECT group_concat("series"."title", ' ') FROM "characters_series" JOIN "series" ON "series"."id" = "characters_series"."series_id" WHERE "characters_series"."character_id" = "characters"."id") FROM "characters" WHERE "id" = new."character_id";
+END;
+
+CREATE TRIGGER "characters_series_search_delete" AFTER DELETE ON "characters_series" BEGIN
+	DELETE FROM "characters_search" WHERE "rowid" = old."character_id";
+	INSERT INTO "characters_search" ("rowid", "name", "aliases", "series") SELECT "id", "name", CASE WHEN json_valid("characters"."aliases") THEN (SELECT group_concat("value", ' ') FROM json_each("characters"."aliases")) ELSE "characters"."aliases" END, (SELECT group_concat("series"."title", ' ') FROM "characters_series" JOIN "series" ON "series"."id" = "characters_series"."series_id" WHERE "characters_series"."character_id" = "characters"."id") FROM "characters" WHERE "id" = old."character_id";
+END;
+
+CREATE TRIGGER "series_search_insert" AFTER INSERT ON "series" BEGIN
+	INSERT INTO "series_search" ("rowid", "title", "aliases") VALUES (new."id", new."title", CASE WHEN json_valid(new."aliases") THEN (SELECT group_concat("value", ' ') FROM json_each(new."aliases")) ELSE new."aliases" END);
+	UPDATE "characters_search" SET "series" = (SELECT group_concat("series"."title", ' ') FROM "characters_series" JOIN "series" ON "series"."id" = "characters_series"."series_id" WHERE "characters_series"."character_id" = "characters_search"."rowid") WHERE "rowid" IN (SELECT "character_id" FROM "characters_series" WHERE "series_id" = new."id");
+END;
+
+CREATE TRIGGER "series_search_update" AFTER UPDATE OF "id", "title", "aliases" ON "series" BEGIN
+	DELETE FROM "series_search" WHERE "rowid" = old."id";
+	INSERT INTO "series_search" ("rowid", "title", "aliases") VALUES (new."id", new."title", CASE WHEN json_valid(new."aliases") THEN (SELECT group_concat("value", ' ') FROM json_each(new."aliases")) ELSE new."aliases" END);
+	UPDATE "characters_search" SET "series" = (SELECT group_concat("series"."title", ' ') FROM "characters_series" JOIN "series" ON "series"."id" = "characters_series"."series_id" WHERE "characters_series"."character_id" = "characters_search"."rowid") WHERE "rowid" IN (SELECT "character_id" FROM "characters_series" WHERE "series_id" IN (old."id", new."id"));
+END;
+
+CREATE TRIGGER "series_search_delete" AFTER DELETE ON "series" BEGIN
+	DELETE FROM "series_search" WHERE "rowid" = old."id";
+	UPDATE "characters_search" SET "series" = (SELECT group_concat("series"."title", ' ') FROM "characters_series" JOIN "series" ON "series"."id" = "characters_series"."series_id" WHERE "characters_series"."character_id" = "characters_search"."rowid") WHERE "rowid" IN (SELECT "character_id" FROM "characters_series" WHERE "series_id" = old."id");
+END;
+
+CREATE TRIGGER "characters_photo_update" AFTER UPDATE OF "image_hash" ON "characters" WHEN old."image_hash" IS NOT new."image_hash" BEGIN
+	DELETE FROM "photo_references" WHERE "key" = 'character_' || old."id";
+END;
+
+CREATE TRIGGER "characters_photo_delete" AFTER DELETE ON "characters" BEGIN
+	DELETE FROM "photo_references" WHERE "key" = 'character_' || old."id";
+END;
+
+CREATE TRIGGER "series_photo_update" AFTER UPDATE OF "banner_hash" ON "series" WHEN old."banner_hash" IS NOT new."banner_hash" BEGIN
+	DELETE FROM "photo_references" WHERE "key" = 'series_' || old."id";
+END;
+
+CREATE TRIGGER "series_photo_delete" AFTER DELETE ON "series" BEGIN
+	DELETE FROM "photo_references" WHERE "key" = 'series_' || old."id";
+END;
+
+CREATE TRIGGER "variants_photo_update" AFTER UPDATE OF "image_hash" ON "character_variants" WHEN old."image_hash" IS NOT new."image_hash" BEGIN
+	DELETE FROM "photo_references" WHERE "key" = 'variant_' || old."id";
+END;
+
+CREATE TRIGGER "variants_photo_delete" AFTER DELETE ON "character_variants" BEGIN
+	DELETE FROM "photo_references" WHERE "key" = 'variant_' || old."id";
+END;
+
+CREATE TRIGGER "series_franchise_delete" AFTER DELETE ON "series" BEGIN
+	UPDATE "series" SET "parent_id" = old."parent_id" WHERE "parent_id" = old."id";
+	DELETE FROM "groups_series" WHERE "series_id" = old."id";
+END;
+
+CREATE TRIGGER "characters_series_insert" AFTER INSERT ON "characters" BEGIN
+	INSERT OR IGNORE INTO "characters_series" ("character_id", "series_id") VALUES (new."id", new."series_id");
+END;
+
+CREATE TRIGGER "characters_series_update" AFTER UPDATE OF "series_id" ON "characters" WHEN old."series_id" IS NOT new."series_id" BEGIN
+	DELETE FROM "characters_series" WHERE "character_id" = old."id" AND "series_id" = old."series_id";
+	INSERT OR IGNORE INTO "characters_series" ("character_id", "series_id") VALUES (new."id", new."series_id");
+END;
+
+CREATE TRIGGER "characters_series_delete" AFTER DELETE ON "characters" BEGIN
+	DELETE FROM "characters_series" WHERE "character_id" = old."id";
+END;
+
+CREATE TRIGGER "series_characters_delete" AFTER DELETE ON "series" BEGIN
+	DELETE FROM "characters_series" WHERE "series_id" = old."id";
+END;
+
+CREATE TRIGGER "raid_participants_delete" AFTER DELETE ON "group_characters" BEGIN
+	DELETE FROM "raid_participants" WHERE "group_id" = old."group_id" AND "message_id" = old."last_message_id";
+END;
+
+CREATE TRIGGER "characters_likes_delete" AFTER DELETE ON "characters" BEGIN
+	DELETE FROM "likes" WHERE "kind" = 'character' AND "target_id" = old."id";
+END;
+
+CREATE TRIGGER "series_likes_delete" AFTER DELETE ON "series" BEGIN
+	DELETE FROM "likes" WHERE "kind" = 'series' AND "target_id" = old."id";
+END;
@@ -6,3 +6,61 @@ api_hash = ""
 token = ""
 catch_up = false
 flood_sleep_threshold = 180
+
+[spawn]
+max_active = 3
+escape_after = 35
+
+raid_chance = 0.05
+raid_window = 180
+raid_min_stars = 5
+raid_participants = 3
+
+[daily]
+reward = 100
+max_streak = 7
+pull_chance = 0.1
+period_hours = 24
+streak_bonus = 0.1
+
+[auctions]
+min_duration = 300
+max_duration = 259200
+min_increment = 10
+check_interval = 30
+
+[duels]
+use_level = true
+luck = 30
+max_stake = 1000
+timeout = 600
+
+[quiz]
+reward = 50
+timeout = 60
+
+[anilist]
+url = "https://graphql.anilist.co"
+sync_interval = 24
+auto_apply = false
+request_delay = 1000
+
+[[shop.items]]
+id = "slot"
+price = 500
+effect = "extra_slot"
+
+[[shop.items]]
+id = "hint"
+price = 50
+effect = "hint_token"
+
+[[shop.items]]
+id = "reroll"
+price = 150
+effect = "reroll"
+
+[[shop.items]]
+id = "summon"
+price = 300
+effect = "summon_series"
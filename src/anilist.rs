@@ -0,0 +1,614 @@
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    database::models::{Character, Gender, Media, Series},
+    Result,
+};
+
+const MEDIA_QUERY: &str = "query ($id: Int, $page: Int) {
+  Media(id: $id) {
+    id
+    format
+    countryOfOrigin
+    synonyms
+    bannerImage
+    coverImage { extraLarge }
+    title { romaji english }
+    characters(page: $page, perPage: 25, sort: [ROLE, RELEVANCE]) {
+      pageInfo { hasNextPage }
+      edges {
+        role
+        node {
+          id
+          gender
+          name { full alternative }
+          image { large }
+        }
+      }
+    }
+  }
+}";
+
+const SERIES_QUERY: &str = "query ($id: Int) {
+  Media(id: $id) {
+    id
+    format
+    countryOfOrigin
+    synonyms
+    bannerImage
+    coverImage { extraLarge }
+    title { romaji english }
+  }
+}";
+
+const CHARACTER_QUERY: &str = "query ($id: Int) {
+  Character(id: $id) {
+    id
+    gender
+    name { full alternative }
+    image { large }
+  }
+}";
+
+// A series and its main and supporting characters, as returned by AniList
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AniMedia {
+    pub id: i64,
+    pub title: String,
+    pub aliases: Vec<String>,
+    pub media_type: Media,
+    pub banner_url: Option<String>,
+    pub characters: Vec<AniCharacter>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AniCharacter {
+    pub id: i64,
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub gender: Gender,
+    pub image_url: Option<String>,
+}
+
+// A field that differs between an entry and AniList, aliases are kept as JSON arrays
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old_value: String,
+    pub new_value: String,
+}
+
+impl AniMedia {
+    pub fn to_series(&self, id: i64) -> Series {
+        Series {
+            id,
+            title: self.title.clone(),
+            artist: "AniList".to_string(),
+            aliases: self.aliases.clone(),
+            image_link: self.banner_url.clone().unwrap_or(".".to_string()),
+            media_type: self.media_type.clone(),
+            anilist_id: Some(self.id),
+            ..Default::default()
+        }
+    }
+
+    pub fn diff(&self, series: &Series) -> Vec<FieldChange> {
+        diff_fields(
+            (&series.title, &self.title),
+            (&series.aliases, &self.aliases),
+            (&series.image_link, self.banner_url.as_ref()),
+        )
+    }
+}
+
+impl AniCharacter {
+    pub fn to_character(&self, id: i64, series_id: i64) -> Character {
+        Character {
+            id,
+            name: self.name.clone(),
+            stars: 1,
+            gender: self.gender.clone(),
+            artist: "AniList".to_string(),
+            aliases: self.aliases.clone(),
+            series_id,
+            image_link: self.image_url.clone().unwrap_or(".".to_string()),
+            anilist_id: Some(self.id),
+            ..Default::default()
+        }
+    }
+
+    pub fn diff(&self, character: &Character) -> Vec<FieldChange> {
+        diff_fields(
+            (&character.name, &self.name),
+            (&character.aliases, &self.aliases),
+            (&character.image_link, self.image_url.as_ref()),
+        )
+    }
+}
+
+fn diff_fields(
+    name: (&String, &String),
+    aliases: (&Vec<String>, &Vec<String>),
+    image: (&String, Option<&String>),
+) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+
+    if name.0 != name.1 && !name.1.trim().is_empty() {
+        changes.push(FieldChange {
+            field: "name",
+            old_value: name.0.clone(),
+            new_value: name.1.clone(),
+        });
+    }
+
+    if aliases.0 != aliases.1 {
+        changes.push(FieldChange {
+            field: "aliases",
+            old_value: serde_json::to_string(aliases.0).unwrap_or_default(),
+            new_value: serde_json::to_string(aliases.1).unwrap_or_default(),
+        });
+    }
+
+    if let Some(url) = image.1.filter(|url| *url != image.0) {
+        changes.push(FieldChange {
+            field: "image",
+            old_value: image.0.clone(),
+            new_value: url.clone(),
+        });
+    }
+
+    changes
+}
+
+// Fetches a media with all of its main and supporting characters, `url` is the GraphQL endpoint
+pub async fn fetch_media(url: &str, id: i64) -> Result<AniMedia> {
+    let client = reqwest::Client::new();
+
+    let mut ani_media = AniMedia::default();
+    let mut page = 1;
+
+    loop {
+        let response: Response = post(
+            &client,
+            url,
+            MEDIA_QUERY,
+            serde_json::json!({ "id": id, "page": page }),
+        )
+        .await?;
+
+        let Some(mut media) = response.data.and_then(|data| data.media) else {
+            return Err(format!("media {} not found", id).into());
+        };
+
+        let characters = media.characters.take();
+        if page == 1 {
+            ani_media = media.into();
+        }
+
+        let Some(characters) = characters else {
+            break;
+        };
+
+        for edge in characters.edges {
+            if !matches!(edge.role.as_deref(), Some("MAIN" | "SUPPORTING")) {
+                continue;
+            }
+
+            ani_media.characters.push(edge.node.into());
+        }
+
+        if !characters.page_info.has_next_page {
+            break;
+        }
+        page += 1;
+    }
+
+    Ok(ani_media)
+}
+
+// Fetches only the series fields of a media, without its characters
+pub async fn fetch_series(url: &str, id: i64) -> Result<AniMedia> {
+    let response: Response = post(
+        &reqwest::Client::new(),
+        url,
+        SERIES_QUERY,
+        serde_json::json!({ "id": id }),
+    )
+    .await?;
+
+    match response.data.and_then(|data| data.media) {
+        Some(media) => Ok(media.into()),
+        None => Err(format!("media {} not found", id).into()),
+    }
+}
+
+pub async fn fetch_character(url: &str, id: i64) -> Result<AniCharacter> {
+    let response: Response = post(
+        &reqwest::Client::new(),
+        url,
+        CHARACTER_QUERY,
+        serde_json::json!({ "id": id }),
+    )
+    .await?;
+
+    match response.data.and_then(|data| data.character) {
+        Some(character) => Ok(character.into()),
+        None => Err(format!("character {} not found", id).into()),
+    }
+}
+
+// The queries are sent by hand rather than through `rust-anilist`, which always talks to the
+// public endpoint and has no way to point it at the mock server the tests use
+async fn post<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+    query: &str,
+    variables: serde_json::Value,
+) -> Result<T> {
+    Ok(client
+        .post(url)
+        .json(&serde_json::json!({ "query": query, "variables": variables }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+pub fn media_type(format: Option<&str>, country: Option<&str>) -> Media {
+    match format {
+        Some("TV" | "TV_SHORT" | "MOVIE" | "SPECIAL" | "OVA" | "ONA" | "MUSIC") => Media::Anime,
+        Some("MANGA" | "ONE_SHOT") => match country {
+            Some("KR") => Media::Manhwa,
+            Some("CN" | "TW") => Media::Manhua,
+            _ => Media::Manga,
+        },
+        Some("NOVEL") => Media::LightNovel,
+        _ => Media::Unknown,
+    }
+}
+
+pub fn gender(gender: Option<&str>) -> Gender {
+    match gender.map(|gender| gender.to_lowercase()).as_deref() {
+        Some("male") => Gender::Male,
+        Some("female") => Gender::Female,
+        _ => Gender::Other,
+    }
+}
+
+#[derive(Deserialize)]
+struct Response {
+    data: Option<ResponseData>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ResponseData {
+    media: Option<MediaNode>,
+    character: Option<CharacterNode>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MediaNode {
+    id: i64,
+    format: Option<String>,
+    country_of_origin: Option<String>,
+    #[serde(default)]
+    synonyms: Vec<String>,
+    banner_image: Option<String>,
+    cover_image: CoverImage,
+    title: Title,
+    characters: Option<CharacterConnection>,
+}
+
+impl From<MediaNode> for AniMedia {
+    fn from(media: MediaNode) -> Self {
+        let title = media
+            .title
+            .english
+            .clone()
+            .or(media.title.romaji.clone())
+            .unwrap_or_default();
+        let mut aliases = media.synonyms;
+        if let Some(romaji) = media.title.romaji.filter(|romaji| *romaji != title) {
+            aliases.insert(0, romaji);
+        }
+
+        Self {
+            id: media.id,
+            title,
+            aliases,
+            media_type: media_type(media.format.as_deref(), media.country_of_origin.as_deref()),
+            banner_url: media.banner_image.or(media.cover_image.extra_large),
+            characters: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CoverImage {
+    extra_large: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Title {
+    romaji: Option<String>,
+    english: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CharacterConnection {
+    page_info: PageInfo,
+    edges: Vec<CharacterEdge>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+}
+
+#[derive(Deserialize)]
+struct CharacterEdge {
+    role: Option<String>,
+    node: CharacterNode,
+}
+
+#[derive(Deserialize)]
+struct CharacterNode {
+    id: i64,
+    gender: Option<String>,
+    name: CharacterName,
+    image: CharacterImage,
+}
+
+impl From<CharacterNode> for AniCharacter {
+    fn from(node: CharacterNode) -> Self {
+        Self {
+            id: node.id,
+            name: node.name.full.unwrap_or_default(),
+            aliases: node
+                .name
+                .alternative
+                .into_iter()
+                .filter(|alias| !alias.trim().is_empty())
+                .collect(),
+            gender: gender(node.gender.as_deref()),
+            image_url: node.image.large,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CharacterName {
+    full: Option<String>,
+    #[serde(default)]
+    alternative: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct CharacterImage {
+    large: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::*;
+
+    // Serves the given bodies in order, one per connection, and returns the server address
+    fn mock_server(bodies: Vec<String>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for body in bodies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut request = vec![0; content_length];
+                reader.read_exact(&mut request).unwrap();
+
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        address
+    }
+
+    fn media_page(has_next_page: bool, edges: serde_json::Value) -> String {
+        serde_json::json!({
+            "data": {
+                "Media": {
+                    "id": 21,
+                    "format": "TV",
+                    "countryOfOrigin": "JP",
+                    "synonyms": ["OP"],
+                    "bannerImage": null,
+                    "coverImage": { "extraLarge": "https://img.anili.st/cover.jpg" },
+                    "title": { "romaji": "One Piece", "english": null },
+                    "characters": {
+                        "pageInfo": { "hasNextPage": has_next_page },
+                        "edges": edges,
+                    },
+                }
+            }
+        })
+        .to_string()
+    }
+
+    fn edge(role: &str, id: i64, name: &str, gender: Option<&str>) -> serde_json::Value {
+        serde_json::json!({
+            "role": role,
+            "node": {
+                "id": id,
+                "gender": gender,
+                "name": { "full": name, "alternative": ["", format!("{} alt", name)] },
+                "image": { "large": format!("https://img.anili.st/{}.jpg", id) },
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn fetches_every_page_of_characters() {
+        let url = mock_server(vec![
+            media_page(
+                true,
+                serde_json::json!([
+                    edge("MAIN", 40, "Monkey D. Luffy", Some("Male")),
+                    edge("BACKGROUND", 41, "Extra", None),
+                ]),
+            ),
+            media_page(
+                false,
+                serde_json::json!([edge("SUPPORTING", 42, "Nami", Some("Female"))]),
+            ),
+        ]);
+
+        let media = fetch_media(&url, 21).await.unwrap();
+
+        assert_eq!(media.title, "One Piece");
+        assert_eq!(media.aliases, vec!["OP".to_string()]);
+        assert_eq!(media.media_type, Media::Anime);
+        assert_eq!(
+            media.banner_url.as_deref(),
+            Some("https://img.anili.st/cover.jpg")
+        );
+
+        let names = media
+            .characters
+            .iter()
+            .map(|character| character.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, vec!["Monkey D. Luffy", "Nami"]);
+        assert_eq!(media.characters[0].aliases, vec!["Monkey D. Luffy alt"]);
+        assert_eq!(media.characters[1].gender, Gender::Female);
+    }
+
+    #[tokio::test]
+    async fn missing_media_is_an_error() {
+        let url = mock_server(vec![r#"{"data":{"Media":null}}"#.to_string()]);
+
+        assert!(fetch_media(&url, 1).await.is_err());
+    }
+
+    #[test]
+    fn maps_formats_to_media() {
+        assert_eq!(media_type(Some("OVA"), None), Media::Anime);
+        assert_eq!(media_type(Some("MANGA"), Some("JP")), Media::Manga);
+        assert_eq!(media_type(Some("MANGA"), Some("KR")), Media::Manhwa);
+        assert_eq!(media_type(Some("NOVEL"), Some("JP")), Media::LightNovel);
+        assert_eq!(media_type(None, None), Media::Unknown);
+    }
+
+    #[tokio::test]
+    async fn fetches_a_single_character() {
+        let url = mock_server(vec![serde_json::json!({
+            "data": { "Character": edge("MAIN", 40, "Luffy", Some("Male"))["node"] }
+        })
+        .to_string()]);
+
+        let character = fetch_character(&url, 40).await.unwrap();
+
+        assert_eq!(character.name, "Luffy");
+        assert_eq!(character.aliases, vec!["Luffy alt".to_string()]);
+        assert_eq!(character.gender, Gender::Male);
+    }
+
+    #[test]
+    fn diffs_only_changed_fields() {
+        let ani_character = AniCharacter {
+            id: 40,
+            name: "Monkey D. Luffy".to_string(),
+            aliases: vec!["Luffy".to_string()],
+            gender: Gender::Male,
+            image_url: Some("https://img.anili.st/40.jpg".to_string()),
+        };
+        let mut character = ani_character.to_character(1, 1);
+
+        assert!(ani_character.diff(&character).is_empty());
+
+        character.name = "Luffy".to_string();
+        character.image_link = "https://old.jpg".to_string();
+
+        let changes = ani_character.diff(&character);
+        assert_eq!(
+            changes,
+            vec![
+                FieldChange {
+                    field: "name",
+                    old_value: "Luffy".to_string(),
+                    new_value: "Monkey D. Luffy".to_string(),
+                },
+                FieldChange {
+                    field: "image",
+                    old_value: "https://old.jpg".to_string(),
+                    new_value: "https://img.anili.st/40.jpg".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn diffs_aliases_as_json() {
+        let media = AniMedia {
+            id: 21,
+            title: "One Piece".to_string(),
+            aliases: vec!["OP".to_string()],
+            ..Default::default()
+        };
+        let mut series = media.to_series(1);
+        series.aliases = Vec::new();
+
+        assert_eq!(
+            media.diff(&series),
+            vec![FieldChange {
+                field: "aliases",
+                old_value: "[]".to_string(),
+                new_value: r#"["OP"]"#.to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn builds_models() {
+        let character = AniCharacter {
+            id: 40,
+            name: "Monkey D. Luffy".to_string(),
+            aliases: vec!["Luffy".to_string()],
+            gender: Gender::Male,
+            image_url: None,
+        }
+        .to_character(7, 3);
+
+        assert_eq!(character.id, 7);
+        assert_eq!(character.series_id, 3);
+        assert_eq!(character.anilist_id, Some(40));
+        assert_eq!(character.aliases, vec!["Luffy".to_string()]);
+    }
+}
@@ -0,0 +1,279 @@
+use serde::{
+    de::{self, DeserializeOwned, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    database::models::{Character, Gender, Media, Series},
+    Result,
+};
+
+// Row number and message of each row that couldn't be read
+pub type RowErrors = Vec<(usize, String)>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().rsplit('.').next()? {
+            "json" => Some(Self::Json),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &str {
+        match self {
+            Self::Json => "json",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+// Images are exported as their link only, a missing `id` inserts a new entry
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CharacterRow {
+    pub id: Option<i64>,
+    pub name: String,
+    pub stars: u8,
+    pub gender: Gender,
+    pub artist: String,
+    #[serde(
+        serialize_with = "serialize_aliases",
+        deserialize_with = "deserialize_aliases"
+    )]
+    pub aliases: Vec<String>,
+    pub series_id: i64,
+    pub image_link: Option<String>,
+    pub anilist_id: Option<i64>,
+}
+
+impl CharacterRow {
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name is empty".to_string());
+        }
+
+        if !(1..=6).contains(&self.stars) {
+            return Err(format!("stars must be between 1 and 6, got {}", self.stars));
+        }
+
+        Ok(())
+    }
+
+    // Copies the row into a character, keeping the fields that aren't exported
+    pub fn apply(&self, character: &mut Character) {
+        character.name = self.name.trim().to_string();
+        character.stars = self.stars;
+        character.gender = self.gender.clone();
+        character.artist = self.artist.clone();
+        character.aliases = self.aliases.clone();
+        character.series_id = self.series_id;
+        character.image_link = self.image_link.clone().unwrap_or(".".to_string());
+        character.anilist_id = self.anilist_id;
+    }
+}
+
+impl From<&Character> for CharacterRow {
+    fn from(character: &Character) -> Self {
+        Self {
+            id: Some(character.id),
+            name: character.name.clone(),
+            stars: character.stars,
+            gender: character.gender.clone(),
+            artist: character.artist.clone(),
+            aliases: character.aliases.clone(),
+            series_id: character.series_id,
+            image_link: image_link(&character.image_link),
+            anilist_id: character.anilist_id,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SeriesRow {
+    pub id: Option<i64>,
+    pub title: String,
+    pub artist: String,
+    #[serde(
+        serialize_with = "serialize_aliases",
+        deserialize_with = "deserialize_aliases"
+    )]
+    pub aliases: Vec<String>,
+    pub media_type: Media,
+    pub image_link: Option<String>,
+    pub anilist_id: Option<i64>,
+}
+
+impl SeriesRow {
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.title.trim().is_empty() {
+            return Err("title is empty".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn apply(&self, series: &mut Series) {
+        series.title = self.title.trim().to_string();
+        series.artist = self.artist.clone();
+        series.aliases = self.aliases.clone();
+        series.media_type = self.media_type.clone();
+        series.image_link = self.image_link.clone().unwrap_or(".".to_string());
+        series.anilist_id = self.anilist_id;
+    }
+}
+
+impl From<&Series> for SeriesRow {
+    fn from(series: &Series) -> Self {
+        Self {
+            id: Some(series.id),
+            title: series.title.clone(),
+            artist: series.artist.clone(),
+            aliases: series.aliases.clone(),
+            media_type: series.media_type.clone(),
+            image_link: image_link(&series.image_link),
+            anilist_id: series.anilist_id,
+        }
+    }
+}
+
+pub fn write_rows<T: Serialize>(rows: &[T], format: Format) -> Result<Vec<u8>> {
+    match format {
+        Format::Json => Ok(serde_json::to_vec_pretty(rows)?),
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for row in rows.iter() {
+                writer.serialize(row)?;
+            }
+
+            Ok(writer.into_inner()?)
+        }
+    }
+}
+
+// Parses every row it can, the errors come with the row number (the file line for CSV)
+pub fn parse_rows<T: DeserializeOwned>(
+    bytes: &[u8],
+    format: Format,
+) -> (Vec<(usize, T)>, RowErrors) {
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+
+    match format {
+        Format::Json => match serde_json::from_slice::<Vec<serde_json::Value>>(bytes) {
+            Ok(values) => {
+                for (index, value) in values.into_iter().enumerate() {
+                    match serde_json::from_value::<T>(value) {
+                        Ok(row) => rows.push((index + 1, row)),
+                        Err(e) => errors.push((index + 1, e.to_string())),
+                    }
+                }
+            }
+            Err(e) => errors.push((e.line(), e.to_string())),
+        },
+        Format::Csv => {
+            let mut reader = csv::Reader::from_reader(bytes);
+            let headers = match reader.byte_headers() {
+                Ok(headers) => headers.clone(),
+                Err(e) => {
+                    errors.push((1, e.to_string()));
+                    return (rows, errors);
+                }
+            };
+
+            let mut record = csv::ByteRecord::new();
+            loop {
+                let line = reader.position().line() as usize;
+                match reader.read_byte_record(&mut record) {
+                    Ok(false) => break,
+                    Ok(true) => match record.deserialize::<T>(Some(&headers)) {
+                        Ok(row) => rows.push((line, row)),
+                        Err(e) => errors.push((line, e.to_string())),
+                    },
+                    Err(e) => {
+                        errors.push((line, e.to_string()));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    (rows, errors)
+}
+
+fn image_link(link: &str) -> Option<String> {
+    Some(link.to_string()).filter(|link| link.starts_with("http"))
+}
+
+fn serialize_aliases<S>(aliases: &[String], serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&aliases.join(" | "))
+}
+
+// Aliases are written as `a | b` so they fit in a CSV cell, JSON arrays are also accepted
+fn deserialize_aliases<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct AliasesVisitor;
+
+    impl<'de> Visitor<'de> for AliasesVisitor {
+        type Value = Vec<String>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a list of aliases or a text separated by `|`")
+        }
+
+        fn visit_str<E: de::Error>(self, text: &str) -> std::result::Result<Self::Value, E> {
+            Ok(text.split('|').map(str::to_string).collect())
+        }
+
+        fn visit_u64<E: de::Error>(self, number: u64) -> std::result::Result<Self::Value, E> {
+            Ok(vec![number.to_string()])
+        }
+
+        fn visit_i64<E: de::Error>(self, number: i64) -> std::result::Result<Self::Value, E> {
+            Ok(vec![number.to_string()])
+        }
+
+        fn visit_f64<E: de::Error>(self, number: f64) -> std::result::Result<Self::Value, E> {
+            Ok(vec![number.to_string()])
+        }
+
+        fn visit_unit<E: de::Error>(self) -> std::result::Result<Self::Value, E> {
+            Ok(Vec::new())
+        }
+
+        fn visit_none<E: de::Error>(self) -> std::result::Result<Self::Value, E> {
+            Ok(Vec::new())
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(
+            self,
+            mut seq: A,
+        ) -> std::result::Result<Self::Value, A::Error> {
+            let mut aliases = Vec::new();
+            while let Some(alias) = seq.next_element::<String>()? {
+                aliases.push(alias);
+            }
+
+            Ok(aliases)
+        }
+    }
+
+    let aliases = deserializer.deserialize_any(AliasesVisitor)?;
+
+    Ok(aliases
+        .into_iter()
+        .map(|alias| alias.trim().to_string())
+        .filter(|alias| !alias.is_empty())
+        .collect())
+}
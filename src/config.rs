@@ -1,15 +1,30 @@
 use std::{fs::File, io::Read};
 
+use grammers_friendly::prelude::*;
 use serde::{Deserialize, Serialize};
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 const PATH: &str = "./config.toml";
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Config {
     pub telegram: Telegram,
     pub bot: Bot,
+    #[serde(default)]
+    pub spawn: Spawn,
+    #[serde(default)]
+    pub daily: Daily,
+    #[serde(default)]
+    pub shop: Shop,
+    #[serde(default)]
+    pub auctions: Auctions,
+    #[serde(default)]
+    pub duels: Duels,
+    #[serde(default)]
+    pub quiz: Quiz,
+    #[serde(default)]
+    pub anilist: AniList,
 }
 
 impl Config {
@@ -21,15 +36,197 @@ impl Config {
     }
 }
 
-#[derive(Deserialize, Serialize)]
+impl Module for Config {}
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Telegram {
     pub api_id: i32,
     pub api_hash: String,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Bot {
     pub token: String,
     pub catch_up: bool,
     pub flood_sleep_threshold: u32,
 }
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Spawn {
+    pub max_active: usize,
+    pub escape_after: i32,
+
+    pub raid_chance: f64,
+    pub raid_window: i64,
+    pub raid_min_stars: u8,
+    pub raid_participants: u8,
+}
+
+impl Default for Spawn {
+    fn default() -> Self {
+        Self {
+            max_active: 1,
+            escape_after: 35,
+
+            raid_chance: 0.0,
+            raid_window: 180,
+            raid_min_stars: 5,
+            raid_participants: 3,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Daily {
+    pub reward: i64,
+    pub max_streak: u32,
+    pub pull_chance: f64,
+    pub period_hours: i64,
+    pub streak_bonus: f64,
+}
+
+impl Default for Daily {
+    fn default() -> Self {
+        Self {
+            reward: 100,
+            max_streak: 7,
+            pull_chance: 0.1,
+            period_hours: 24,
+            streak_bonus: 0.1,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Shop {
+    pub items: Vec<ShopItem>,
+}
+
+impl Default for Shop {
+    fn default() -> Self {
+        Self {
+            items: vec![
+                ShopItem::new("slot", 500, ItemEffect::ExtraSlot),
+                ShopItem::new("hint", 50, ItemEffect::HintToken),
+                ShopItem::new("reroll", 150, ItemEffect::Reroll),
+                ShopItem::new("summon", 300, ItemEffect::SummonSeries),
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Auctions {
+    pub min_duration: i64,
+    pub max_duration: i64,
+    pub min_increment: i64,
+    pub check_interval: u64,
+}
+
+impl Default for Auctions {
+    fn default() -> Self {
+        Self {
+            min_duration: 300,
+            max_duration: 259200,
+            min_increment: 10,
+            check_interval: 30,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Duels {
+    pub use_level: bool,
+    pub luck: u32,
+    pub max_stake: i64,
+    pub timeout: i64,
+}
+
+impl Default for Duels {
+    fn default() -> Self {
+        Self {
+            use_level: true,
+            luck: 30,
+            max_stake: 1000,
+            timeout: 600,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Quiz {
+    pub reward: i64,
+    pub timeout: i64,
+}
+
+impl Default for Quiz {
+    fn default() -> Self {
+        Self {
+            reward: 50,
+            timeout: 60,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AniList {
+    pub url: String,
+    pub sync_interval: u64,
+    pub auto_apply: bool,
+    pub request_delay: u64,
+}
+
+impl Default for AniList {
+    fn default() -> Self {
+        Self {
+            url: "https://graphql.anilist.co".to_string(),
+            sync_interval: 0,
+            auto_apply: false,
+            request_delay: 1000,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ShopItem {
+    pub id: String,
+    pub price: i64,
+    pub effect: ItemEffect,
+}
+
+impl ShopItem {
+    pub fn new(id: &str, price: i64, effect: ItemEffect) -> Self {
+        Self {
+            id: id.to_string(),
+            price,
+            effect,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemEffect {
+    ExtraSlot,
+    HintToken,
+    Reroll,
+    SummonSeries,
+}
+
+impl ItemEffect {
+    pub fn key(&self) -> &str {
+        match self {
+            Self::ExtraSlot => "extra_slot",
+            Self::HintToken => "hint_token",
+            Self::Reroll => "reroll",
+            Self::SummonSeries => "summon_series",
+        }
+    }
+}
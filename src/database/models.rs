@@ -1,16 +1,20 @@
-use rbatis::{crud, impl_delete, impl_select, impl_update, RBatis};
+use rand::{thread_rng, Rng};
+use rbatis::{crud, executor::Executor, impl_delete, impl_select, impl_update, RBatis};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::search::CharacterFilter;
+
 #[derive(Clone, Default, Deserialize, Serialize)]
 pub struct Character {
     pub id: i64,
     pub name: String,
     pub stars: u8,
-    pub image: Option<Vec<u8>>,
+    // Hash of the image in the image store
+    pub image_hash: Option<String>,
     pub gender: Gender,
     pub artist: String,
     pub aliases: Vec<String>,
-    pub liked_by: Vec<i64>,
+    // The primary series, the one the character is displayed with
     pub series_id: i64,
     pub image_link: String,
 
@@ -21,25 +25,283 @@ crud!(Character {}, "characters");
 impl_delete!(Character { delete_by_id(id: i64) => "`where id = #{id}`" }, "characters");
 impl_update!(Character { update_by_id(id: i64) => "`where id = #{id}`" }, "characters");
 impl_select!(Character { select_by_id(id: i64) -> Option => "`where id = #{id} limit 1`" }, "characters");
-impl_select!(Character { select_by_name(name: &str) -> Option => "`where name like #{'%' + name + '%'} or aliases like #{'%' + name + '%'} order by name limit 1`" }, "characters");
-impl_select!(Character { select_by_series(series_id: i64) -> Vec => "`where series_id = #{series_id}`" }, "characters");
+impl_select!(Character { select_by_series(series_id: i64) -> Vec => "`where id in (select character_id from characters_series where series_id = #{series_id})`" }, "characters");
+impl_select!(Character { select_by_image_hash(image_hash: &str) -> Vec => "`where image_hash = #{image_hash}`" }, "characters");
 impl_select!(Character { select_page(page: u16, limit: u16) => "`limit #{limit} offset #{(page - 1) * limit}`" }, "characters");
-impl_select!(Character { select_page_by_name(name: &str, page: u16, limit: u16) -> Vec => "`where name like #{'%' + name + '%'} or aliases like #{'%' + name + '%'} order by name limit #{limit} offset #{(page - 1) * limit}`" }, "characters");
-impl_select!(Character { select_page_by_series(series_id: i64, page: u16, limit: u16) -> Vec => "`where series_id = #{series_id} order by name limit #{limit} offset #{(page - 1) * limit}`" }, "characters");
+impl_select!(Character { select_page_by_tag(tag_id: i64, page: u16, limit: u16) -> Vec => "`where id in (select character_id from characters_tags where tag_id = #{tag_id}) order by name, id limit #{limit} offset #{(page - 1) * limit}`" }, "characters");
+impl_select!(Character { select_page_by_series(series_id: i64, page: u16, limit: u16) -> Vec => "`where id in (select character_id from characters_series where series_id = #{series_id}) order by name limit #{limit} offset #{(page - 1) * limit}`" }, "characters");
+impl_select!(Character { select_random_with_image_by_series(series_id: i64, limit: u16) -> Vec => "`where image_hash is not null and id in (select character_id from characters_series where series_id = #{series_id}) order by random() limit #{limit}`" }, "characters");
+impl_select!(Character { select_by_anilist_id(anilist_id: i64) -> Option => "`where anilist_id = #{anilist_id} limit 1`" }, "characters");
+impl_select!(Character { select_linked() -> Vec => "`where anilist_id is not null order by id`" }, "characters");
 impl_select!(Character { select_last() -> Option => "`order by id desc limit 1`" }, "characters");
 impl_select!(Character { select_random() -> Option => "`order by random() limit 1`" }, "characters");
 
 impl Character {
+    // Ranked by relevance, names weigh more than aliases and series titles
+    pub async fn search(
+        conn: &mut RBatis,
+        text: &str,
+        page: u16,
+        limit: u16,
+    ) -> rbatis::Result<Vec<Self>> {
+        let Some(query) = search_query(text) else {
+            return Ok(Vec::new());
+        };
+
+        conn.query_decode(
+            "select characters.* from characters_search join characters on characters.id = characters_search.rowid where characters_search match ? order by bm25(characters_search, 10.0, 5.0, 1.0), characters.name, characters.id limit ? offset ?",
+            vec![
+                rbs::to_value!(query),
+                rbs::to_value!(limit),
+                rbs::to_value!((page - 1) * limit),
+            ],
+        )
+        .await
+    }
+
+    pub async fn search_by_filter(
+        conn: &mut RBatis,
+        filter: &CharacterFilter,
+        page: u16,
+        limit: u16,
+    ) -> rbatis::Result<Vec<Self>> {
+        let (sql, mut args) = filter.to_sql();
+        args.push(rbs::to_value!(limit));
+        args.push(rbs::to_value!((page - 1) * limit));
+
+        conn.query_decode(
+            &format!(
+                "select characters.* {0} {1} limit ? offset ?",
+                sql,
+                filter.order_by()
+            ),
+            args,
+        )
+        .await
+    }
+
+    pub async fn count_by_filter(
+        conn: &mut RBatis,
+        filter: &CharacterFilter,
+    ) -> rbatis::Result<usize> {
+        let (sql, args) = filter.to_sql();
+        let count: u64 = conn
+            .query_decode(&format!("select count(*) as count {}", sql), args)
+            .await?;
+
+        Ok(count as usize)
+    }
+
+    // Characters with any of the tags
+    pub async fn count_by_tags(conn: &mut RBatis, tag_ids: &[i64]) -> rbatis::Result<usize> {
+        let count: u64 = conn
+            .query_decode(
+                &format!(
+                    "select count(*) as count from characters where id in (select character_id from characters_tags where tag_id in ({}))",
+                    placeholders(tag_ids.len())
+                ),
+                tag_ids.iter().map(|id| rbs::to_value!(id)).collect(),
+            )
+            .await?;
+
+        Ok(count as usize)
+    }
+
+    pub async fn count_by_series_ids(
+        conn: &mut RBatis,
+        series_ids: &[i64],
+    ) -> rbatis::Result<usize> {
+        let count: u64 = conn
+            .query_decode(
+                &format!(
+                    "select count(distinct character_id) as count from characters_series where series_id in ({})",
+                    placeholders(series_ids.len())
+                ),
+                series_ids.iter().map(|id| rbs::to_value!(id)).collect(),
+            )
+            .await?;
+
+        Ok(count as usize)
+    }
+
     pub async fn count_by_series(conn: &mut RBatis, series_id: i64) -> rbatis::Result<usize> {
         let count: u64 = conn
             .query_decode(
-                "select count(*) as count from characters where series_id = ?",
+                "select count(*) as count from characters_series where series_id = ?",
                 vec![rbs::to_value!(series_id)],
             )
             .await?;
 
         Ok(count as usize)
     }
+
+    pub async fn select_random_by_pool(
+        conn: &mut RBatis,
+        min_stars: u8,
+        pool: Option<(&[i64], &[i64])>,
+        tag_ids: &[i64],
+        series_ids: &[i64],
+        excluded_ids: &[i64],
+    ) -> rbatis::Result<Option<Self>> {
+        let mut sql = String::from("select * from characters where stars >= ?");
+        let mut args = vec![rbs::to_value!(min_stars)];
+
+        if !series_ids.is_empty() {
+            sql += &format!(
+                " and id in (select character_id from characters_series where series_id in ({}))",
+                placeholders(series_ids.len())
+            );
+            args.extend(series_ids.iter().map(|id| rbs::to_value!(id)));
+        }
+
+        if !tag_ids.is_empty() {
+            sql += &format!(
+                " and id in (select character_id from characters_tags where tag_id in ({}))",
+                placeholders(tag_ids.len())
+            );
+            args.extend(tag_ids.iter().map(|id| rbs::to_value!(id)));
+        }
+
+        if let Some((series_ids, ids)) = pool {
+            sql += &format!(
+                " and (id in (select character_id from characters_series where series_id in ({0})) or id in ({1}))",
+                placeholders(series_ids.len()),
+                placeholders(ids.len())
+            );
+            args.extend(series_ids.iter().map(|id| rbs::to_value!(id)));
+            args.extend(ids.iter().map(|id| rbs::to_value!(id)));
+        }
+
+        if !excluded_ids.is_empty() {
+            sql += &format!(" and id not in ({})", placeholders(excluded_ids.len()));
+            args.extend(excluded_ids.iter().map(|id| rbs::to_value!(id)));
+        }
+
+        sql += " order by random() limit 1";
+
+        let characters: Vec<Self> = conn.query_decode(&sql, args).await?;
+        Ok(characters.into_iter().next())
+    }
+
+    // Moves the collections, spawns, variants, tags, series, likes, events, auctions and duels
+    // of a character to another one, owners of both keep the most advanced copy and groups the
+    // spawn that was already there
+    pub async fn merge_references(
+        executor: &dyn Executor,
+        keep_id: i64,
+        drop_id: i64,
+    ) -> rbatis::Result<()> {
+        // The alternate arts go after the kept character's own
+        let last_position = CharacterVariant::select_by_character(executor, keep_id)
+            .await?
+            .last()
+            .map_or(0, |variant| variant.position);
+        for mut variant in CharacterVariant::select_by_character(executor, drop_id).await? {
+            variant.character_id = keep_id;
+            variant.position += last_position;
+            CharacterVariant::update_by_id(executor, &variant, variant.id).await?;
+        }
+
+        let statements = [
+            (
+                "update owned_characters set level = dropped.level, xp = dropped.xp from (select user_id, group_id, level, xp from owned_characters where character_id = ?) as dropped where owned_characters.character_id = ? and owned_characters.user_id = dropped.user_id and owned_characters.group_id = dropped.group_id and (dropped.level, dropped.xp) > (owned_characters.level, owned_characters.xp)",
+                [drop_id, keep_id],
+            ),
+            (
+                "delete from owned_characters where character_id = ? and exists (select 1 from owned_characters as kept where kept.character_id = ? and kept.user_id = owned_characters.user_id and kept.group_id = owned_characters.group_id)",
+                [drop_id, keep_id],
+            ),
+            (
+                "update owned_characters set character_id = ? where character_id = ?",
+                [keep_id, drop_id],
+            ),
+            (
+                "delete from groups_characters where character_id = ? and exists (select 1 from groups_characters as kept where kept.character_id = ? and kept.group_id = groups_characters.group_id)",
+                [drop_id, keep_id],
+            ),
+            (
+                "update groups_characters set character_id = ? where character_id = ?",
+                [keep_id, drop_id],
+            ),
+            (
+                "insert or ignore into characters_tags (character_id, tag_id) select ?, tag_id from characters_tags where character_id = ?",
+                [keep_id, drop_id],
+            ),
+            (
+                "insert or ignore into characters_series (character_id, series_id) select ?, series_id from characters_series where character_id = ?",
+                [keep_id, drop_id],
+            ),
+            (
+                "insert or ignore into likes (user_id, kind, target_id, created_at) select user_id, kind, ?, created_at from likes where kind = 'character' and target_id = ?",
+                [keep_id, drop_id],
+            ),
+            (
+                "update auctions set character_id = ? where character_id = ?",
+                [keep_id, drop_id],
+            ),
+            (
+                "update duels set challenger_character = ? where challenger_character = ?",
+                [keep_id, drop_id],
+            ),
+            (
+                "update duels set opponent_character = ? where opponent_character = ?",
+                [keep_id, drop_id],
+            ),
+        ];
+
+        for (sql, ids) in statements.iter() {
+            executor
+                .exec(sql, ids.iter().map(|id| rbs::to_value!(id)).collect())
+                .await?;
+        }
+        CharacterTag::delete_by_character(executor, drop_id).await?;
+
+        // Events keep their characters in JSON arrays, the kept one is listed once
+        for column in ["boosted_characters", "exclusive_characters"] {
+            executor
+                .exec(
+                    &format!(
+                        "update events set {0} = (select json_group_array(distinct case when value = ? then ? else value end) from json_each(events.{0})) where exists (select 1 from json_each(events.{0}) where value = ?)",
+                        column
+                    ),
+                    vec![
+                        rbs::to_value!(drop_id),
+                        rbs::to_value!(keep_id),
+                        rbs::to_value!(drop_id),
+                    ],
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Event {
+    pub id: i64,
+    pub name: String,
+    pub boost: u8,
+    pub ends_at: i64,
+    pub starts_at: i64,
+    pub spawn_text: String,
+    pub boosted_series: Vec<i64>,
+    pub boosted_characters: Vec<i64>,
+    pub exclusive_characters: Vec<i64>,
+}
+
+crud!(Event {}, "events");
+impl_delete!(Event { delete_by_id(id: i64) => "`where id = #{id}`" }, "events");
+impl_update!(Event { update_by_id(id: i64) => "`where id = #{id}`" }, "events");
+impl_select!(Event { select_by_id(id: i64) -> Option => "`where id = #{id}`" }, "events");
+impl_select!(Event { select_active(now: i64) -> Vec => "`where starts_at <= #{now} and ends_at > #{now} order by starts_at`" }, "events");
+impl_select!(Event { select_not_ended(now: i64) -> Vec => "`where ends_at > #{now} order by starts_at`" }, "events");
+impl_select!(Event { select_last() -> Option => "`order by id desc limit 1`" }, "events");
+
+impl Event {
+    pub fn is_active(&self, now: i64) -> bool {
+        self.starts_at <= now && now < self.ends_at
+    }
 }
 
 #[derive(Default, Deserialize, Serialize)]
@@ -47,6 +309,7 @@ pub struct Group {
     pub id: i64,
     pub title: String,
     pub username: Option<String>,
+    pub utc_offset: Option<i32>,
     pub language_code: String,
 }
 
@@ -58,50 +321,956 @@ impl_select!(Group { select_by_id(id: i64) -> Option => "`where id = #{id}`" },
 pub struct GroupCharacter {
     pub group_id: i64,
     pub character_id: i64,
+    pub variant_id: Option<i64>,
     pub last_message_id: i32,
 
     #[serde(deserialize_with = "bool_from_int", serialize_with = "bool_to_int")]
     pub available: bool,
+
+    pub kind: SpawnKind,
+    pub required: u8,
+    pub expires_at: i64,
 }
 
 crud!(GroupCharacter {}, "groups_characters");
 impl_delete!(GroupCharacter { delete_by_id(group_id: i64, character_id: i64) => "`where group_id = #{group_id} and character_id = #{character_id}`" }, "groups_characters");
-impl_update!(GroupCharacter { update_by_id(group_id: i64, character_id: i64) => "`where group_id = #{group_id} and character_id = #{character_id}`" }, "groups_characters");
+impl_delete!(GroupCharacter { delete_by_message_id(group_id: i64, last_message_id: i32) => "`where group_id = #{group_id} and last_message_id = #{last_message_id}`" }, "groups_characters");
+impl_update!(GroupCharacter { update_by_message_id(group_id: i64, last_message_id: i32) => "`where group_id = #{group_id} and last_message_id = #{last_message_id}`" }, "groups_characters");
 impl_select!(GroupCharacter { select_by_id(group_id: i64, character_id: i64) -> Option => "`where group_id = #{group_id} and character_id = #{character_id} limit 1`" }, "groups_characters");
-impl_select!(GroupCharacter { select_last_by_id(group_id: i64) -> Option => "`where group_id = #{group_id} order by last_message_id desc limit 1`" }, "groups_characters");
+impl_select!(GroupCharacter { select_by_message_id(group_id: i64, last_message_id: i32) -> Option => "`where group_id = #{group_id} and last_message_id = #{last_message_id} limit 1`" }, "groups_characters");
+impl_select!(GroupCharacter { select_available_by_group(group_id: i64) -> Vec => "`where group_id = #{group_id} and available = 1 order by last_message_id`" }, "groups_characters");
+
+impl GroupCharacter {
+    pub fn has_escaped(&self, message_id: i32, escape_after: i32) -> bool {
+        match self.kind {
+            SpawnKind::Normal => (message_id - self.last_message_id) >= escape_after,
+            SpawnKind::Raid => chrono::Utc::now().timestamp() >= self.expires_at,
+        }
+    }
+
+    // Makes the spawn unavailable, returns `false` when another guess or raid join already
+    // took it
+    pub async fn close(
+        executor: &dyn Executor,
+        group_id: i64,
+        last_message_id: i32,
+    ) -> rbatis::Result<bool> {
+        let result = executor
+            .exec(
+                "update groups_characters set available = 0 where group_id = ? and last_message_id = ? and available = 1",
+                vec![rbs::to_value!(group_id), rbs::to_value!(last_message_id)],
+            )
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+}
+
+// A member who joined a raid with the part of the name they guessed
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct RaidParticipant {
+    pub group_id: i64,
+    pub message_id: i32,
+    pub user_id: i64,
+    pub guess: String,
+    pub joined_at: i64,
+}
+
+crud!(RaidParticipant {}, "raid_participants");
+impl_select!(RaidParticipant { select_by_raid(group_id: i64, message_id: i32) -> Vec => "`where group_id = #{group_id} and message_id = #{message_id} order by joined_at, user_id`" }, "raid_participants");
+
+impl RaidParticipant {
+    // Joins while the raid is available, the primary key keeps concurrent joins of the same
+    // member from counting twice, returns whether they joined now
+    pub async fn join(executor: &dyn Executor, participant: &Self) -> rbatis::Result<bool> {
+        let result = executor
+            .exec(
+                "insert or ignore into raid_participants (group_id, message_id, user_id, guess, joined_at) select ?, ?, ?, ?, ? where exists (select 1 from groups_characters where group_id = ? and last_message_id = ? and available = 1)",
+                vec![
+                    rbs::to_value!(participant.group_id),
+                    rbs::to_value!(participant.message_id),
+                    rbs::to_value!(participant.user_id),
+                    rbs::to_value!(&participant.guess),
+                    rbs::to_value!(participant.joined_at),
+                    rbs::to_value!(participant.group_id),
+                    rbs::to_value!(participant.message_id),
+                ],
+            )
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+}
+
+// Alternate art of a character, the image of the character itself is the default one
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct CharacterVariant {
+    pub id: i64,
+    pub character_id: i64,
+    pub position: u16,
+    pub image_hash: Option<String>,
+    pub artist: String,
+    pub image_link: String,
+    // How many times rarer than the default art it is, `None` is as common
+    pub rarity: Option<u8>,
+}
+
+crud!(CharacterVariant {}, "character_variants");
+impl_delete!(CharacterVariant { delete_by_id(id: i64) => "`where id = #{id}`" }, "character_variants");
+impl_delete!(CharacterVariant { delete_by_character(character_id: i64) => "`where character_id = #{character_id}`" }, "character_variants");
+impl_update!(CharacterVariant { update_by_id(id: i64) => "`where id = #{id}`" }, "character_variants");
+impl_select!(CharacterVariant { select_by_id(id: i64) -> Option => "`where id = #{id} limit 1`" }, "character_variants");
+impl_select!(CharacterVariant { select_by_character(character_id: i64) -> Vec => "`where character_id = #{character_id} order by position, id`" }, "character_variants");
+impl_select!(CharacterVariant { select_last() -> Option => "`order by id desc limit 1`" }, "character_variants");
+
+impl CharacterVariant {
+    pub const MAX_VARIANTS: usize = 10;
+
+    // Picks the art of a spawn, `None` being the default one, which weighs as much as a
+    // variant without rarity
+    pub fn pick(variants: &[Self]) -> Option<&Self> {
+        let weight = |variant: &Self| 1.0 / variant.rarity.unwrap_or(1).max(1) as f64;
+
+        let total = 1.0 + variants.iter().map(weight).sum::<f64>();
+        let mut roll = thread_rng().gen_range(0.0..total);
+        if roll < 1.0 {
+            return None;
+        }
+        roll -= 1.0;
+
+        for variant in variants.iter() {
+            if roll < weight(variant) {
+                return Some(variant);
+            }
+            roll -= weight(variant);
+        }
+
+        None
+    }
+
+    // The character with the art and credits of this variant
+    pub fn apply(&self, character: &Character) -> Character {
+        let mut character = character.clone();
+        if self.image_hash.is_some() {
+            character.image_hash = self.image_hash.clone();
+        }
+        character.artist = self.artist.clone();
+        character.image_link = self.image_link.clone();
+
+        character
+    }
+
+    // Owners and spawns of a removed variant fall back to the default art
+    pub async fn unlink(executor: &dyn Executor, id: i64) -> rbatis::Result<()> {
+        executor
+            .exec(
+                "update owned_characters set variant_id = null where variant_id = ?",
+                vec![rbs::to_value!(id)],
+            )
+            .await?;
+        executor
+            .exec(
+                "update groups_characters set variant_id = null where variant_id = ?",
+                vec![rbs::to_value!(id)],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+// A file of the image store, named after the SHA-256 hash of its bytes
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Image {
+    pub hash: String,
+    pub size: u64,
+    pub mime_type: String,
+    // Perceptual hash, close for images that look alike
+    pub phash: Option<i64>,
+    pub created_at: i64,
+}
+
+crud!(Image {}, "images");
+impl_delete!(Image { delete_by_hash(hash: &str) => "`where hash = #{hash}`" }, "images");
+impl_update!(Image { update_by_hash(hash: &str) => "`where hash = #{hash}`" }, "images");
+impl_select!(Image { select_by_hash(hash: &str) -> Option => "`where hash = #{hash} limit 1`" }, "images");
+impl_select!(Image { select_with_phash() -> Vec => "`where phash is not null`" }, "images");
+impl_select!(Image { select_without_phash() -> Vec => "`where phash is null`" }, "images");
+
+impl Image {
+    // Images no character, variant or series points to anymore
+    pub async fn select_unreferenced(conn: &mut RBatis) -> rbatis::Result<Vec<Self>> {
+        conn.query_decode(
+            "select * from images where hash not in (select image_hash from characters where image_hash is not null union select image_hash from character_variants where image_hash is not null union select banner_hash from series where banner_hash is not null)",
+            vec![],
+        )
+        .await
+    }
+}
+
+// The IDs of the series sharing the topmost ancestor of the one given as its parameter,
+// `union` keeps the recursion from looping if a cycle ever slips into the table
+pub const FRANCHISE_IDS: &str = "with recursive ancestors(id, parent_id) as (select id, parent_id from series where id = ? union select series.id, series.parent_id from series join ancestors on series.id = ancestors.parent_id), franchise(id) as (select id from ancestors where parent_id is null union select series.id from series join franchise on series.parent_id = franchise.id) select id from franchise";
 
 #[derive(Clone, Default, Deserialize, Serialize)]
 pub struct Series {
     pub id: i64,
     pub title: String,
     pub artist: String,
-    pub banner: Option<Vec<u8>>,
+    pub banner_hash: Option<String>,
     pub aliases: Vec<String>,
-    pub liked_by: Vec<i64>,
     pub image_link: String,
     pub media_type: Media,
+    // The series this one is a sequel, spin-off or adaptation of
+    pub parent_id: Option<i64>,
+
+    pub anilist_id: Option<i64>,
 }
 
 crud!(Series {}, "series");
 impl_delete!(Series { delete_by_id(id: i64) => "`where id = #{id}`" }, "series");
 impl_update!(Series { update_by_id(id: i64) => "`where id = #{id}`" }, "series");
 impl_select!(Series { select_by_id(id: i64) -> Option => "`where id = #{id}`" }, "series");
-impl_select!(Series { select_by_title(title: &str) -> Option => "`where title like #{'%' + title + '%'} or aliases like #{'%' + title + '%'} order by title limit 1`" }, "series");
-impl_select!(Series { select_page_by_title(title: &str, page: u16, limit: u16) -> Vec => "`where title like #{'%' + title + '%'} or aliases like #{'%' + title + '%'} order by title limit #{limit} offset #{(page - 1) * limit}`" }, "series");
+impl_select!(Series { select_linked() -> Vec => "`where anilist_id is not null order by id`" }, "series");
 impl_select!(Series { select_last() -> Option => "`order by id desc limit 1`" }, "series");
+impl_select!(Series { select_random_for_quiz() -> Option => "`where banner_hash is not null or id in (select series_id from characters_series where character_id in (select id from characters where image_hash is not null)) order by random() limit 1`" }, "series");
+
+impl Series {
+    pub async fn search(
+        conn: &mut RBatis,
+        text: &str,
+        page: u16,
+        limit: u16,
+    ) -> rbatis::Result<Vec<Self>> {
+        let Some(query) = search_query(text) else {
+            return Ok(Vec::new());
+        };
+
+        conn.query_decode(
+            "select series.* from series_search join series on series.id = series_search.rowid where series_search match ? order by bm25(series_search, 10.0, 5.0), series.title, series.id limit ? offset ?",
+            vec![
+                rbs::to_value!(query),
+                rbs::to_value!(limit),
+                rbs::to_value!((page - 1) * limit),
+            ],
+        )
+        .await
+    }
+
+    // Every series sharing the topmost ancestor of this one, itself included
+    pub async fn select_franchise(conn: &mut RBatis, series_id: i64) -> rbatis::Result<Vec<Self>> {
+        let franchise: Vec<Self> = conn
+            .query_decode(
+                &format!(
+                    "select * from series where id in ({}) order by id",
+                    FRANCHISE_IDS
+                ),
+                vec![rbs::to_value!(series_id)],
+            )
+            .await?;
+
+        if franchise.is_empty() {
+            return Ok(Self::select_by_id(conn, series_id)
+                .await?
+                .into_iter()
+                .collect());
+        }
+
+        Ok(franchise)
+    }
+
+    // Whether the series is the other one or one of its ancestors, so making it a child of
+    // the other one would close a cycle
+    pub async fn is_ancestor_of(
+        conn: &mut RBatis,
+        series_id: i64,
+        other_id: i64,
+    ) -> rbatis::Result<bool> {
+        let count: u64 = conn
+            .query_decode(
+                "with recursive ancestors(id, parent_id) as (select id, parent_id from series where id = ? union select series.id, series.parent_id from series join ancestors on series.id = ancestors.parent_id) select count(*) as count from ancestors where id = ?",
+                vec![rbs::to_value!(other_id), rbs::to_value!(series_id)],
+            )
+            .await?;
+
+        Ok(count > 0)
+    }
+}
 
 #[derive(Default, Deserialize, Serialize)]
 pub struct User {
     pub id: i64,
     pub username: Option<String>,
     pub full_name: String,
+    pub utc_offset: Option<i32>,
     pub language_code: String,
 }
 
 crud!(User {}, "users");
 impl_update!(User { update_by_id(id: i64) => "`where id = #{id}`" }, "users");
 impl_select!(User { select_by_id(id: i64) -> Option => "`where id = #{id}`" }, "users");
+impl_select!(User { select_by_username(username: &str) -> Option => "`where username = #{username} limit 1`" }, "users");
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Auction {
+    pub id: i64,
+    // Packed group chat, so the auction can be closed without an update from it
+    pub chat: Vec<u8>,
+    pub group_id: i64,
+    pub seller_id: i64,
+    pub character_id: i64,
+    pub message_id: i32,
+    pub bid: i64,
+    pub bidder_id: Option<i64>,
+    pub ends_at: i64,
+}
+
+crud!(Auction {}, "auctions");
+impl_delete!(Auction { delete_by_id(id: i64) => "`where id = #{id}`" }, "auctions");
+impl_update!(Auction { update_by_id(id: i64) => "`where id = #{id}`" }, "auctions");
+impl_select!(Auction { select_by_id(id: i64) -> Option => "`where id = #{id}`" }, "auctions");
+impl_select!(Auction { select_by_message_id(group_id: i64, message_id: i32) -> Option => "`where group_id = #{group_id} and message_id = #{message_id} limit 1`" }, "auctions");
+impl_select!(Auction { select_by_character(group_id: i64, seller_id: i64, character_id: i64) -> Option => "`where group_id = #{group_id} and seller_id = #{seller_id} and character_id = #{character_id} limit 1`" }, "auctions");
+impl_select!(Auction { select_ended(now: i64) -> Vec => "`where ends_at <= #{now} order by ends_at`" }, "auctions");
+
+impl Auction {
+    // Inserts the auction with the next ID, picked by the insert itself so concurrent auctions
+    // can't get the same one, and returns it
+    pub async fn insert_new(conn: &mut RBatis, auction: &Self) -> rbatis::Result<i64> {
+        conn.query_decode(
+            "insert into auctions (id, chat, group_id, seller_id, character_id, message_id, bid, bidder_id, ends_at) select coalesce(max(id), 0) + 1, ?, ?, ?, ?, ?, ?, ?, ? from auctions returning id",
+            vec![
+                rbs::to_value!(&auction.chat),
+                rbs::to_value!(auction.group_id),
+                rbs::to_value!(auction.seller_id),
+                rbs::to_value!(auction.character_id),
+                rbs::to_value!(auction.message_id),
+                rbs::to_value!(auction.bid),
+                rbs::to_value!(auction.bidder_id),
+                rbs::to_value!(auction.ends_at),
+            ],
+        )
+        .await
+    }
+
+    // The lowest amount the next bid can have
+    pub fn min_bid(&self, increment: i64) -> i64 {
+        if self.bidder_id.is_some() {
+            self.bid + increment
+        } else {
+            self.bid
+        }
+    }
+
+    // Deletes the auction, returns `false` when it was already closed meanwhile
+    pub async fn remove(executor: &dyn Executor, id: i64) -> rbatis::Result<bool> {
+        let result = executor
+            .exec(
+                "delete from auctions where id = ?",
+                vec![rbs::to_value!(id)],
+            )
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+
+    // Replaces the current bid only if nobody else outbid it meanwhile
+    pub async fn raise_bid(
+        &self,
+        executor: &dyn Executor,
+        bid: i64,
+        bidder_id: i64,
+    ) -> rbatis::Result<bool> {
+        let result = executor
+            .exec(
+                "update auctions set bid = ?, bidder_id = ? where id = ? and bid = ? and ifnull(bidder_id, 0) = ?",
+                vec![
+                    rbs::to_value!(bid),
+                    rbs::to_value!(bidder_id),
+                    rbs::to_value!(self.id),
+                    rbs::to_value!(self.bid),
+                    rbs::to_value!(self.bidder_id.unwrap_or(0)),
+                ],
+            )
+            .await?;
+
+        Ok(result.rows_affected > 0)
+    }
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Duel {
+    pub id: i64,
+    pub group_id: i64,
+    pub challenger_id: i64,
+    pub opponent_id: i64,
+    pub stake: i64,
+    pub seed: i64,
+    pub challenger_character: Option<i64>,
+    pub opponent_character: Option<i64>,
+    pub created_at: i64,
+}
+
+crud!(Duel {}, "duels");
+impl_delete!(Duel { delete_by_id(id: i64) => "`where id = #{id}`" }, "duels");
+impl_update!(Duel { update_by_id(id: i64) => "`where id = #{id}`" }, "duels");
+impl_select!(Duel { select_by_id(id: i64) -> Option => "`where id = #{id}`" }, "duels");
+impl_select!(Duel { select_by_user(group_id: i64, user_id: i64) -> Option => "`where group_id = #{group_id} and (challenger_id = #{user_id} or opponent_id = #{user_id}) limit 1`" }, "duels");
+
+impl Duel {
+    // Like auctions, the insert picks the next ID and returns it
+    pub async fn insert_new(conn: &mut RBatis, duel: &Self) -> rbatis::Result<i64> {
+        conn.query_decode(
+            "insert into duels (id, group_id, challenger_id, opponent_id, stake, seed, challenger_character, opponent_character, created_at) select coalesce(max(id), 0) + 1, ?, ?, ?, ?, ?, ?, ?, ? from duels returning id",
+            vec![
+                rbs::to_value!(duel.group_id),
+                rbs::to_value!(duel.challenger_id),
+                rbs::to_value!(duel.opponent_id),
+                rbs::to_value!(duel.stake),
+                rbs::to_value!(duel.seed),
+                rbs::to_value!(duel.challenger_character),
+                rbs::to_value!(duel.opponent_character),
+                rbs::to_value!(duel.created_at),
+            ],
+        )
+        .await
+    }
+
+    // Saves the pick of one side only if it has not picked yet, so concurrent picks never
+    // overwrite each other, returns whether it was saved
+    pub async fn set_pick(
+        executor: &dyn Executor,
+        id: i64,
+        challenger: bool,
+        character_id: i64,
+    ) -> rbatis::Result<bool> {
+        let column = if challenger {
+            "challenger_character"
+        } else {
+            "opponent_character"
+        };
+        let result = executor
+            .exec(
+                &format!(
+                    "update duels set {0} = ? where id = ? and {0} is null",
+                    column
+                ),
+                vec![rbs::to_value!(character_id), rbs::to_value!(id)],
+            )
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+
+    // Deletes the duel, returns `false` when it was already finished or cancelled meanwhile
+    pub async fn remove(executor: &dyn Executor, id: i64) -> rbatis::Result<bool> {
+        let result = executor
+            .exec("delete from duels where id = ?", vec![rbs::to_value!(id)])
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct DuelRecord {
+    pub user_id: i64,
+    pub wins: u32,
+    pub losses: u32,
+}
+
+crud!(DuelRecord {}, "duel_records");
+impl_update!(DuelRecord { update_by_id(user_id: i64) => "`where user_id = #{user_id}`" }, "duel_records");
+impl_select!(DuelRecord { select_by_id(user_id: i64) -> Option => "`where user_id = #{user_id} limit 1`" }, "duel_records");
+
+impl DuelRecord {
+    pub async fn add_result(
+        executor: &dyn Executor,
+        user_id: i64,
+        won: bool,
+    ) -> rbatis::Result<()> {
+        match Self::select_by_id(executor, user_id).await? {
+            Some(mut record) => {
+                if won {
+                    record.wins += 1;
+                } else {
+                    record.losses += 1;
+                }
+                Self::update_by_id(executor, &record, user_id).await?;
+            }
+            None => {
+                Self::insert(
+                    executor,
+                    &Self {
+                        user_id,
+                        wins: won as u32,
+                        losses: !won as u32,
+                    },
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Quiz {
+    pub group_id: i64,
+    pub series_id: i64,
+    pub message_id: i32,
+    pub expires_at: i64,
+}
+
+crud!(Quiz {}, "quizzes");
+impl_delete!(Quiz { delete_by_id(group_id: i64) => "`where group_id = #{group_id}`" }, "quizzes");
+impl_select!(Quiz { select_by_id(group_id: i64) -> Option => "`where group_id = #{group_id} limit 1`" }, "quizzes");
+impl_select!(Quiz { select_by_message_id(group_id: i64, message_id: i32) -> Option => "`where group_id = #{group_id} and message_id = #{message_id} limit 1`" }, "quizzes");
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct QuizScore {
+    pub group_id: i64,
+    pub user_id: i64,
+    pub score: u32,
+}
+
+crud!(QuizScore {}, "quiz_scores");
+impl_update!(QuizScore { update_by_id(group_id: i64, user_id: i64) => "`where group_id = #{group_id} and user_id = #{user_id}`" }, "quiz_scores");
+impl_select!(QuizScore { select_by_id(group_id: i64, user_id: i64) -> Option => "`where group_id = #{group_id} and user_id = #{user_id} limit 1`" }, "quiz_scores");
+impl_select!(QuizScore { select_ranking_by_group(group_id: i64, limit: u16) -> Vec => "`where group_id = #{group_id} order by score desc, user_id limit #{limit}`" }, "quiz_scores");
+
+// A field change found while syncing with AniList, waiting for a sudoer to review it
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct SyncChange {
+    pub id: i64,
+    pub kind: SyncKind,
+    pub target_id: i64,
+    pub field: String,
+    pub old_value: String,
+    pub new_value: String,
+    pub created_at: i64,
+}
+
+crud!(SyncChange {}, "sync_changes");
+impl_delete!(SyncChange { delete_by_id(id: i64) => "`where id = #{id}`" }, "sync_changes");
+impl_delete!(SyncChange { delete_by_field(kind: &SyncKind, target_id: i64, field: &str) => "`where kind = #{kind} and target_id = #{target_id} and field = #{field}`" }, "sync_changes");
+impl_select!(SyncChange { select_by_id(id: i64) -> Option => "`where id = #{id}`" }, "sync_changes");
+impl_select!(SyncChange { select_first() -> Option => "`order by id limit 1`" }, "sync_changes");
+impl_select!(SyncChange { select_last() -> Option => "`order by id desc limit 1`" }, "sync_changes");
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncKind {
+    #[default]
+    Character,
+    Series,
+}
+
+// A user's like of a character or a series
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Like {
+    pub user_id: i64,
+    pub kind: LikeKind,
+    pub target_id: i64,
+    pub created_at: i64,
+}
 
+crud!(Like {}, "likes");
+impl_select!(Like { select_page_by_user(user_id: i64, page: u16, limit: u16) -> Vec => "`where user_id = #{user_id} order by created_at desc, kind, target_id limit #{limit} offset #{(page - 1) * limit}`" }, "likes");
+
+impl Like {
+    // Removes the like or adds it if there was none, each with a single statement so concurrent
+    // likes never overwrite each other, returns whether the target is liked now
+    pub async fn toggle(
+        executor: &dyn Executor,
+        user_id: i64,
+        kind: &LikeKind,
+        target_id: i64,
+    ) -> rbatis::Result<bool> {
+        let removed = executor
+            .exec(
+                "delete from likes where user_id = ? and kind = ? and target_id = ?",
+                vec![
+                    rbs::to_value!(user_id),
+                    rbs::to_value!(kind),
+                    rbs::to_value!(target_id),
+                ],
+            )
+            .await?;
+        if removed.rows_affected > 0 {
+            return Ok(false);
+        }
+
+        executor
+            .exec(
+                "insert or ignore into likes (user_id, kind, target_id, created_at) values (?, ?, ?, ?)",
+                vec![
+                    rbs::to_value!(user_id),
+                    rbs::to_value!(kind),
+                    rbs::to_value!(target_id),
+                    rbs::to_value!(chrono::Utc::now().timestamp()),
+                ],
+            )
+            .await?;
+
+        Ok(true)
+    }
+
+    pub async fn count_by_target(
+        conn: &mut RBatis,
+        kind: &LikeKind,
+        target_id: i64,
+    ) -> rbatis::Result<usize> {
+        let count: u64 = conn
+            .query_decode(
+                "select count(*) as count from likes where kind = ? and target_id = ?",
+                vec![rbs::to_value!(kind), rbs::to_value!(target_id)],
+            )
+            .await?;
+
+        Ok(count as usize)
+    }
+
+    pub async fn count_by_user(conn: &mut RBatis, user_id: i64) -> rbatis::Result<usize> {
+        let count: u64 = conn
+            .query_decode(
+                "select count(*) as count from likes where user_id = ?",
+                vec![rbs::to_value!(user_id)],
+            )
+            .await?;
+
+        Ok(count as usize)
+    }
+
+    // The most liked characters or series among the likes given since the timestamp, the media
+    // type only keeps the ones from series of that type
+    pub async fn select_ranking(
+        conn: &mut RBatis,
+        kind: &LikeKind,
+        since: i64,
+        media: Option<&Media>,
+        limit: u16,
+    ) -> rbatis::Result<Vec<LikeRanking>> {
+        let mut sql = String::from(
+            "select target_id, count(*) as likes from likes where kind = ? and created_at >= ?",
+        );
+        let mut args = vec![rbs::to_value!(kind), rbs::to_value!(since)];
+
+        if let Some(media) = media {
+            sql += match kind {
+                LikeKind::Character => " and target_id in (select character_id from characters_series where series_id in (select id from series where media_type = ?))",
+                LikeKind::Series => " and target_id in (select id from series where media_type = ?)",
+            };
+            args.push(rbs::to_value!(media));
+        }
+
+        sql += " group by target_id order by likes desc, target_id limit ?";
+        args.push(rbs::to_value!(limit));
+
+        conn.query_decode(&sql, args).await
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LikeRanking {
+    pub target_id: i64,
+    pub likes: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LikeKind {
+    #[default]
+    Character,
+    Series,
+}
+
+// A photo already stored by Telegram, sent again without uploading its bytes
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct PhotoReference {
+    pub key: String,
+    pub photo_id: i64,
+    pub access_hash: i64,
+    pub file_reference: Vec<u8>,
+}
+
+crud!(PhotoReference {}, "photo_references");
+impl_delete!(PhotoReference { delete_by_key(key: &str) => "`where key = #{key}`" }, "photo_references");
+impl_select!(PhotoReference { select_by_key(key: &str) -> Option => "`where key = #{key} limit 1`" }, "photo_references");
+
+// Tags classify characters (e.g. `villain`), `name` is the identifier used in commands
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Tag {
+    pub id: i64,
+    pub name: String,
+}
+
+crud!(Tag {}, "tags");
+impl_delete!(Tag { delete_by_id(id: i64) => "`where id = #{id}`" }, "tags");
+impl_select!(Tag { select_by_id(id: i64) -> Option => "`where id = #{id}`" }, "tags");
+impl_select!(Tag { select_by_name(name: &str) -> Option => "`where name = #{name} limit 1`" }, "tags");
+impl_select!(Tag { select_by_character(character_id: i64) -> Vec => "`where id in (select tag_id from characters_tags where character_id = #{character_id}) order by name`" }, "tags");
+impl_select!(Tag { select_by_group(group_id: i64) -> Vec => "`where id in (select tag_id from groups_tags where group_id = #{group_id}) order by name`" }, "tags");
+impl_select!(Tag { select_ordered() -> Vec => "`order by name`" }, "tags");
+impl_select!(Tag { select_last() -> Option => "`order by id desc limit 1`" }, "tags");
+
+impl Tag {
+    // Finds a tag by its name or by any of its display names
+    pub async fn find(executor: &dyn Executor, name: &str) -> rbatis::Result<Option<Self>> {
+        let name = name.trim().to_lowercase();
+        if let Some(tag) = Self::select_by_name(executor, &name).await? {
+            return Ok(Some(tag));
+        }
+
+        match TagName::select_by_name(executor, &name).await? {
+            Some(tag_name) => Self::select_by_id(executor, tag_name.tag_id).await,
+            None => Ok(None),
+        }
+    }
+
+    // The name shown in the given locale, falling back to the tag's own name
+    pub async fn display_name(
+        &self,
+        executor: &dyn Executor,
+        language_code: &str,
+    ) -> rbatis::Result<String> {
+        Ok(TagName::select_by_id(executor, self.id, language_code)
+            .await?
+            .map_or(self.name.clone(), |tag_name| tag_name.name))
+    }
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct TagName {
+    pub tag_id: i64,
+    pub language_code: String,
+    pub name: String,
+}
+
+crud!(TagName {}, "tag_names");
+impl_delete!(TagName { delete_by_id(tag_id: i64, language_code: &str) => "`where tag_id = #{tag_id} and language_code = #{language_code}`" }, "tag_names");
+impl_delete!(TagName { delete_by_tag(tag_id: i64) => "`where tag_id = #{tag_id}`" }, "tag_names");
+impl_select!(TagName { select_by_id(tag_id: i64, language_code: &str) -> Option => "`where tag_id = #{tag_id} and language_code = #{language_code} limit 1`" }, "tag_names");
+impl_select!(TagName { select_by_name(name: &str) -> Option => "`where lower(name) = #{name} limit 1`" }, "tag_names");
+impl_select!(TagName { select_by_tag(tag_id: i64) -> Vec => "`where tag_id = #{tag_id} order by language_code`" }, "tag_names");
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct CharacterTag {
+    pub character_id: i64,
+    pub tag_id: i64,
+}
+
+crud!(CharacterTag {}, "characters_tags");
+impl_delete!(CharacterTag { delete_by_id(character_id: i64, tag_id: i64) => "`where character_id = #{character_id} and tag_id = #{tag_id}`" }, "characters_tags");
+impl_delete!(CharacterTag { delete_by_character(character_id: i64) => "`where character_id = #{character_id}`" }, "characters_tags");
+impl_delete!(CharacterTag { delete_by_tag(tag_id: i64) => "`where tag_id = #{tag_id}`" }, "characters_tags");
+impl_select!(CharacterTag { select_by_id(character_id: i64, tag_id: i64) -> Option => "`where character_id = #{character_id} and tag_id = #{tag_id} limit 1`" }, "characters_tags");
+
+// Every series a character appears in, the primary one from `Character::series_id` included,
+// the triggers keep the primary series linked
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct CharacterSeries {
+    pub character_id: i64,
+    pub series_id: i64,
+}
+
+crud!(CharacterSeries {}, "characters_series");
+impl_delete!(CharacterSeries { delete_by_id(character_id: i64, series_id: i64) => "`where character_id = #{character_id} and series_id = #{series_id}`" }, "characters_series");
+impl_delete!(CharacterSeries { delete_by_character(character_id: i64) => "`where character_id = #{character_id}`" }, "characters_series");
+impl_select!(CharacterSeries { select_by_id(character_id: i64, series_id: i64) -> Option => "`where character_id = #{character_id} and series_id = #{series_id} limit 1`" }, "characters_series");
+impl_select!(CharacterSeries { select_by_character(character_id: i64) -> Vec => "`where character_id = #{character_id} order by series_id`" }, "characters_series");
+
+// The tags a group restricted its spawns to, no rows means every character can spawn
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct GroupTag {
+    pub group_id: i64,
+    pub tag_id: i64,
+}
+
+crud!(GroupTag {}, "groups_tags");
+impl_delete!(GroupTag { delete_by_group(group_id: i64) => "`where group_id = #{group_id}`" }, "groups_tags");
+impl_delete!(GroupTag { delete_by_tag(tag_id: i64) => "`where tag_id = #{tag_id}`" }, "groups_tags");
+impl_select!(GroupTag { select_by_group(group_id: i64) -> Vec => "`where group_id = #{group_id}`" }, "groups_tags");
+
+// The franchises a group restricted its spawns to, by any of their series
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct GroupSeries {
+    pub group_id: i64,
+    pub series_id: i64,
+}
+
+crud!(GroupSeries {}, "groups_series");
+impl_delete!(GroupSeries { delete_by_group(group_id: i64) => "`where group_id = #{group_id}`" }, "groups_series");
+impl_select!(GroupSeries { select_by_group(group_id: i64) -> Vec => "`where group_id = #{group_id}`" }, "groups_series");
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Wallet {
+    pub user_id: i64,
+    pub balance: i64,
+}
+
+crud!(Wallet {}, "wallets");
+impl_select!(Wallet { select_by_id(user_id: i64) -> Option => "`where user_id = #{user_id} limit 1`" }, "wallets");
+
+impl Wallet {
+    // Adds (or removes, when negative) an amount from the balance and records it in the ledger,
+    // returns `false` without touching anything when the balance is not enough
+    pub async fn apply(
+        executor: &dyn Executor,
+        user_id: i64,
+        amount: i64,
+        reason: &str,
+    ) -> rbatis::Result<bool> {
+        // Only credits need the wallet to exist, a debit from a missing one is refused anyway
+        if amount > 0 {
+            executor
+                .exec(
+                    "insert or ignore into wallets (user_id, balance) values (?, 0)",
+                    vec![rbs::to_value!(user_id)],
+                )
+                .await?;
+        }
+
+        let result = executor
+            .exec(
+                "update wallets set balance = balance + ? where user_id = ? and balance + ? >= 0",
+                vec![
+                    rbs::to_value!(amount),
+                    rbs::to_value!(user_id),
+                    rbs::to_value!(amount),
+                ],
+            )
+            .await?;
+        if result.rows_affected == 0 {
+            return Ok(false);
+        }
+
+        Transaction::insert(
+            executor,
+            &Transaction {
+                user_id,
+                amount,
+                reason: reason.to_string(),
+                created_at: chrono::Utc::now().timestamp(),
+            },
+        )
+        .await?;
+
+        Ok(true)
+    }
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Transaction {
+    pub user_id: i64,
+    pub amount: i64,
+    pub reason: String,
+    pub created_at: i64,
+}
+
+crud!(Transaction {}, "transactions");
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Inventory {
+    pub user_id: i64,
+    pub hint_tokens: u32,
+    pub extra_slots: u32,
+}
+
+crud!(Inventory {}, "inventories");
+impl_update!(Inventory { update_by_id(user_id: i64) => "`where user_id = #{user_id}`" }, "inventories");
+impl_select!(Inventory { select_by_id(user_id: i64) -> Option => "`where user_id = #{user_id} limit 1`" }, "inventories");
+
+impl Inventory {
+    pub async fn select_or_default(executor: &dyn Executor, user_id: i64) -> rbatis::Result<Self> {
+        Ok(Self::select_by_id(executor, user_id)
+            .await?
+            .unwrap_or(Self {
+                user_id,
+                ..Default::default()
+            }))
+    }
+
+    pub async fn save(&self, executor: &dyn Executor) -> rbatis::Result<()> {
+        let result = executor
+            .exec(
+                "insert or ignore into inventories (user_id, hint_tokens, extra_slots) values (?, ?, ?)",
+                vec![
+                    rbs::to_value!(self.user_id),
+                    rbs::to_value!(self.hint_tokens),
+                    rbs::to_value!(self.extra_slots),
+                ],
+            )
+            .await?;
+        if result.rows_affected == 0 {
+            Self::update_by_id(executor, self, self.user_id).await?;
+        }
+
+        Ok(())
+    }
+
+    // Spends a hint token with a single statement so concurrent uses can't both take the last
+    // one, returns whether there was one to spend
+    pub async fn use_hint_token(executor: &dyn Executor, user_id: i64) -> rbatis::Result<bool> {
+        let result = executor
+            .exec(
+                "update inventories set hint_tokens = hint_tokens - 1 where user_id = ? and hint_tokens > 0",
+                vec![rbs::to_value!(user_id)],
+            )
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+
+    pub fn capacity(&self) -> usize {
+        OwnedCharacter::COLLECTION_SIZE + self.extra_slots as usize
+    }
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct DailyStreak {
+    pub user_id: i64,
+    pub streak: u32,
+    pub last_period: i64,
+}
+
+crud!(DailyStreak {}, "daily_streaks");
+impl_update!(DailyStreak { update_by_id(user_id: i64) => "`where user_id = #{user_id}`" }, "daily_streaks");
+impl_select!(DailyStreak { select_by_id(user_id: i64) -> Option => "`where user_id = #{user_id} limit 1`" }, "daily_streaks");
+
+impl DailyStreak {
+    // Claims the period only if it was not claimed yet, each with a single statement so
+    // concurrent claims can't both succeed, the streak goes on when the last claim was in the
+    // previous period, returns whether it was claimed
+    pub async fn claim(executor: &dyn Executor, user_id: i64, period: i64) -> rbatis::Result<bool> {
+        let inserted = executor
+            .exec(
+                "insert or ignore into daily_streaks (user_id, streak, last_period) values (?, 1, ?)",
+                vec![rbs::to_value!(user_id), rbs::to_value!(period)],
+            )
+            .await?;
+        if inserted.rows_affected == 1 {
+            return Ok(true);
+        }
+
+        let updated = executor
+            .exec(
+                "update daily_streaks set streak = case when last_period = ? - 1 then streak + 1 else 1 end, last_period = ? where user_id = ? and last_period < ?",
+                vec![
+                    rbs::to_value!(period),
+                    rbs::to_value!(period),
+                    rbs::to_value!(user_id),
+                    rbs::to_value!(period),
+                ],
+            )
+            .await?;
+
+        Ok(updated.rows_affected == 1)
+    }
+}
+
+// Legacy collection rows, only read to migrate them into `OwnedCharacter`
 #[derive(Default, Deserialize, Serialize)]
 pub struct UserCharacters {
     pub user_id: i64,
@@ -112,26 +1281,159 @@ pub struct UserCharacters {
 crud!(UserCharacters {}, "users_characters");
 impl_delete!(UserCharacters { delete_by_id(user_id: i64, group_id: i64) => "`where user_id = #{user_id} and group_id = #{group_id}`" }, "users_characters");
 impl_update!(UserCharacters { update_by_id(user_id: i64, group_id: i64) => "`where user_id = #{user_id} and group_id = #{group_id}`" }, "users_characters");
-impl_select!(UserCharacters { select_by_id(user_id: i64, group_id: i64) -> Option => "`where user_id = #{user_id} and group_id = #{group_id}`" }, "users_characters");
 
-impl UserCharacters {
-    pub async fn select_or_insert_by_id(
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct OwnedCharacter {
+    pub user_id: i64,
+    pub group_id: i64,
+    pub character_id: i64,
+    pub variant_id: Option<i64>,
+    pub level: u32,
+    pub xp: u32,
+    pub obtained_at: i64,
+}
+
+crud!(OwnedCharacter {}, "owned_characters");
+impl_delete!(OwnedCharacter { delete_by_id(user_id: i64, group_id: i64, character_id: i64) => "`where user_id = #{user_id} and group_id = #{group_id} and character_id = #{character_id}`" }, "owned_characters");
+impl_update!(OwnedCharacter { update_by_id(user_id: i64, group_id: i64, character_id: i64) => "`where user_id = #{user_id} and group_id = #{group_id} and character_id = #{character_id}`" }, "owned_characters");
+impl_select!(OwnedCharacter { select_by_id(user_id: i64, group_id: i64, character_id: i64) -> Option => "`where user_id = #{user_id} and group_id = #{group_id} and character_id = #{character_id} limit 1`" }, "owned_characters");
+impl_select!(OwnedCharacter { select_by_user(user_id: i64, group_id: i64) -> Vec => "`where user_id = #{user_id} and group_id = #{group_id} order by obtained_at, character_id`" }, "owned_characters");
+
+impl OwnedCharacter {
+    pub const MAX_LEVEL: u32 = 50;
+    pub const COLLECTION_SIZE: usize = 9;
+
+    pub fn new(user_id: i64, group_id: i64, character_id: i64, variant_id: Option<i64>) -> Self {
+        Self {
+            user_id,
+            group_id,
+            character_id,
+            variant_id,
+            level: 1,
+            xp: 0,
+            obtained_at: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    // Inserts the copy unless the user already owns the character, the unique key keeps two
+    // collects at the same moment from making two rows, returns whether it was inserted
+    pub async fn insert_new(
+        executor: &dyn Executor,
+        owned_character: &Self,
+    ) -> rbatis::Result<bool> {
+        let result = executor
+            .exec(
+                "insert or ignore into owned_characters (user_id, group_id, character_id, variant_id, level, xp, obtained_at) values (?, ?, ?, ?, ?, ?, ?)",
+                vec![
+                    rbs::to_value!(owned_character.user_id),
+                    rbs::to_value!(owned_character.group_id),
+                    rbs::to_value!(owned_character.character_id),
+                    rbs::to_value!(owned_character.variant_id),
+                    rbs::to_value!(owned_character.level),
+                    rbs::to_value!(owned_character.xp),
+                    rbs::to_value!(owned_character.obtained_at),
+                ],
+            )
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+
+    pub fn xp_to_next_level(&self) -> u32 {
+        self.level * 100
+    }
+
+    pub fn add_xp(&mut self, amount: u32) -> bool {
+        let previous_level = self.level;
+
+        self.xp += amount;
+        while self.level < Self::MAX_LEVEL && self.xp >= self.xp_to_next_level() {
+            self.xp -= self.xp_to_next_level();
+            self.level += 1;
+        }
+
+        if self.level == Self::MAX_LEVEL {
+            self.xp = 0;
+        }
+
+        self.level > previous_level
+    }
+
+    // How many characters of the series the user has in the group
+    pub async fn count_by_series(
         conn: &mut RBatis,
         user_id: i64,
         group_id: i64,
-    ) -> rbatis::Result<Option<Self>> {
-        if let user_characters @ Some(_) = Self::select_by_id(conn, user_id, group_id).await? {
-            Ok(user_characters)
-        } else {
-            let user_characters = Self {
-                user_id,
-                group_id,
-                characters_id: Vec::new(),
-            };
-            Self::insert(conn, &user_characters).await?;
+        series_ids: &[i64],
+    ) -> rbatis::Result<usize> {
+        let mut args = vec![rbs::to_value!(user_id), rbs::to_value!(group_id)];
+        args.extend(series_ids.iter().map(|id| rbs::to_value!(id)));
 
-            Ok(Some(user_characters))
-        }
+        let count: u64 = conn
+            .query_decode(
+                &format!(
+                    "select count(*) as count from owned_characters where user_id = ? and group_id = ? and character_id in (select character_id from characters_series where series_id in ({}))",
+                    placeholders(series_ids.len())
+                ),
+                args,
+            )
+            .await?;
+
+        Ok(count as usize)
+    }
+
+    pub async fn count_by_user(
+        conn: &mut RBatis,
+        user_id: i64,
+        group_id: i64,
+    ) -> rbatis::Result<usize> {
+        let count: u64 = conn
+            .query_decode(
+                "select count(*) as count from owned_characters where user_id = ? and group_id = ?",
+                vec![rbs::to_value!(user_id), rbs::to_value!(group_id)],
+            )
+            .await?;
+
+        Ok(count as usize)
+    }
+
+    pub async fn select_ranking_by_group(
+        conn: &mut RBatis,
+        group_id: i64,
+        limit: u16,
+    ) -> rbatis::Result<Vec<Ranking>> {
+        conn.query_decode(
+            "select user_id, count(*) as characters, sum(level) as levels from owned_characters where group_id = ? group by user_id order by levels desc, characters desc, user_id limit ?",
+            vec![rbs::to_value!(group_id), rbs::to_value!(limit)],
+        )
+        .await
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Ranking {
+    pub user_id: i64,
+    pub characters: u64,
+    pub levels: u64,
+}
+
+fn placeholders(count: usize) -> String {
+    vec!["?"; count].join(", ")
+}
+
+// Every word matches whole or as a prefix, whole words rank higher, the
+// FTS5 syntax is stripped so user input can't break the query
+pub fn search_query(text: &str) -> Option<String> {
+    let terms = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("(\"{0}\" OR \"{0}\"*)", term))
+        .collect::<Vec<String>>();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" AND "))
     }
 }
 
@@ -152,7 +1454,7 @@ where
     serializer.serialize_u8(if *value { 1 } else { 0 })
 }
 
-#[derive(Clone, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Gender {
     #[default]
@@ -171,7 +1473,7 @@ impl std::fmt::Display for Gender {
     }
 }
 
-#[derive(Clone, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Media {
     Anime,
@@ -199,3 +1501,80 @@ impl std::fmt::Display for Media {
         })
     }
 }
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpawnKind {
+    #[default]
+    Normal,
+    Raid,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::Database;
+
+    #[tokio::test]
+    async fn merge_moves_variants_and_events() {
+        let path = std::env::temp_dir().join(format!("maiden-merge-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        std::env::set_var(
+            "DATABASE_URL",
+            format!("sqlite://{}?mode=rwc", path.display()),
+        );
+
+        let mut db = Database::connect().await;
+        let conn = db.get_conn();
+
+        for (id, character_id) in [(1, 1), (2, 2)] {
+            CharacterVariant::insert(
+                conn,
+                &CharacterVariant {
+                    id,
+                    character_id,
+                    position: 1,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        }
+        Event::insert(
+            conn,
+            &Event {
+                id: 1,
+                boosted_characters: vec![2, 3],
+                exclusive_characters: vec![1, 2],
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let tx = conn.acquire_begin().await.unwrap();
+        Character::merge_references(&tx, 1, 2).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let variants = CharacterVariant::select_by_character(conn, 1)
+            .await
+            .unwrap();
+        assert_eq!(
+            variants
+                .iter()
+                .map(|variant| (variant.id, variant.position))
+                .collect::<Vec<_>>(),
+            vec![(1, 1), (2, 2)]
+        );
+        assert!(CharacterVariant::select_by_character(conn, 2)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let event = Event::select_by_id(conn, 1).await.unwrap().unwrap();
+        assert_eq!(event.boosted_characters, vec![1, 3]);
+        assert_eq!(event.exclusive_characters, vec![1]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -47,3 +47,18 @@ pub fn sudoers() -> SudoUser {
         ],
     }
 }
+
+// Passes the inline queries, `@bot <text>`
+#[derive(Clone)]
+pub struct Inline;
+
+#[async_trait]
+impl Filter for Inline {
+    async fn is_ok(&mut self, _client: &Client, update: &Update) -> bool {
+        matches!(update, Update::InlineQuery(_))
+    }
+}
+
+pub fn inline() -> Inline {
+    Inline
+}
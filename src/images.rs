@@ -0,0 +1,250 @@
+use std::{
+    fs,
+    io::{Cursor, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use image::{imageops::FilterType, DynamicImage, ImageOutputFormat, RgbImage};
+use rbatis::{executor::Executor, RBatis};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    database::models::{Character, Image},
+    Result,
+};
+
+const PATH: &str = "./assets/images";
+
+// Larger images are shrunk to fit it, keeping their aspect ratio
+const MAX_DIMENSION: u32 = 1280;
+const JPEG_QUALITY: u8 = 90;
+
+// Each image of a collage is cropped to a portrait cell, four of them go in a square
+const COLLAGE_CELL_WIDTH: u32 = 360;
+const COLLAGE_CELL_HEIGHT: u32 = 480;
+
+// How many of the 64 bits of two perceptual hashes may differ for the images to look alike
+const SIMILARITY_THRESHOLD: u32 = 10;
+
+// Files are spread in folders named after the first two characters of their hash
+pub fn path(hash: &str) -> PathBuf {
+    Path::new(PATH).join(&hash[..2]).join(hash)
+}
+
+pub fn hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// Saves the bytes once no matter how many rows use them, returns their hash
+pub async fn store(executor: &dyn Executor, bytes: &[u8]) -> Result<String> {
+    let hash = hash(bytes);
+    let path = path(&hash);
+
+    if !path.exists() {
+        fs::create_dir_all(path.parent().unwrap())?;
+
+        // Written aside first, so a crash never leaves half a file under the hash
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, bytes)?;
+        fs::rename(&temporary, &path)?;
+    }
+
+    if Image::select_by_hash(executor, &hash).await?.is_none() {
+        let image = Image {
+            hash: hash.clone(),
+            size: bytes.len() as u64,
+            mime_type: mime_type(bytes).to_string(),
+            phash: perceptual_hash(bytes),
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        Image::insert(executor, &image).await?;
+    }
+
+    Ok(hash)
+}
+
+// Decodes the image, which fails when it isn't one, and re-encodes it as a JPEG no larger
+// than the maximum dimension; every new image goes through it before being stored
+pub fn normalize(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut image = image::load_from_memory(bytes)?;
+    if image.width() == 0 || image.height() == 0 {
+        return Err("the image is empty".into());
+    }
+
+    if image.width() > MAX_DIMENSION || image.height() > MAX_DIMENSION {
+        image = image.resize(MAX_DIMENSION, MAX_DIMENSION, FilterType::Lanczos3);
+    }
+
+    let mut output = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_to(&mut output, ImageOutputFormat::Jpeg(JPEG_QUALITY))?;
+
+    Ok(output.into_inner())
+}
+
+// Tiles the images side by side, or in two rows when there are four, the ones that can't be
+// decoded are skipped
+pub fn collage(images: &[Vec<u8>]) -> Result<Vec<u8>> {
+    let images = images
+        .iter()
+        .filter_map(|bytes| image::load_from_memory(bytes).ok())
+        .collect::<Vec<_>>();
+    if images.is_empty() {
+        return Err("no image to make the collage of".into());
+    }
+
+    let columns = if images.len() == 4 {
+        2
+    } else {
+        images.len() as u32
+    };
+    let rows = (images.len() as u32).div_ceil(columns);
+
+    let mut canvas = RgbImage::new(columns * COLLAGE_CELL_WIDTH, rows * COLLAGE_CELL_HEIGHT);
+    for (index, image) in images.iter().enumerate() {
+        let cell = image
+            .resize_to_fill(
+                COLLAGE_CELL_WIDTH,
+                COLLAGE_CELL_HEIGHT,
+                FilterType::Triangle,
+            )
+            .to_rgb8();
+
+        image::imageops::overlay(
+            &mut canvas,
+            &cell,
+            (index as u32 % columns * COLLAGE_CELL_WIDTH) as i64,
+            (index as u32 / columns * COLLAGE_CELL_HEIGHT) as i64,
+        );
+    }
+
+    let mut output = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(canvas).write_to(&mut output, ImageOutputFormat::Jpeg(JPEG_QUALITY))?;
+
+    Ok(output.into_inner())
+}
+
+// A difference hash: each bit tells whether a pixel of the 9x8 grayscale thumbnail is
+// darker than the one on its right, so resized or re-encoded copies get close hashes
+pub fn perceptual_hash(bytes: &[u8]) -> Option<i64> {
+    let image = image::load_from_memory(bytes).ok()?;
+    let pixels = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if pixels.get_pixel(x, y)[0] < pixels.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+
+    // SQLite integers are signed, the bits are kept as they are
+    Some(hash as i64)
+}
+
+// Characters other than `character_id` whose image looks like the stored one
+pub async fn similar_characters(
+    executor: &dyn Executor,
+    hash: &str,
+    character_id: i64,
+) -> Result<Vec<Character>> {
+    let Some(phash) = Image::select_by_hash(executor, hash)
+        .await?
+        .and_then(|image| image.phash)
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut characters = Vec::new();
+    for image in Image::select_with_phash(executor).await? {
+        let distance = (phash ^ image.phash.unwrap_or_default()).count_ones();
+        if distance > SIMILARITY_THRESHOLD {
+            continue;
+        }
+
+        characters.extend(
+            Character::select_by_image_hash(executor, &image.hash)
+                .await?
+                .into_iter()
+                .filter(|character| character.id != character_id),
+        );
+    }
+
+    Ok(characters)
+}
+
+pub fn load(hash: &str) -> Result<Vec<u8>> {
+    Ok(fs::read(path(hash))?)
+}
+
+// Removes the images nothing points to and the files left without metadata, returns how
+// many were removed; it runs on startup, before any update can store a new image
+pub async fn cleanup(conn: &mut RBatis) -> Result<usize> {
+    let mut removed = 0;
+
+    for image in Image::select_unreferenced(conn).await? {
+        match fs::remove_file(path(&image.hash)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        Image::delete_by_hash(conn, &image.hash).await?;
+
+        removed += 1;
+    }
+
+    let Ok(folders) = fs::read_dir(PATH) else {
+        return Ok(removed);
+    };
+
+    for folder in folders.flatten() {
+        if !folder.path().is_dir() {
+            continue;
+        }
+
+        for file in fs::read_dir(folder.path())?.flatten() {
+            let name = file.file_name().to_string_lossy().to_string();
+            if Image::select_by_hash(conn, &name).await?.is_none() {
+                fs::remove_file(file.path())?;
+                removed += 1;
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
+// Computes the perceptual hashes the images stored before them are missing, returns how many
+pub async fn backfill_hashes(conn: &mut RBatis) -> Result<usize> {
+    let mut updated = 0;
+
+    for mut image in Image::select_without_phash(conn).await? {
+        let Some(phash) = load(&image.hash)
+            .ok()
+            .and_then(|bytes| perceptual_hash(&bytes))
+        else {
+            continue;
+        };
+
+        image.phash = Some(phash);
+        Image::update_by_hash(conn, &image, &image.hash).await?;
+
+        updated += 1;
+    }
+
+    Ok(updated)
+}
+
+fn mime_type(bytes: &[u8]) -> &'static str {
+    match bytes {
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
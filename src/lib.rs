@@ -1,11 +1,16 @@
+pub mod anilist;
+pub mod catalog;
 mod config;
 pub mod database;
 pub mod filters;
+pub mod images;
 pub mod middlewares;
 pub mod modules;
 pub mod routers;
+pub mod search;
+pub mod tasks;
 pub mod utils;
 
-pub use config::Config;
+pub use config::{Config, ItemEffect, ShopItem};
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -7,7 +7,7 @@ use grammers_friendly::prelude::*;
 use holy_maiden_bot::{
     middlewares::{SaveChat, SetLocale},
     modules::{Conversation, Database, I18n},
-    routers, Result,
+    routers, tasks, Result,
 };
 
 const LOG_FILE: &str = "./assets/maiden.log";
@@ -56,7 +56,7 @@ async fn main() -> Result<()> {
     let client = Client::connect(Config {
         session: Session::load_file_or_create(SESSION_FILE)?,
         api_id: config.telegram.api_id,
-        api_hash: config.telegram.api_hash,
+        api_hash: config.telegram.api_hash.clone(),
         params: InitParams {
             catch_up: config.bot.catch_up,
             reconnection_policy: &AutoReconnectPolicy,
@@ -73,20 +73,43 @@ async fn main() -> Result<()> {
         log::info!("bot authorized");
     }
 
+    let db = Database::connect().await;
+    let i18n = I18n::new("en-GB");
+
+    // Background tasks
+    tokio::spawn(tasks::close_auctions(
+        client.clone(),
+        db.clone(),
+        i18n.clone(),
+        config.auctions.check_interval,
+    ));
+    tokio::spawn(tasks::sync_anilist(db.clone(), config.clone()));
+
     // Dispatcher
     Dispatcher::default()
-        .add_module(Database::connect().await)
-        .add_module(I18n::new("en-GB"))
+        .add_module(config.clone())
+        .add_module(db)
+        .add_module(i18n)
         .add_module(Conversation::new(client.clone()))
         .add_middleware(Middleware::before(SaveChat))
         .add_middleware(Middleware::before(SetLocale))
         .add_router(routers::start())
         .add_router(routers::help())
+        .add_router(routers::inline())
         .add_router(routers::language())
         .add_router(routers::character())
         .add_router(routers::series())
+        .add_router(routers::tag())
+        .add_router(routers::like())
         .add_router(routers::list())
         .add_router(routers::collect())
+        .add_router(routers::ranking())
+        .add_router(routers::event())
+        .add_router(routers::daily())
+        .add_router(routers::shop())
+        .add_router(routers::auction())
+        .add_router(routers::duel())
+        .add_router(routers::quiz())
         .add_router(routers::admin())
         .add_router(routers::send_character())
         .ignore_updates_from_self(true)
@@ -35,6 +35,7 @@ impl MiddlewareImpl for SaveChat {
                         id: group.id(),
                         title: group.title().to_string(),
                         username: group.username().map(String::from),
+                        utc_offset: None,
                         language_code: "en-GB".to_string(),
                     };
                     Group::insert(conn, &g).await?;
@@ -51,6 +52,7 @@ impl MiddlewareImpl for SaveChat {
                         id: user.id(),
                         username: user.username().map(String::from),
                         full_name: user.full_name(),
+                        utc_offset: None,
                         language_code: user
                             .lang_code()
                             .map(|lang| match lang {
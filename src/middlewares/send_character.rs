@@ -1,14 +1,23 @@
 use std::{collections::HashMap, ops::Range};
 
 use async_trait::async_trait;
-use grammers_client::{types::Chat, Client, InputMessage, Update};
+use grammers_client::{
+    button, reply_markup,
+    types::{Chat, Message},
+    Client, InputMessage, Update,
+};
 use grammers_friendly::prelude::*;
 use rand::{thread_rng, Rng};
+use rbatis::RBatis;
 
 use crate::{
-    database::models::{Character, GroupCharacter},
+    database::models::{
+        Character, CharacterVariant, Event, GroupCharacter, GroupSeries, GroupTag, Series,
+        SpawnKind,
+    },
     modules::{Database, I18n},
-    Result,
+    utils::PhotoSource,
+    Config, Result,
 };
 
 #[derive(Clone, Default)]
@@ -28,6 +37,216 @@ impl SendCharacter {
             ..Default::default()
         }
     }
+
+    // Picks a random character, taking the boosted and exclusive characters of events
+    // and the tags and franchises the group restricted its spawns to into account
+    pub async fn pick_character(
+        conn: &mut RBatis,
+        group_id: Option<i64>,
+        min_stars: u8,
+        series_id: Option<i64>,
+    ) -> rbatis::Result<Option<(Character, Option<Event>)>> {
+        let now = chrono::Utc::now().timestamp();
+
+        let (active_events, inactive_events): (Vec<Event>, Vec<Event>) = Event::select_all(conn)
+            .await?
+            .into_iter()
+            .partition(|event| event.is_active(now));
+
+        // Event-exclusive characters never spawn outside their events
+        let excluded_ids = inactive_events
+            .iter()
+            .flat_map(|event| event.exclusive_characters.iter().copied())
+            .filter(|id| {
+                !active_events
+                    .iter()
+                    .any(|event| event.exclusive_characters.contains(id))
+            })
+            .collect::<Vec<i64>>();
+
+        // Series summons skip the event boosts
+        if let Some(series_id) = series_id {
+            return Ok(Character::select_random_by_pool(
+                conn,
+                min_stars,
+                Some((&[series_id][..], &[][..])),
+                &[],
+                &[],
+                &excluded_ids,
+            )
+            .await?
+            .map(|character| (character, None)));
+        }
+
+        let tag_ids = match group_id {
+            Some(group_id) => GroupTag::select_by_group(conn, group_id)
+                .await?
+                .into_iter()
+                .map(|group_tag| group_tag.tag_id)
+                .collect::<Vec<i64>>(),
+            None => Vec::new(),
+        };
+
+        let mut series_ids = Vec::new();
+        if let Some(group_id) = group_id {
+            for group_series in GroupSeries::select_by_group(conn, group_id).await? {
+                for series in Series::select_franchise(conn, group_series.series_id).await? {
+                    if !series_ids.contains(&series.id) {
+                        series_ids.push(series.id);
+                    }
+                }
+            }
+        }
+
+        for event in active_events.iter() {
+            let roll = thread_rng().gen_range(0..100);
+            if roll >= event.boost {
+                continue;
+            }
+
+            let ids = event
+                .boosted_characters
+                .iter()
+                .chain(event.exclusive_characters.iter())
+                .copied()
+                .collect::<Vec<i64>>();
+
+            if let Some(character) = Character::select_random_by_pool(
+                conn,
+                min_stars,
+                Some((event.boosted_series.as_slice(), ids.as_slice())),
+                &tag_ids,
+                &series_ids,
+                &excluded_ids,
+            )
+            .await?
+            {
+                return Ok(Some((character, Some(event.clone()))));
+            }
+        }
+
+        Ok(Character::select_random_by_pool(
+            conn,
+            min_stars,
+            None,
+            &tag_ids,
+            &series_ids,
+            &excluded_ids,
+        )
+        .await?
+        .map(|character| (character, active_events.into_iter().next())))
+    }
+
+    // Sends a random character to the message's chat, returns `false` when nothing was spawned
+    pub async fn spawn_character(
+        client: &mut Client,
+        conn: &mut RBatis,
+        i18n: &I18n,
+        config: &Config,
+        message: &Message,
+        active_characters: &[GroupCharacter],
+    ) -> Result<bool> {
+        let t = |key| i18n.get(key);
+
+        let group_id = message.chat().id();
+
+        // Rare characters may show up as a raid that needs several members
+        let is_raid = config.spawn.raid_chance > 0.0
+            && thread_rng().gen_bool(config.spawn.raid_chance.min(1.0));
+        let raid_character = if is_raid {
+            Self::pick_character(conn, Some(group_id), config.spawn.raid_min_stars, None).await?
+        } else {
+            None
+        };
+        let (kind, random_character) = match raid_character {
+            Some(picked) => (SpawnKind::Raid, Some(picked)),
+            None => (
+                SpawnKind::Normal,
+                Self::pick_character(conn, Some(group_id), 0, None).await?,
+            ),
+        };
+
+        let Some((random_character, event)) = random_character else {
+            return Ok(false);
+        };
+
+        // The same character can't be active twice
+        if active_characters
+            .iter()
+            .any(|group_character| group_character.character_id == random_character.id)
+        {
+            return Ok(false);
+        }
+
+        // Characters with alternate art may spawn with any of them
+        let variants = CharacterVariant::select_by_character(conn, random_character.id).await?;
+        let variant = CharacterVariant::pick(&variants);
+
+        let mut group_character = GroupCharacter {
+            group_id,
+            character_id: random_character.id,
+            variant_id: variant.map(|variant| variant.id),
+
+            available: true,
+
+            kind,
+            ..Default::default()
+        };
+
+        // Send the character
+        let input_message = if group_character.kind == SpawnKind::Raid {
+            group_character.required = config.spawn.raid_participants;
+            group_character.expires_at = chrono::Utc::now().timestamp() + config.spawn.raid_window;
+
+            InputMessage::html(crate::utils::construct_raid_info(i18n, &group_character, 0))
+                .reply_markup(&reply_markup::inline(vec![vec![button::inline(
+                    t("raid_progress_button"),
+                    "raid progress",
+                )]]))
+        } else {
+            // Active events may replace the spawn text
+            InputMessage::html(
+                event
+                    .map(|event| event.spawn_text)
+                    .filter(|spawn_text| !spawn_text.is_empty())
+                    .unwrap_or_else(|| t("new_character")),
+            )
+        };
+        let input_message = input_message.media_ttl(200);
+        let response = crate::utils::send_photo(
+            client,
+            conn,
+            PhotoSource::Character(&random_character, variant),
+            |file| {
+                let input_message = input_message.clone();
+
+                async move {
+                    match file {
+                        Some(file) => message
+                            .respond(input_message.copy_media(&file))
+                            .await
+                            .map(Some),
+                        None => Ok(None),
+                    }
+                }
+            },
+        )
+        .await?;
+
+        // Nothing is spawned when there's no image to send
+        let Some(response) = response else {
+            return Ok(false);
+        };
+
+        // Replace any previous spawn of the same character
+        GroupCharacter::delete_by_id(conn, group_id, random_character.id).await?;
+
+        // Insert the new active character
+        group_character.last_message_id = response.id();
+        GroupCharacter::insert(conn, &group_character).await?;
+
+        Ok(true)
+    }
 }
 
 #[async_trait]
@@ -40,6 +259,7 @@ impl MiddlewareImpl for SendCharacter {
     ) -> Result<()> {
         let mut db = data.get_module::<Database>().unwrap();
         let i18n = data.get_module::<I18n>().unwrap();
+        let config = data.get_module::<Config>().unwrap();
 
         let t = |key| i18n.get(key);
 
@@ -57,88 +277,60 @@ impl MiddlewareImpl for SendCharacter {
                 ));
                 *num_messages += 1;
 
-                let last_group_character =
-                    GroupCharacter::select_last_by_id(conn, group_id).await?;
-
-                if let Some(ref group_character) = last_group_character {
-                    if group_character.available {
-                        // Check if the character is left behind without anyone collecting it
-                        if (message.id() - group_character.last_message_id) >= 35 {
-                            if let Some(character) =
-                                Character::select_by_id(conn, group_character.character_id).await?
-                            {
-                                // Reset message count
-                                *num_messages = 0;
-
-                                // Delete group last character
-                                GroupCharacter::delete_by_id(
-                                    conn,
-                                    group_character.group_id,
-                                    group_character.character_id,
-                                )
-                                .await?;
+                let mut active_characters =
+                    GroupCharacter::select_available_by_group(conn, group_id).await?;
 
-                                // Send the reply message
-                                message
-                                    .respond(
-                                        InputMessage::html(
-                                            t("character_escaped")
-                                                .replace("{name}", &character.name),
-                                        )
-                                        .reply_to(Some(group_character.last_message_id)),
-                                    )
-                                    .await?;
+                // Check if any character is left behind without anyone collecting it
+                for group_character in active_characters.iter() {
+                    if group_character.has_escaped(message.id(), config.spawn.escape_after) {
+                        // Delete the escaped character
+                        GroupCharacter::delete_by_message_id(
+                            conn,
+                            group_id,
+                            group_character.last_message_id,
+                        )
+                        .await?;
 
-                                return Ok(());
-                            }
+                        if let Some(character) =
+                            Character::select_by_id(conn, group_character.character_id).await?
+                        {
+                            // Send the reply message
+                            message
+                                .respond(
+                                    InputMessage::html(
+                                        t("character_escaped").replace("{name}", &character.name),
+                                    )
+                                    .reply_to(Some(group_character.last_message_id)),
+                                )
+                                .await?;
                         }
                     }
                 }
+                active_characters.retain(|group_character| {
+                    !group_character.has_escaped(message.id(), config.spawn.escape_after)
+                });
 
                 if num_messages >= num_needed {
+                    // Wait until one of the active characters is collected or escapes
+                    if active_characters.len() >= config.spawn.max_active {
+                        return Ok(());
+                    }
+
                     *num_messages = 0;
                     *num_needed = thread_rng().gen_range(self.min_messages..self.max_messages);
 
-                    if let Some(random_character) = Character::select_random(conn).await? {
-                        // If the character is the last one, skip it
-                        if let Some(group_character) = last_group_character {
-                            if random_character.id == group_character.character_id {
-                                return Ok(());
-                            }
-                        }
-
-                        // If the character has already been sent, skip it
-                        if GroupCharacter::select_by_id(conn, group_id, random_character.id)
-                            .await?
-                            .is_some()
-                        {
-                            *num_messages = *num_needed;
-                            return Ok(());
-                        }
-
-                        let file =
-                            crate::utils::upload_photo(client, random_character.clone(), conn)
-                                .await?
-                                .unwrap();
-
-                        // Send the character
-                        let response = message
-                            .respond(
-                                InputMessage::html(t("new_character"))
-                                    .media_ttl(200)
-                                    .photo(file),
-                            )
-                            .await?;
-
-                        // Insert group last character
-                        let group_character = GroupCharacter {
-                            group_id,
-                            character_id: random_character.id,
-                            last_message_id: response.id(),
-
-                            available: true,
-                        };
-                        GroupCharacter::insert(conn, &group_character).await?;
+                    // If nothing was spawned, try again on the next message
+                    if !Self::spawn_character(
+                        client,
+                        conn,
+                        &i18n,
+                        &config,
+                        &message,
+                        &active_characters,
+                    )
+                    .await?
+                    {
+                        *num_messages = *num_needed;
                     }
                 }
             }
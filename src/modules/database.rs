@@ -5,9 +5,124 @@ use grammers_friendly::prelude::*;
 use rbatis::{intercept_log::LogInterceptor, table_sync::SqliteTableMapper, RBatis};
 use rbdc_pool_deadpool::DeadPool;
 use rbdc_sqlite::{Driver, SqliteConnectOptions};
+use serde::Deserialize;
 
 use crate::database::models::*;
 
+// Full-text index over names, aliases and the titles of every series of the characters, kept in
+// sync by triggers; the ones that only looked at the primary series are recreated
+const SEARCH_SCHEMA: &[&str] = &[
+    "drop trigger if exists characters_search_insert",
+    "drop trigger if exists characters_search_update",
+    "drop trigger if exists series_search_insert",
+    "drop trigger if exists series_search_update",
+    "drop trigger if exists series_search_delete",
+    "create virtual table if not exists characters_search using fts5(name, aliases, series, tokenize = 'unicode61 remove_diacritics 2')",
+    "create virtual table if not exists series_search using fts5(title, aliases, tokenize = 'unicode61 remove_diacritics 2')",
+    "create trigger if not exists characters_search_insert after insert on characters begin delete from characters_search where rowid = new.id; insert into characters_search (rowid, name, aliases, series) select id, name, case when json_valid(characters.aliases) then (select group_concat(value, ' ') from json_each(characters.aliases)) else characters.aliases end, (select group_concat(series.title, ' ') from characters_series join series on series.id = characters_series.series_id where characters_series.character_id = characters.id) from characters where id = new.id; end",
+    "create trigger if not exists characters_search_update after update of id, name, aliases, series_id on characters begin delete from characters_search where rowid = old.id; delete from characters_search where rowid = new.id; insert into characters_search (rowid, name, aliases, series) select id, name, case when json_valid(characters.aliases) then (select group_concat(value, ' ') from json_each(characters.aliases)) else characters.aliases end, (select group_concat(series.title, ' ') from characters_series join series on series.id = characters_series.series_id where characters_series.character_id = characters.id) from characters where id = new.id; end",
+    "create trigger if not exists characters_search_delete after delete on characters begin delete from characters_search where rowid = old.id; end",
+    "create trigger if not exists characters_series_search_insert after insert on characters_series begin delete from characters_search where rowid = new.character_id; insert into characters_search (rowid, name, aliases, series) select id, name, case when json_valid(characters.aliases) then (select group_concat(value, ' ') from json_each(characters.aliases)) else characters.aliases end, (select group_concat(series.title, ' ') from characters_series join series on series.id = characters_series.series_id where characters_series.character_id = characters.id) from characters where id = new.character_id; end",
+    "create trigger if not exists characters_series_search_delete after delete on characters_series begin delete from characters_search where rowid = old.character_id; insert into characters_search (rowid, name, aliases, series) select id, name, case when json_valid(characters.aliases) then (select group_concat(value, ' ') from json_each(characters.aliases)) else characters.aliases end, (select group_concat(series.title, ' ') from characters_series join series on series.id = characters_series.series_id where characters_series.character_id = characters.id) from characters where id = old.character_id; end",
+    "create trigger if not exists series_search_insert after insert on series begin insert into series_search (rowid, title, aliases) values (new.id, new.title, case when json_valid(new.aliases) then (select group_concat(value, ' ') from json_each(new.aliases)) else new.aliases end); update characters_search set series = (select group_concat(series.title, ' ') from characters_series join series on series.id = characters_series.series_id where characters_series.character_id = characters_search.rowid) where rowid in (select character_id from characters_series where series_id = new.id); end",
+    "create trigger if not exists series_search_update after update of id, title, aliases on series begin delete from series_search where rowid = old.id; insert into series_search (rowid, title, aliases) values (new.id, new.title, case when json_valid(new.aliases) then (select group_concat(value, ' ') from json_each(new.aliases)) else new.aliases end); update characters_search set series = (select group_concat(series.title, ' ') from characters_series join series on series.id = characters_series.series_id where characters_series.character_id = characters_search.rowid) where rowid in (select character_id from characters_series where series_id in (old.id, new.id)); end",
+    "create trigger if not exists series_search_delete after delete on series begin delete from series_search where rowid = old.id; update characters_search set series = (select group_concat(series.title, ' ') from characters_series join series on series.id = characters_series.series_id where characters_series.character_id = characters_search.rowid) where rowid in (select character_id from characters_series where series_id = old.id); end",
+    "delete from characters_search",
+    "insert into characters_search (rowid, name, aliases, series) select id, name, case when json_valid(characters.aliases) then (select group_concat(value, ' ') from json_each(characters.aliases)) else characters.aliases end, (select group_concat(series.title, ' ') from characters_series join series on series.id = characters_series.series_id where characters_series.character_id = characters.id) from characters",
+    "delete from series_search",
+    "insert into series_search (rowid, title, aliases) select id, title, case when json_valid(series.aliases) then (select group_concat(value, ' ') from json_each(series.aliases)) else series.aliases end from series",
+];
+
+// Cached Telegram photos are dropped when the image they were uploaded from changes,
+// the update triggers are recreated since they used to watch the image blobs
+const PHOTO_REFERENCE_SCHEMA: &[&str] = &[
+    "drop trigger if exists characters_photo_update",
+    "drop trigger if exists series_photo_update",
+    "create trigger if not exists characters_photo_update after update of image_hash on characters when old.image_hash is not new.image_hash begin delete from photo_references where key = 'character_' || old.id; end",
+    "create trigger if not exists characters_photo_delete after delete on characters begin delete from photo_references where key = 'character_' || old.id; end",
+    "create trigger if not exists series_photo_update after update of banner_hash on series when old.banner_hash is not new.banner_hash begin delete from photo_references where key = 'series_' || old.id; end",
+    "create trigger if not exists series_photo_delete after delete on series begin delete from photo_references where key = 'series_' || old.id; end",
+    "create trigger if not exists variants_photo_update after update of image_hash on character_variants when old.image_hash is not new.image_hash begin delete from photo_references where key = 'variant_' || old.id; end",
+    "create trigger if not exists variants_photo_delete after delete on character_variants begin delete from photo_references where key = 'variant_' || old.id; end",
+];
+
+// Characters can appear in several series, the table is created here to have its primary key,
+// the existing `series_id` values are linked as the primary series and triggers keep them linked
+const CHARACTER_SERIES_SCHEMA: &[&str] = &[
+    "create table if not exists characters_series (character_id integer not null, series_id integer not null, primary key (character_id, series_id))",
+    "create trigger if not exists characters_series_insert after insert on characters begin insert or ignore into characters_series (character_id, series_id) values (new.id, new.series_id); end",
+    "create trigger if not exists characters_series_update after update of series_id on characters when old.series_id is not new.series_id begin delete from characters_series where character_id = old.id and series_id = old.series_id; insert or ignore into characters_series (character_id, series_id) values (new.id, new.series_id); end",
+    "create trigger if not exists characters_series_delete after delete on characters begin delete from characters_series where character_id = old.id; end",
+    "create trigger if not exists series_characters_delete after delete on series begin delete from characters_series where series_id = old.id; end",
+    "insert or ignore into characters_series (character_id, series_id) select id, series_id from characters",
+];
+
+// Deleting a series hands its children to its own parent and lifts the spawn restrictions
+// pointing to it
+const FRANCHISE_SCHEMA: &[&str] = &[
+    "create trigger if not exists series_franchise_delete after delete on series begin update series set parent_id = old.parent_id where parent_id = old.id; delete from groups_series where series_id = old.id; end",
+];
+
+// Likes live in their own table, created here to have its primary key, and go away with what
+// they point to
+const LIKES_SCHEMA: &[&str] = &[
+    "create table if not exists likes (user_id integer not null, kind text not null, target_id integer not null, created_at integer not null, primary key (user_id, kind, target_id))",
+    "create index if not exists likes_target on likes (kind, target_id)",
+    "create trigger if not exists characters_likes_delete after delete on characters begin delete from likes where kind = 'character' and target_id = old.id; end",
+    "create trigger if not exists series_likes_delete after delete on series begin delete from likes where kind = 'series' and target_id = old.id; end",
+];
+
+// A user owns a character once per group, the duplicates older versions could leave are merged
+// into the most advanced copy before the index is created
+const OWNED_CHARACTERS_SCHEMA: &[&str] = &[
+    "delete from owned_characters where rowid not in (select (select rowid from owned_characters as copy where copy.user_id = owned.user_id and copy.group_id = owned.group_id and copy.character_id = owned.character_id order by copy.level desc, copy.xp desc, copy.rowid limit 1) from (select distinct user_id, group_id, character_id from owned_characters) as owned)",
+    "create unique index if not exists owned_characters_user_character on owned_characters (user_id, group_id, character_id)",
+];
+
+// Raid participants have their own table so concurrent joins never drop each other, they go
+// away with their raid
+const RAID_SCHEMA: &[&str] = &[
+    "create table if not exists raid_participants (group_id integer not null, message_id integer not null, user_id integer not null, guess text not null, joined_at integer not null, primary key (group_id, message_id, user_id))",
+    "create trigger if not exists raid_participants_delete after delete on groups_characters begin delete from raid_participants where group_id = old.group_id and message_id = old.last_message_id; end",
+];
+
+// Daily claims rely on a single streak per user, duplicates left by older versions are dropped
+// before the index is created
+const DAILY_STREAKS_SCHEMA: &[&str] = &[
+    "delete from daily_streaks where rowid not in (select max(rowid) from daily_streaks group by user_id)",
+    "create unique index if not exists daily_streaks_user on daily_streaks (user_id)",
+];
+
+// Wallets are created on the first credit with `insert or ignore`, which needs one row per user;
+// the copies concurrent credits could leave hold the same balance, since every update hit them all
+const WALLETS_SCHEMA: &[&str] = &[
+    "delete from wallets where rowid not in (select max(rowid) from wallets group by user_id)",
+    "create unique index if not exists wallets_user on wallets (user_id)",
+];
+
+// Like wallets, inventories are saved with `insert or ignore` and every update hit all the copies
+const INVENTORIES_SCHEMA: &[&str] = &[
+    "delete from inventories where rowid not in (select max(rowid) from inventories group by user_id)",
+    "create unique index if not exists inventories_user on inventories (user_id)",
+];
+
+// The tables that used to keep their likes in a `liked_by` array, with the kind of their likes
+const LIKED_BY_COLUMNS: &[(&str, &str)] = &[("characters", "character"), ("series", "series")];
+
+// The blob columns images were kept in before the image store, with their hash columns
+const IMAGE_COLUMNS: &[(&str, &str, &str)] = &[
+    ("characters", "image", "image_hash"),
+    ("character_variants", "image", "image_hash"),
+    ("series", "banner", "banner_hash"),
+];
+
+#[derive(Deserialize)]
+struct StoredBlob {
+    id: i64,
+    kind: String,
+    bytes: Vec<u8>,
+}
+
 #[derive(Clone)]
 pub struct Database {
     conn: RBatis,
@@ -63,9 +178,39 @@ impl Database {
     async fn sync(&mut self) {
         log::info!("syncing database...");
 
+        let auction = Auction::default();
+        let _ = RBatis::sync(&self.conn, &SqliteTableMapper {}, &auction, "auctions").await;
+
         let character = Character::default();
         let _ = RBatis::sync(&self.conn, &SqliteTableMapper {}, &character, "characters").await;
 
+        let character_variant = CharacterVariant::default();
+        let _ = RBatis::sync(
+            &self.conn,
+            &SqliteTableMapper {},
+            &character_variant,
+            "character_variants",
+        )
+        .await;
+
+        let duel = Duel::default();
+        let _ = RBatis::sync(&self.conn, &SqliteTableMapper {}, &duel, "duels").await;
+
+        let duel_record = DuelRecord::default();
+        let _ = RBatis::sync(
+            &self.conn,
+            &SqliteTableMapper {},
+            &duel_record,
+            "duel_records",
+        )
+        .await;
+
+        let event = Event::default();
+        let _ = RBatis::sync(&self.conn, &SqliteTableMapper {}, &event, "events").await;
+
+        let image = Image::default();
+        let _ = RBatis::sync(&self.conn, &SqliteTableMapper {}, &image, "images").await;
+
         let group = Group::default();
         let _ = RBatis::sync(&self.conn, &SqliteTableMapper {}, &group, "groups").await;
 
@@ -78,23 +223,354 @@ impl Database {
         )
         .await;
 
+        let photo_reference = PhotoReference::default();
+        let _ = RBatis::sync(
+            &self.conn,
+            &SqliteTableMapper {},
+            &photo_reference,
+            "photo_references",
+        )
+        .await;
+
+        let quiz = Quiz::default();
+        let _ = RBatis::sync(&self.conn, &SqliteTableMapper {}, &quiz, "quizzes").await;
+
+        let quiz_score = QuizScore::default();
+        let _ = RBatis::sync(
+            &self.conn,
+            &SqliteTableMapper {},
+            &quiz_score,
+            "quiz_scores",
+        )
+        .await;
+
+        let sync_change = SyncChange::default();
+        let _ = RBatis::sync(
+            &self.conn,
+            &SqliteTableMapper {},
+            &sync_change,
+            "sync_changes",
+        )
+        .await;
+
         let series = Series::default();
         let _ = RBatis::sync(&self.conn, &SqliteTableMapper {}, &series, "series").await;
 
         let user = User::default();
         let _ = RBatis::sync(&self.conn, &SqliteTableMapper {}, &user, "users").await;
 
-        let user_characters = UserCharacters::default();
+        let wallet = Wallet::default();
+        let _ = RBatis::sync(&self.conn, &SqliteTableMapper {}, &wallet, "wallets").await;
+
+        let tag = Tag::default();
+        let _ = RBatis::sync(&self.conn, &SqliteTableMapper {}, &tag, "tags").await;
+
+        let tag_name = TagName::default();
+        let _ = RBatis::sync(&self.conn, &SqliteTableMapper {}, &tag_name, "tag_names").await;
+
+        let character_tag = CharacterTag::default();
         let _ = RBatis::sync(
             &self.conn,
             &SqliteTableMapper {},
-            &user_characters,
-            "users_characters",
+            &character_tag,
+            "characters_tags",
         )
         .await;
 
+        let group_tag = GroupTag::default();
+        let _ = RBatis::sync(&self.conn, &SqliteTableMapper {}, &group_tag, "groups_tags").await;
+
+        let group_series = GroupSeries::default();
+        let _ = RBatis::sync(
+            &self.conn,
+            &SqliteTableMapper {},
+            &group_series,
+            "groups_series",
+        )
+        .await;
+
+        let transaction = Transaction::default();
+        let _ = RBatis::sync(
+            &self.conn,
+            &SqliteTableMapper {},
+            &transaction,
+            "transactions",
+        )
+        .await;
+
+        let inventory = Inventory::default();
+        let _ = RBatis::sync(&self.conn, &SqliteTableMapper {}, &inventory, "inventories").await;
+
+        let daily_streak = DailyStreak::default();
+        let _ = RBatis::sync(
+            &self.conn,
+            &SqliteTableMapper {},
+            &daily_streak,
+            "daily_streaks",
+        )
+        .await;
+
+        let owned_character = OwnedCharacter::default();
+        let _ = RBatis::sync(
+            &self.conn,
+            &SqliteTableMapper {},
+            &owned_character,
+            "owned_characters",
+        )
+        .await;
+
+        for sql in PHOTO_REFERENCE_SCHEMA.iter() {
+            if let Err(e) = self.conn.exec(sql, vec![]).await {
+                log::error!("failed to sync the photo references: {}", e);
+                break;
+            }
+        }
+
+        for sql in CHARACTER_SERIES_SCHEMA.iter() {
+            if let Err(e) = self.conn.exec(sql, vec![]).await {
+                log::error!("failed to sync the characters series: {}", e);
+                break;
+            }
+        }
+
+        // The index is rebuilt on every start in case the tables were changed without the triggers,
+        // after the series links it reads the titles from
+        for sql in SEARCH_SCHEMA.iter() {
+            if let Err(e) = self.conn.exec(sql, vec![]).await {
+                log::error!("failed to sync the search index: {}", e);
+                break;
+            }
+        }
+
+        for sql in LIKES_SCHEMA.iter() {
+            if let Err(e) = self.conn.exec(sql, vec![]).await {
+                log::error!("failed to sync the likes: {}", e);
+                break;
+            }
+        }
+
+        for sql in OWNED_CHARACTERS_SCHEMA.iter() {
+            if let Err(e) = self.conn.exec(sql, vec![]).await {
+                log::error!("failed to sync the owned characters: {}", e);
+                break;
+            }
+        }
+
+        for sql in RAID_SCHEMA.iter() {
+            if let Err(e) = self.conn.exec(sql, vec![]).await {
+                log::error!("failed to sync the raid participants: {}", e);
+                break;
+            }
+        }
+
+        for sql in DAILY_STREAKS_SCHEMA.iter() {
+            if let Err(e) = self.conn.exec(sql, vec![]).await {
+                log::error!("failed to sync the daily streaks: {}", e);
+                break;
+            }
+        }
+
+        for sql in WALLETS_SCHEMA.iter() {
+            if let Err(e) = self.conn.exec(sql, vec![]).await {
+                log::error!("failed to sync the wallets: {}", e);
+                break;
+            }
+        }
+
+        for sql in INVENTORIES_SCHEMA.iter() {
+            if let Err(e) = self.conn.exec(sql, vec![]).await {
+                log::error!("failed to sync the inventories: {}", e);
+                break;
+            }
+        }
+
+        for sql in FRANCHISE_SCHEMA.iter() {
+            if let Err(e) = self.conn.exec(sql, vec![]).await {
+                log::error!("failed to sync the franchises: {}", e);
+                break;
+            }
+        }
+
+        if let Err(e) = self.migrate_user_characters().await {
+            log::error!("failed to migrate users characters: {}", e);
+        }
+
+        if let Err(e) = self.migrate_images().await {
+            log::error!("failed to move the images to the image store: {}", e);
+        }
+
+        if let Err(e) = self.migrate_likes().await {
+            log::error!("failed to move the likes to their table: {}", e);
+        }
+
+        match crate::images::cleanup(&mut self.conn).await {
+            Ok(removed) if removed > 0 => log::info!("removed {} unused images", removed),
+            Ok(_) => {}
+            Err(e) => log::error!("failed to clean the image store: {}", e),
+        }
+
+        match crate::images::backfill_hashes(&mut self.conn).await {
+            Ok(updated) if updated > 0 => log::info!("hashed {} stored images", updated),
+            Ok(_) => {}
+            Err(e) => log::error!("failed to hash the stored images: {}", e),
+        }
+
         log::info!("database synced");
     }
+
+    // Moves the images to the image store one row at a time, then drops their columns. Besides
+    // blobs, older versions could keep them as text, or as the JSON byte arrays rbatis writes for
+    // `Vec<u8>`, the column is only dropped once none of them is left behind
+    async fn migrate_images(&mut self) -> crate::Result<()> {
+        let conn = &mut self.conn;
+
+        for (table, column, hash_column) in IMAGE_COLUMNS.iter() {
+            let count: u64 = conn
+                .query_decode(
+                    &format!(
+                        "select count(*) as count from pragma_table_info('{}') where name = '{}'",
+                        table, column
+                    ),
+                    vec![],
+                )
+                .await?;
+            if count == 0 {
+                continue;
+            }
+
+            let mut moved = 0;
+            loop {
+                let blobs: Vec<StoredBlob> = conn
+                    .query_decode(
+                        &format!(
+                            "select id, typeof({0}) as kind, cast({0} as blob) as bytes from {1} where {0} is not null and {2} is null limit 1",
+                            column, table, hash_column
+                        ),
+                        vec![],
+                    )
+                    .await?;
+                let Some(blob) = blobs.into_iter().next() else {
+                    break;
+                };
+
+                let bytes = match blob.kind.as_str() {
+                    "text" => serde_json::from_slice::<Vec<u8>>(&blob.bytes).unwrap_or(blob.bytes),
+                    _ => blob.bytes,
+                };
+                let hash = if bytes.is_empty() {
+                    None
+                } else {
+                    Some(crate::images::store(conn, &bytes).await?)
+                };
+                conn.exec(
+                    &format!(
+                        "update {0} set {1} = ?, {2} = null where id = ?",
+                        table, hash_column, column
+                    ),
+                    vec![rbs::to_value!(hash), rbs::to_value!(blob.id)],
+                )
+                .await?;
+
+                moved += 1;
+            }
+
+            let left: u64 = conn
+                .query_decode(
+                    &format!(
+                        "select count(*) as count from {0} where {1} is not null and {2} is null",
+                        table, column, hash_column
+                    ),
+                    vec![],
+                )
+                .await?;
+            if left > 0 {
+                return Err(format!(
+                    "{} images of {} could not be moved, {} is kept",
+                    left, table, column
+                )
+                .into());
+            }
+
+            conn.exec(
+                &format!("alter table {} drop column {}", table, column),
+                vec![],
+            )
+            .await?;
+            log::info!("moved {} images of {} to the image store", moved, table);
+        }
+
+        Ok(())
+    }
+
+    // Copies the `liked_by` arrays to the likes table, then drops their columns
+    async fn migrate_likes(&mut self) -> rbatis::Result<()> {
+        let conn = &mut self.conn;
+        let now = chrono::Utc::now().timestamp();
+
+        for (table, kind) in LIKED_BY_COLUMNS.iter() {
+            let count: u64 = conn
+                .query_decode(
+                    &format!(
+                        "select count(*) as count from pragma_table_info('{}') where name = 'liked_by'",
+                        table
+                    ),
+                    vec![],
+                )
+                .await?;
+            if count == 0 {
+                continue;
+            }
+
+            let result = conn
+                .exec(
+                    &format!(
+                        "insert or ignore into likes (user_id, kind, target_id, created_at) select json_each.value, ?, {0}.id, ? from {0}, json_each(case when json_valid({0}.liked_by) then {0}.liked_by else '[]' end)",
+                        table
+                    ),
+                    vec![rbs::to_value!(kind), rbs::to_value!(now)],
+                )
+                .await?;
+
+            conn.exec(
+                &format!("alter table {} drop column liked_by", table),
+                vec![],
+            )
+            .await?;
+            log::info!(
+                "moved {} likes of {} to their table",
+                result.rows_affected,
+                table
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn migrate_user_characters(&mut self) -> rbatis::Result<()> {
+        let conn = &mut self.conn;
+
+        // The legacy table may not exist at all
+        let Ok(rows) = UserCharacters::select_all(conn).await else {
+            return Ok(());
+        };
+
+        for user_characters in rows {
+            for character_id in user_characters.characters_id {
+                let owned_character = OwnedCharacter::new(
+                    user_characters.user_id,
+                    user_characters.group_id,
+                    character_id,
+                    None,
+                );
+                OwnedCharacter::insert_new(conn, &owned_character).await?;
+            }
+
+            UserCharacters::delete_by_id(conn, user_characters.user_id, user_characters.group_id)
+                .await?;
+        }
+
+        Ok(())
+    }
 }
 
 impl Module for Database {}
@@ -0,0 +1,392 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+};
+
+use grammers_client::{
+    types::{Media, Message},
+    Client, InputMessage, Update,
+};
+use grammers_friendly::prelude::*;
+use rbatis::{executor::Executor, RBatis};
+
+use crate::{
+    catalog::{CharacterRow, Format, RowErrors, SeriesRow},
+    database::models::{Character, Series},
+    modules::{Database, I18n},
+    Result,
+};
+
+const MAX_ERRORS: usize = 30;
+
+pub fn router() -> Router {
+    Router::default().add_handler(Handler::new_message(
+        export,
+        macros::command!("/!.", "export").and(crate::filters::sudoers()),
+    ))
+}
+
+async fn export(client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let message = update.get_message().unwrap();
+
+    let splitted = message.text().split_whitespace().collect::<Vec<&str>>();
+    let format = match splitted.get(2) {
+        Some(text) => Format::from_name(text),
+        None => Some(Format::Json),
+    };
+
+    let (Some(kind @ (&"characters" | &"series")), Some(format)) = (splitted.get(1), format) else {
+        message
+            .reply(InputMessage::html(t("invalid_command").replace(
+                "{cmd}",
+                &crate::utils::escape_html(format!("{} characters|series [json|csv]", splitted[0])),
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    let conn = db.get_conn();
+
+    let (bytes, count) = if *kind == "characters" {
+        let rows = Character::select_all(conn)
+            .await?
+            .iter()
+            .map(CharacterRow::from)
+            .collect::<Vec<CharacterRow>>();
+
+        (crate::catalog::write_rows(&rows, format)?, rows.len())
+    } else {
+        let rows = Series::select_all(conn)
+            .await?
+            .iter()
+            .map(SeriesRow::from)
+            .collect::<Vec<SeriesRow>>();
+
+        (crate::catalog::write_rows(&rows, format)?, rows.len())
+    };
+
+    let mut stream = Cursor::new(&bytes);
+    let file = client
+        .upload_stream(
+            &mut stream,
+            bytes.len(),
+            format!("{}.{}", kind, format.extension()),
+        )
+        .await?;
+
+    message
+        .reply(
+            InputMessage::html(
+                t("catalog_exported")
+                    .replace("{count}", &count.to_string())
+                    .replace("{type}", &i18n.get(*kind).to_lowercase()),
+            )
+            .file(file),
+        )
+        .await?;
+
+    Ok(())
+}
+
+// Called by `/import characters|series`, the file can be attached to the command or replied to
+pub async fn import_document(
+    client: &mut Client,
+    message: &Message,
+    kind: &str,
+    conn: &mut RBatis,
+    i18n: &I18n,
+) -> Result<()> {
+    let t = |key| i18n.get(key);
+
+    let mut media = message.media();
+    if media.is_none() {
+        if let Ok(Some(reply_message)) = message.get_reply().await {
+            media = reply_message.media();
+        }
+    }
+
+    let format = match media.as_ref() {
+        Some(Media::Document(document)) => Format::from_name(document.name()),
+        _ => None,
+    };
+
+    let (Some(media), Some(format)) = (media, format) else {
+        message
+            .reply(InputMessage::html(t("catalog_no_document")))
+            .await?;
+
+        return Ok(());
+    };
+
+    let bytes = crate::utils::download_tele_media(client, media).await?;
+
+    let result = if kind == "characters" {
+        import_characters(conn, &bytes, format).await?
+    } else {
+        import_series(conn, &bytes, format).await?
+    };
+
+    match result {
+        Ok((inserted, updated)) => {
+            message
+                .reply(InputMessage::html(
+                    t("catalog_imported")
+                        .replace("{inserted}", &inserted.to_string())
+                        .replace("{updated}", &updated.to_string()),
+                ))
+                .await?;
+        }
+        Err(mut errors) => {
+            errors.sort_by_key(|(row, _)| *row);
+
+            let mut text = errors
+                .iter()
+                .take(MAX_ERRORS)
+                .map(|(row, error)| {
+                    t("catalog_row_error")
+                        .replace("{row}", &row.to_string())
+                        .replace("{error}", &crate::utils::escape_html(error))
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+
+            if errors.len() > MAX_ERRORS {
+                text += "\n";
+                text += &t("catalog_more_errors")
+                    .replace("{count}", &(errors.len() - MAX_ERRORS).to_string());
+            }
+
+            message
+                .reply(InputMessage::html(
+                    t("catalog_invalid_rows").replace("{errors}", &text),
+                ))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Nothing is written unless every row is valid, returns the inserted and updated counts
+async fn import_characters(
+    conn: &mut RBatis,
+    bytes: &[u8],
+    format: Format,
+) -> Result<std::result::Result<(usize, usize), RowErrors>> {
+    let (rows, mut errors) = crate::catalog::parse_rows::<CharacterRow>(bytes, format);
+
+    let series_ids = Series::select_all(conn)
+        .await?
+        .into_iter()
+        .map(|series| series.id)
+        .collect::<HashSet<i64>>();
+    let mut existing = Character::select_all(conn)
+        .await?
+        .into_iter()
+        .map(|character| (character.id, character))
+        .collect::<HashMap<i64, Character>>();
+
+    let mut seen_ids = HashSet::new();
+    for (line, row) in rows.iter() {
+        if let Err(e) = row.validate() {
+            errors.push((*line, e));
+        } else if !series_ids.contains(&row.series_id) {
+            errors.push((*line, format!("series {} doesn't exist", row.series_id)));
+        } else if row.id.is_some_and(|id| !seen_ids.insert(id)) {
+            errors.push((*line, format!("id {} is repeated", row.id.unwrap())));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Ok(Err(errors));
+    }
+
+    // New rows get the IDs after the highest one, including the ones in the file
+    let mut next_id = existing
+        .keys()
+        .chain(seen_ids.iter())
+        .max()
+        .map_or(0, |id| *id);
+
+    let mut characters = Vec::new();
+    for (_, row) in rows.iter() {
+        let (mut character, is_new) = match row.id.and_then(|id| existing.remove(&id)) {
+            Some(character) => (character, false),
+            None => (
+                Character {
+                    id: row.id.unwrap_or_else(|| {
+                        next_id += 1;
+                        next_id
+                    }),
+                    ..Default::default()
+                },
+                true,
+            ),
+        };
+
+        // Images are downloaded before the transaction, only when the link changed
+        if let Some(link) = row.image_link.as_ref() {
+            if is_new || character.image_link != *link || character.image_hash.is_none() {
+                match crate::utils::download_photo(link)
+                    .await
+                    .and_then(|image| crate::images::normalize(&image))
+                {
+                    Ok(image) => {
+                        let image_hash = crate::images::store(conn, &image).await?;
+                        for similar in
+                            crate::images::similar_characters(conn, &image_hash, character.id)
+                                .await?
+                        {
+                            log::warn!(
+                                "the image of character {} looks like the one of {}",
+                                character.id,
+                                similar.id
+                            );
+                        }
+
+                        character.image_hash = Some(image_hash);
+                    }
+                    Err(e) => log::warn!("failed to download the image {}: {}", link, e),
+                }
+            }
+        }
+
+        row.apply(&mut character);
+        characters.push((character, is_new));
+    }
+
+    let tx = conn.acquire_begin().await?;
+    let counts = match save_characters(&tx, &characters).await {
+        Ok(counts) => counts,
+        Err(e) => {
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+    tx.commit().await?;
+
+    Ok(Ok(counts))
+}
+
+async fn save_characters(
+    executor: &dyn Executor,
+    characters: &[(Character, bool)],
+) -> rbatis::Result<(usize, usize)> {
+    let (mut inserted, mut updated) = (0, 0);
+
+    for (character, is_new) in characters.iter() {
+        if *is_new {
+            Character::insert(executor, character).await?;
+            inserted += 1;
+        } else {
+            Character::update_by_id(executor, character, character.id).await?;
+            updated += 1;
+        }
+    }
+
+    Ok((inserted, updated))
+}
+
+async fn import_series(
+    conn: &mut RBatis,
+    bytes: &[u8],
+    format: Format,
+) -> Result<std::result::Result<(usize, usize), RowErrors>> {
+    let (rows, mut errors) = crate::catalog::parse_rows::<SeriesRow>(bytes, format);
+
+    let mut existing = Series::select_all(conn)
+        .await?
+        .into_iter()
+        .map(|series| (series.id, series))
+        .collect::<HashMap<i64, Series>>();
+
+    let mut seen_ids = HashSet::new();
+    for (line, row) in rows.iter() {
+        if let Err(e) = row.validate() {
+            errors.push((*line, e));
+        } else if row.id.is_some_and(|id| !seen_ids.insert(id)) {
+            errors.push((*line, format!("id {} is repeated", row.id.unwrap())));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Ok(Err(errors));
+    }
+
+    let mut next_id = existing
+        .keys()
+        .chain(seen_ids.iter())
+        .max()
+        .map_or(0, |id| *id);
+
+    let mut series_list = Vec::new();
+    for (_, row) in rows.iter() {
+        let (mut series, is_new) = match row.id.and_then(|id| existing.remove(&id)) {
+            Some(series) => (series, false),
+            None => (
+                Series {
+                    id: row.id.unwrap_or_else(|| {
+                        next_id += 1;
+                        next_id
+                    }),
+                    ..Default::default()
+                },
+                true,
+            ),
+        };
+
+        if let Some(link) = row.image_link.as_ref() {
+            if is_new || series.image_link != *link || series.banner_hash.is_none() {
+                match crate::utils::download_photo(link)
+                    .await
+                    .and_then(|banner| crate::images::normalize(&banner))
+                {
+                    Ok(banner) => {
+                        series.banner_hash = Some(crate::images::store(conn, &banner).await?)
+                    }
+                    Err(e) => log::warn!("failed to download the banner {}: {}", link, e),
+                }
+            }
+        }
+
+        row.apply(&mut series);
+        series_list.push((series, is_new));
+    }
+
+    let tx = conn.acquire_begin().await?;
+    let counts = match save_series(&tx, &series_list).await {
+        Ok(counts) => counts,
+        Err(e) => {
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+    tx.commit().await?;
+
+    Ok(Ok(counts))
+}
+
+async fn save_series(
+    executor: &dyn Executor,
+    series_list: &[(Series, bool)],
+) -> rbatis::Result<(usize, usize)> {
+    let (mut inserted, mut updated) = (0, 0);
+
+    for (series, is_new) in series_list.iter() {
+        if *is_new {
+            Series::insert(executor, series).await?;
+            inserted += 1;
+        } else {
+            Series::update_by_id(executor, series, series.id).await?;
+            updated += 1;
+        }
+    }
+
+    Ok((inserted, updated))
+}
@@ -1,10 +1,12 @@
 use std::{io::Cursor, time::Duration};
 
-use grammers_client::{button, reply_markup, Client, InputMessage, Update};
+use grammers_client::{button, reply_markup, types::Message, Client, InputMessage, Update};
 use grammers_friendly::prelude::*;
 
 use crate::{
-    database::models::{Character, Gender, Series},
+    database::models::{
+        Character, CharacterSeries, CharacterTag, CharacterVariant, Gender, Series, Tag,
+    },
     modules::{Conversation, Database, I18n},
     Result,
 };
@@ -60,33 +62,136 @@ async fn add_character(client: &mut Client, update: &mut Update, data: &mut Data
         (sent, Some(response)) => {
             let conn = db.get_conn();
 
+            let name = response.text().trim().to_string();
+
+            // The series comes first, so the name can be compared with the characters in it
+            let series = match conv
+                .ask_message(
+                    chat.clone(),
+                    sender,
+                    InputMessage::html(
+                        t("ask_field")
+                            .replace("{field}", &t("series_id"))
+                            .replace("{timeout}", &timeout.to_string()),
+                    ),
+                    crate::filters::sudoers(),
+                    Duration::from_secs(timeout),
+                )
+                .await?
+            {
+                (sent, Some(response)) => {
+                    let series = match response.text().trim().parse::<i64>() {
+                        Ok(series_id) => Series::select_by_id(conn, series_id).await?,
+                        Err(_) => None,
+                    };
+
+                    if series.is_none() {
+                        sent.edit(InputMessage::html(t("invalid_id"))).await?;
+                        tokio::time::sleep(Duration::from_secs(2)).await;
+                    }
+                    sent.delete().await?;
+                    let _ = response.delete().await;
+
+                    series
+                }
+                (sent, None) => {
+                    sent.edit(InputMessage::html(
+                        t("operation_cancelled").replace("{reason}", &t("timeout")),
+                    ))
+                    .await?;
+                    tokio::time::sleep(Duration::from_secs(2)).await;
+                    sent.delete().await?;
+
+                    None
+                }
+            };
+            let Some(series) = series else {
+                sent.delete().await?;
+                let _ = response.delete().await;
+
+                return Ok(());
+            };
+
+            let candidates = Character::select_by_series(conn, series.id)
+                .await?
+                .into_iter()
+                .filter(|character| {
+                    std::iter::once(&character.name)
+                        .chain(character.aliases.iter())
+                        .any(|other| crate::search::is_similar_name(&name, other))
+                })
+                .map(|character| (character.id, character.name))
+                .collect::<Vec<_>>();
+
+            let duplicate =
+                super::merge::confirm_new(&conv, &i18n, &message, sender, &name, &candidates)
+                    .await?;
+
+            // The name prompt stays until the duplicates are settled, like in the series wizard
+            sent.delete().await?;
+            let _ = response.delete().await;
+
+            match duplicate {
+                super::merge::Duplicate::Create => {}
+                super::merge::Duplicate::Merge(character_id) => {
+                    if let Some(mut character) = Character::select_by_id(conn, character_id).await?
+                    {
+                        super::merge::add_alias(&character.name, &mut character.aliases, &name);
+                        Character::update_by_id(conn, &character, character.id).await?;
+
+                        message
+                            .edit(
+                                InputMessage::html(
+                                    t("duplicate_merged")
+                                        .replace("{name}", &crate::utils::escape_html(&name))
+                                        .replace(
+                                            "{target}",
+                                            &crate::utils::escape_html(&character.name),
+                                        ),
+                                )
+                                .reply_markup(
+                                    &reply_markup::inline(vec![vec![button::inline(
+                                        t("continue_button"),
+                                        format!("char edit {}", character.id),
+                                    )]]),
+                                ),
+                            )
+                            .await?;
+                    }
+
+                    return Ok(());
+                }
+                super::merge::Duplicate::Cancel => return Ok(()),
+            }
+
             let last_id = Character::select_last(conn)
                 .await?
                 .map_or(0, |character| character.id);
 
-            let name = response.text();
             let mut character = Character {
                 id: last_id + 1,
-                name: name.trim().to_string(),
+                name,
                 stars: 1,
                 artist: "Artist".to_string(),
                 image_link: ".".to_string(),
+                series_id: series.id,
                 ..Default::default()
             };
             Character::insert(conn, &character).await?;
 
-            sent.edit(InputMessage::html(
-                t("object_created").replace("{object}", &t("character")),
-            ))
-            .await?;
+            let sent = message
+                .respond(InputMessage::html(
+                    t("object_created").replace("{object}", &t("character")),
+                ))
+                .await?;
 
             tokio::time::sleep(Duration::from_secs(2)).await;
             sent.delete().await?;
-            let _ = response.delete().await;
 
             message
                 .edit(InputMessage::html(crate::utils::construct_character_info(
-                    &character, None,
+                    &character,
+                    Some(series),
                 )))
                 .await?;
 
@@ -110,9 +215,18 @@ async fn add_character(client: &mut Client, update: &mut Update, data: &mut Data
             {
                 (sent, Some(response)) => {
                     let photo = response.photo().unwrap();
-                    let bytes = crate::utils::download_tele_photo(client, photo).await?;
-
-                    character.image = Some(bytes.clone());
+                    let bytes = crate::images::normalize(
+                        &crate::utils::download_tele_photo(client, photo).await?,
+                    )?;
+
+                    let image_hash = crate::images::store(conn, &bytes).await?;
+                    warn_similar_images(
+                        &i18n,
+                        &message,
+                        &crate::images::similar_characters(conn, &image_hash, character.id).await?,
+                    )
+                    .await?;
+                    character.image_hash = Some(image_hash);
                     match Character::update_by_id(conn, &character, character.id).await {
                         Ok(_) => {
                             sent.edit(InputMessage::html(
@@ -207,6 +321,8 @@ async fn delete_character(
         if splitted.len() == 4 && splitted[3].as_str() == "confirm" {
             if let Some(character) = Character::select_by_id(conn, character_id).await? {
                 Character::delete_by_id(conn, character_id).await?;
+                CharacterTag::delete_by_character(conn, character_id).await?;
+                CharacterVariant::delete_by_character(conn, character_id).await?;
                 message
                     .edit(InputMessage::html(
                         t("object_deleted")
@@ -863,10 +979,23 @@ async fn edit_character(client: &mut Client, update: &mut Update, data: &mut Dat
                         {
                             (sent, Some(response)) => {
                                 let photo = response.photo().unwrap();
-                                let bytes =
-                                    crate::utils::download_tele_photo(client, photo).await?;
-
-                                character.image = Some(bytes.clone());
+                                let bytes = crate::images::normalize(
+                                    &crate::utils::download_tele_photo(client, photo).await?,
+                                )?;
+
+                                let image_hash = crate::images::store(conn, &bytes).await?;
+                                warn_similar_images(
+                                    &i18n,
+                                    &message,
+                                    &crate::images::similar_characters(
+                                        conn,
+                                        &image_hash,
+                                        character_id,
+                                    )
+                                    .await?,
+                                )
+                                .await?;
+                                character.image_hash = Some(image_hash);
 
                                 match Character::update_by_id(conn, &character, character_id).await
                                 {
@@ -1009,16 +1138,25 @@ async fn edit_character(client: &mut Client, update: &mut Update, data: &mut Dat
                         }
                     }
                     "series" => {
-                        let field = t("series_id");
+                        let field = t("series");
                         let timeout = 15;
 
+                        let current = CharacterSeries::select_by_character(conn, character_id)
+                            .await?
+                            .into_iter()
+                            .map(|character_series| character_series.series_id)
+                            .filter(|series_id| *series_id != character.series_id)
+                            .fold(character.series_id.to_string(), |ids, series_id| {
+                                ids + " " + &series_id.to_string()
+                            });
+
                         match conv
                             .ask_message(
                                 chat,
                                 sender,
                                 InputMessage::html(
-                                    t("ask_field")
-                                        .replace("{field}", &field)
+                                    t("ask_series")
+                                        .replace("{series}", &current)
                                         .replace("{timeout}", &timeout.to_string()),
                                 ),
                                 crate::filters::sudoers(),
@@ -1028,38 +1166,134 @@ async fn edit_character(client: &mut Client, update: &mut Update, data: &mut Dat
                             .unwrap()
                         {
                             (sent, Some(response)) => {
-                                let new_series_id = response.text().trim();
-                                if let Ok(new_series_id) = new_series_id.parse::<i64>() {
-                                    if Series::select_by_id(conn, new_series_id).await?.is_some() {
-                                        character.series_id = new_series_id;
-
-                                        match Character::update_by_id(
-                                            conn,
-                                            &character,
+                                let mut series_ids = Vec::new();
+                                let mut is_valid = true;
+                                for part in response.text().split_whitespace() {
+                                    let series = match part.parse::<i64>() {
+                                        Ok(series_id) => {
+                                            Series::select_by_id(conn, series_id).await?
+                                        }
+                                        Err(_) => None,
+                                    };
+
+                                    match series {
+                                        Some(series) if !series_ids.contains(&series.id) => {
+                                            series_ids.push(series.id)
+                                        }
+                                        Some(_) => {}
+                                        None => {
+                                            is_valid = false;
+                                            break;
+                                        }
+                                    }
+                                }
+
+                                if !is_valid || series_ids.is_empty() {
+                                    sent.edit(InputMessage::html(t("invalid_id"))).await?;
+                                } else {
+                                    // The first series is the one the character is displayed with
+                                    character.series_id = series_ids[0];
+                                    Character::update_by_id(conn, &character, character_id).await?;
+
+                                    CharacterSeries::delete_by_character(conn, character_id)
+                                        .await?;
+                                    for series_id in series_ids.iter() {
+                                        let character_series = CharacterSeries {
                                             character_id,
-                                        )
-                                        .await
-                                        {
-                                            Ok(_) => {
-                                                sent.edit(InputMessage::html(
-                                                    t("field_updated")
-                                                        .replace("{field}", &field.to_lowercase()),
-                                                ))
-                                                .await?;
-                                            }
-                                            Err(_) => {
-                                                sent.edit(InputMessage::html(
-                                                    t("error_occurred")
-                                                        .replace("{field}", &field.to_lowercase()),
-                                                ))
-                                                .await?;
+                                            series_id: *series_id,
+                                        };
+                                        CharacterSeries::insert(conn, &character_series).await?;
+                                    }
+
+                                    sent.edit(InputMessage::html(
+                                        t("field_updated")
+                                            .replace("{field}", &field.to_lowercase()),
+                                    ))
+                                    .await?;
+                                }
+
+                                tokio::time::sleep(Duration::from_secs(2)).await;
+                                sent.delete().await?;
+                                let _ = response.delete().await;
+                            }
+                            (sent, None) => {
+                                sent.edit(InputMessage::html(
+                                    t("operation_cancelled").replace("{reason}", &t("timeout")),
+                                ))
+                                .await?;
+
+                                tokio::time::sleep(Duration::from_secs(2)).await;
+                                sent.delete().await?;
+
+                                return Ok(());
+                            }
+                        }
+                    }
+                    "variants" => return super::variant::edit_variants(client, update, data).await,
+                    "tags" => {
+                        let field = t("tags");
+                        let timeout = 30;
+
+                        let current = Tag::select_by_character(conn, character_id)
+                            .await?
+                            .into_iter()
+                            .map(|tag| tag.name)
+                            .collect::<Vec<String>>();
+
+                        match conv
+                            .ask_message(
+                                chat,
+                                sender,
+                                InputMessage::html(
+                                    t("ask_tags")
+                                        .replace("{tags}", &current.join(" "))
+                                        .replace("{timeout}", &timeout.to_string()),
+                                ),
+                                crate::filters::sudoers(),
+                                Duration::from_secs(timeout),
+                            )
+                            .await
+                            .unwrap()
+                        {
+                            (sent, Some(response)) => {
+                                let text = response.text().trim().to_lowercase();
+
+                                // `0` removes every tag
+                                let mut tags = Vec::new();
+                                let mut unknown = None;
+                                if text != "0" {
+                                    for name in text.split_whitespace() {
+                                        match Tag::select_by_name(conn, name).await? {
+                                            Some(tag) => tags.push(tag),
+                                            None => {
+                                                unknown = Some(name.to_string());
+                                                break;
                                             }
                                         }
-                                    } else {
-                                        sent.edit(InputMessage::html(t("invalid_id"))).await?;
                                     }
+                                }
+
+                                if let Some(name) = unknown {
+                                    sent.edit(InputMessage::html(
+                                        t("unknown_tag")
+                                            .replace("{tag}", &crate::utils::escape_html(name)),
+                                    ))
+                                    .await?;
                                 } else {
-                                    sent.edit(InputMessage::html(t("not_a_id"))).await?;
+                                    CharacterTag::delete_by_character(conn, character_id).await?;
+                                    for tag in tags.iter() {
+                                        let character_tag = CharacterTag {
+                                            character_id,
+                                            tag_id: tag.id,
+                                        };
+                                        CharacterTag::insert(conn, &character_tag).await?;
+                                    }
+
+                                    sent.edit(InputMessage::html(
+                                        t("field_updated")
+                                            .replace("{field}", &field.to_lowercase()),
+                                    ))
+                                    .await?;
                                 }
 
                                 tokio::time::sleep(Duration::from_secs(2)).await;
@@ -1084,7 +1318,8 @@ async fn edit_character(client: &mut Client, update: &mut Update, data: &mut Dat
             }
 
             let fields = [
-                "name", "artist", "aliases", "photo", "gender", "stars", "series",
+                "name", "artist", "aliases", "photo", "gender", "stars", "series", "tags",
+                "variants",
             ];
             let buttons = fields
                 .into_iter()
@@ -1184,3 +1419,34 @@ async fn list_characters(client: &mut Client, update: &mut Update, data: &mut Da
 
     Ok(())
 }
+
+// Look-alike images usually mean the same character was added twice
+pub async fn warn_similar_images(
+    i18n: &I18n,
+    message: &Message,
+    characters: &[Character],
+) -> Result<()> {
+    if characters.is_empty() {
+        return Ok(());
+    }
+
+    let names = characters
+        .iter()
+        .map(|character| {
+            format!(
+                "<code>{}</code>. {}",
+                character.id,
+                crate::utils::escape_html(&character.name)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    message
+        .respond(InputMessage::html(
+            i18n.get("similar_images").replace("{characters}", &names),
+        ))
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use std::time::Duration;
+
+use grammers_client::{button, reply_markup, types::Chat, Client, InputMessage, Update};
+use grammers_friendly::prelude::*;
+
+use crate::{
+    database::models::Event,
+    modules::{Conversation, Database, I18n},
+    Result,
+};
+
+pub fn router() -> Router {
+    Router::default()
+        .add_handler(Handler::callback_query(
+            add_event,
+            filters::query("event add").and(crate::filters::sudoers()),
+        ))
+        .add_handler(Handler::callback_query(
+            delete_event,
+            filters::query("event delete id:int").and(crate::filters::sudoers()),
+        ))
+}
+
+async fn add_event(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+    let conv = data.get_module::<Conversation>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let chat = update.get_chat().unwrap();
+    let query = update.get_query().unwrap();
+    let sender = query.sender();
+    let message = query.load_message().await?;
+
+    let Some(name) = ask_field(&conv, &i18n, &chat, sender, &t("name")).await? else {
+        return Ok(());
+    };
+    let Some(starts_at) = ask_field(&conv, &i18n, &chat, sender, &t("event_starts_at")).await?
+    else {
+        return Ok(());
+    };
+    let Some(ends_at) = ask_field(&conv, &i18n, &chat, sender, &t("event_ends_at")).await? else {
+        return Ok(());
+    };
+
+    let (Some(starts_at), Some(ends_at)) = (
+        crate::utils::parse_timestamp(&starts_at),
+        crate::utils::parse_timestamp(&ends_at),
+    ) else {
+        message.edit(InputMessage::html(t("invalid_date"))).await?;
+
+        return Ok(());
+    };
+    if ends_at <= starts_at {
+        message.edit(InputMessage::html(t("invalid_date"))).await?;
+
+        return Ok(());
+    }
+
+    let Some(boost) = ask_field(&conv, &i18n, &chat, sender, &t("event_boost")).await? else {
+        return Ok(());
+    };
+    let Some(boosted_series) =
+        ask_field(&conv, &i18n, &chat, sender, &t("event_boosted_series")).await?
+    else {
+        return Ok(());
+    };
+    let Some(boosted_characters) = ask_field(
+        &conv,
+        &i18n,
+        &chat,
+        sender,
+        &t("event_boosted_characters_field"),
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+    let Some(exclusive_characters) = ask_field(
+        &conv,
+        &i18n,
+        &chat,
+        sender,
+        &t("event_exclusive_characters_field"),
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+    let Some(spawn_text) = ask_field(&conv, &i18n, &chat, sender, &t("event_spawn_text")).await?
+    else {
+        return Ok(());
+    };
+
+    let conn = db.get_conn();
+
+    let last_id = Event::select_last(conn).await?.map_or(0, |event| event.id);
+
+    let event = Event {
+        id: last_id + 1,
+        name,
+        boost: boost.parse::<u8>().unwrap_or(50).min(100),
+        ends_at,
+        starts_at,
+        // "." keeps the default spawn text
+        spawn_text: if spawn_text == "." {
+            String::new()
+        } else {
+            spawn_text
+        },
+        boosted_series: crate::utils::parse_ids(&boosted_series),
+        boosted_characters: crate::utils::parse_ids(&boosted_characters),
+        exclusive_characters: crate::utils::parse_ids(&exclusive_characters),
+    };
+    Event::insert(conn, &event).await?;
+
+    message
+        .edit(InputMessage::html(
+            t("object_created").replace("{object}", &t("event")),
+        ))
+        .await?;
+
+    Ok(())
+}
+
+async fn delete_event(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let query = update.get_query().unwrap();
+    let message = query.load_message().await?;
+
+    let splitted = utils::split_query(query.data());
+
+    if splitted.len() >= 3 {
+        let conn = db.get_conn();
+
+        let event_id = splitted[2].parse::<i64>().unwrap();
+
+        if splitted.len() == 4 && splitted[3].as_str() == "confirm" {
+            if Event::select_by_id(conn, event_id).await?.is_some() {
+                Event::delete_by_id(conn, event_id).await?;
+                message
+                    .edit(InputMessage::html(
+                        t("object_deleted")
+                            .replace("{object}", &t("event"))
+                            .replace("{id}", &event_id.to_string()),
+                    ))
+                    .await?;
+            } else {
+                message.edit(InputMessage::html(t("unknown_event"))).await?;
+            }
+
+            return Ok(());
+        }
+
+        message
+            .edit(
+                InputMessage::html(
+                    t("confirm_delete")
+                        .replace("{object}", &t("event").to_lowercase())
+                        .replace("{id}", &event_id.to_string()),
+                )
+                .reply_markup(&reply_markup::inline(vec![vec![button::inline(
+                    t("confirm_button"),
+                    format!("event delete {} confirm", event_id),
+                )]])),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn ask_field(
+    conv: &Conversation,
+    i18n: &I18n,
+    chat: &Chat,
+    sender: &Chat,
+    field: &str,
+) -> Result<Option<String>> {
+    let t = |key| i18n.get(key);
+    let timeout = 30;
+
+    match conv
+        .ask_message(
+            chat.clone(),
+            sender,
+            InputMessage::html(
+                t("ask_field")
+                    .replace("{field}", field)
+                    .replace("{timeout}", &timeout.to_string()),
+            ),
+            crate::filters::sudoers(),
+            Duration::from_secs(timeout),
+        )
+        .await?
+    {
+        (sent, Some(response)) => {
+            sent.delete().await?;
+            let _ = response.delete().await;
+
+            Ok(Some(response.text().trim().to_string()))
+        }
+        (sent, None) => {
+            sent.edit(InputMessage::html(
+                t("operation_cancelled").replace("{reason}", &t("timeout")),
+            ))
+            .await?;
+
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            sent.delete().await?;
+
+            Ok(None)
+        }
+    }
+}
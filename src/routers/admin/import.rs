@@ -0,0 +1,227 @@
+use std::time::Duration;
+
+use grammers_client::{button, reply_markup, Client, InputMessage, Update};
+use grammers_friendly::prelude::*;
+use rbatis::{executor::Executor, RBatis};
+
+use crate::{
+    anilist::AniMedia,
+    database::models::{Character, Series},
+    modules::{Conversation, Database, I18n},
+    Config, Result,
+};
+
+pub fn router() -> Router {
+    Router::default().add_handler(Handler::new_message(
+        import,
+        macros::command!("/!.", "import").and(crate::filters::sudoers()),
+    ))
+}
+
+async fn import(client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+    let conv = data.get_module::<Conversation>().unwrap();
+    let config = data.get_module::<Config>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let sender = update.get_sender().unwrap();
+    let message = update.get_message().unwrap();
+
+    let splitted = message.text().split_whitespace().collect::<Vec<&str>>();
+
+    if let Some(kind @ (&"characters" | &"series")) = splitted.get(1) {
+        return super::catalog::import_document(client, &message, kind, db.get_conn(), &i18n).await;
+    }
+
+    let (Some(&"anilist"), Some(media_id)) = (
+        splitted.get(1),
+        splitted.get(2).and_then(|text| text.parse::<i64>().ok()),
+    ) else {
+        message
+            .reply(InputMessage::html(t("invalid_command").replace(
+                "{cmd}",
+                &crate::utils::escape_html(format!(
+                    "{} anilist <media id> | characters|series <file>",
+                    splitted[0]
+                )),
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    let sent = message
+        .reply(InputMessage::html(t("anilist_fetching")))
+        .await?;
+
+    let mut media = match crate::anilist::fetch_media(&config.anilist.url, media_id).await {
+        Ok(media) => media,
+        Err(e) => {
+            log::error!("failed to fetch anilist media {}: {}", media_id, e);
+            sent.edit(InputMessage::html(t("anilist_not_found")))
+                .await?;
+
+            return Ok(());
+        }
+    };
+
+    let conn = db.get_conn();
+
+    // Characters imported before are left untouched
+    let total = media.characters.len();
+    let mut new_characters = Vec::new();
+    for character in media.characters.into_iter() {
+        if Character::select_by_anilist_id(conn, character.id)
+            .await?
+            .is_none()
+        {
+            new_characters.push(character);
+        }
+    }
+    media.characters = new_characters;
+
+    let names = media
+        .characters
+        .iter()
+        .take(20)
+        .map(|character| crate::utils::escape_html(&character.name))
+        .collect::<Vec<String>>()
+        .join(", ");
+    let timeout = 30;
+
+    sent.edit(
+        InputMessage::html(
+            t("anilist_preview")
+                .replace("{title}", &crate::utils::escape_html(&media.title))
+                .replace("{media}", &media.media_type.to_string())
+                .replace(
+                    "{aliases}",
+                    &crate::utils::escape_html(media.aliases.join(", ")),
+                )
+                .replace("{characters}", &media.characters.len().to_string())
+                .replace("{existing}", &(total - media.characters.len()).to_string())
+                .replace("{names}", &names)
+                .replace("{timeout}", &timeout.to_string()),
+        )
+        .reply_markup(&reply_markup::inline(vec![vec![
+            button::inline(t("yes_button"), "yes"),
+            button::inline(t("no_button"), "no"),
+        ]])),
+    )
+    .await?;
+
+    let confirmed = match conv
+        .wait_for_update(
+            &sender,
+            filters::query("[yes|no]"),
+            Duration::from_secs(timeout),
+        )
+        .await
+        .unwrap()
+    {
+        Some(update) => update
+            .get_query()
+            .is_some_and(|query| utils::split_query(query.data())[0] == "yes"),
+        None => false,
+    };
+
+    if !confirmed {
+        sent.edit(InputMessage::html(t("anilist_import_cancelled")))
+            .await?;
+
+        return Ok(());
+    }
+
+    sent.edit(InputMessage::html(t("anilist_importing")))
+        .await?;
+
+    // Images are stored before writing anything, the ones that fail are fetched again
+    // from AniList when the character is first sent
+    let banner = store_image(conn, media.banner_url.as_deref()).await?;
+    let mut images = Vec::new();
+    for character in media.characters.iter() {
+        images.push(store_image(conn, character.image_url.as_deref()).await?);
+    }
+
+    let tx = conn.acquire_begin().await?;
+    let series_id = match save_media(&tx, &media, banner, images.clone()).await {
+        Ok(series_id) => series_id,
+        Err(e) => {
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+    tx.commit().await?;
+
+    sent.edit(InputMessage::html(
+        t("anilist_imported")
+            .replace("{title}", &crate::utils::escape_html(&media.title))
+            .replace("{id}", &series_id.to_string())
+            .replace("{characters}", &media.characters.len().to_string()),
+    ))
+    .await?;
+
+    // Only characters of other series are reported, the imported ones are new
+    let mut similar = Vec::new();
+    for image_hash in images.iter().flatten() {
+        for character in crate::images::similar_characters(conn, image_hash, 0).await? {
+            if character.series_id != series_id
+                && !similar
+                    .iter()
+                    .any(|other: &Character| other.id == character.id)
+            {
+                similar.push(character);
+            }
+        }
+    }
+    super::character::warn_similar_images(&i18n, &sent, &similar).await?;
+
+    Ok(())
+}
+
+// Returns the hash of the stored image, or `None` when it couldn't be downloaded or decoded
+async fn store_image(conn: &mut RBatis, url: Option<&str>) -> Result<Option<String>> {
+    let Some(url) = url else {
+        return Ok(None);
+    };
+
+    match crate::utils::download_photo(url)
+        .await
+        .and_then(|bytes| crate::images::normalize(&bytes))
+    {
+        Ok(bytes) => Ok(Some(crate::images::store(conn, &bytes).await?)),
+        Err(_) => Ok(None),
+    }
+}
+
+async fn save_media(
+    executor: &dyn Executor,
+    media: &AniMedia,
+    banner: Option<String>,
+    images: Vec<Option<String>>,
+) -> rbatis::Result<i64> {
+    let series_id = Series::select_last(executor)
+        .await?
+        .map_or(0, |series| series.id)
+        + 1;
+
+    let mut series = media.to_series(series_id);
+    series.banner_hash = banner;
+    Series::insert(executor, &series).await?;
+
+    let mut character_id = Character::select_last(executor)
+        .await?
+        .map_or(0, |character| character.id);
+
+    for (ani_character, image) in media.characters.iter().zip(images) {
+        character_id += 1;
+
+        let mut character = ani_character.to_character(character_id, series_id);
+        character.image_hash = image;
+        Character::insert(executor, &character).await?;
+    }
+
+    Ok(series_id)
+}
@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use grammers_client::{
+    button, reply_markup,
+    types::{Chat, Message},
+    Client, InputMessage, Update,
+};
+use grammers_friendly::prelude::*;
+use rbatis::executor::Executor;
+
+use crate::{
+    database::models::{Character, UserCharacters},
+    modules::{Conversation, Database, I18n},
+    Result,
+};
+
+const MAX_CANDIDATES: usize = 5;
+
+pub fn router() -> Router {
+    Router::default().add_handler(Handler::new_message(
+        merge_characters,
+        macros::command!("/!.", "merge").and(crate::filters::sudoers()),
+    ))
+}
+
+// What the admin chose to do with a new entry that looks like existing ones
+pub enum Duplicate {
+    Create,
+    Merge(i64),
+    Cancel,
+}
+
+// Shows the entries the new one may repeat and waits for the admin to merge it into one of
+// them, to create it anyway or to cancel; without candidates there's nothing to ask
+pub async fn confirm_new(
+    conv: &Conversation,
+    i18n: &I18n,
+    message: &Message,
+    sender: &Chat,
+    name: &str,
+    candidates: &[(i64, String)],
+) -> Result<Duplicate> {
+    if candidates.is_empty() {
+        return Ok(Duplicate::Create);
+    }
+
+    let t = |key| i18n.get(key);
+    let timeout = 30;
+
+    let mut buttons = candidates
+        .iter()
+        .take(MAX_CANDIDATES)
+        .map(|(id, name)| {
+            vec![button::inline(
+                format!("🔀 {0}. {1}", id, crate::utils::shorten_text(name, 24)),
+                format!("merge {}", id),
+            )]
+        })
+        .collect::<Vec<_>>();
+    buttons.push(vec![
+        button::inline(t("add_button"), "yes"),
+        button::inline(t("cancel_button"), "no"),
+    ]);
+
+    message
+        .edit(
+            InputMessage::html(
+                t("duplicate_candidates")
+                    .replace("{name}", &crate::utils::escape_html(name))
+                    .replace("{timeout}", &timeout.to_string()),
+            )
+            .reply_markup(&reply_markup::inline(buttons)),
+        )
+        .await?;
+
+    let Some(update) = conv
+        .wait_for_update(
+            sender,
+            filters::query("[yes|no|merge]"),
+            Duration::from_secs(timeout),
+        )
+        .await?
+    else {
+        message
+            .edit(InputMessage::html(
+                t("operation_cancelled").replace("{reason}", &t("timeout")),
+            ))
+            .await?;
+
+        return Ok(Duplicate::Cancel);
+    };
+
+    let splitted = update
+        .get_query()
+        .map(|query| utils::split_query(query.data()))
+        .unwrap_or_default();
+
+    match splitted.first().map(|action| action.as_str()) {
+        Some("yes") => Ok(Duplicate::Create),
+        Some("merge") => match splitted.get(1).and_then(|id| id.parse::<i64>().ok()) {
+            Some(id) => Ok(Duplicate::Merge(id)),
+            None => Ok(Duplicate::Cancel),
+        },
+        _ => {
+            message
+                .edit(InputMessage::html(t("duplicate_cancelled")))
+                .await?;
+
+            Ok(Duplicate::Cancel)
+        }
+    }
+}
+
+// Adds the name as an alias, unless the entry already goes by it
+pub fn add_alias(name: &str, aliases: &mut Vec<String>, alias: &str) {
+    let alias = alias.trim();
+
+    if !alias.is_empty()
+        && !name.eq_ignore_ascii_case(alias)
+        && !aliases
+            .iter()
+            .any(|other| other.eq_ignore_ascii_case(alias))
+    {
+        aliases.push(alias.to_string());
+    }
+}
+
+async fn merge_characters(
+    _client: &mut Client,
+    update: &mut Update,
+    data: &mut Data,
+) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+    let conn = db.get_conn();
+
+    let message = update.get_message().unwrap();
+
+    let splitted = message.text().split_whitespace().collect::<Vec<&str>>();
+
+    let (Some(keep_id), Some(drop_id)) = (
+        splitted.get(1).and_then(|id| id.parse::<i64>().ok()),
+        splitted.get(2).and_then(|id| id.parse::<i64>().ok()),
+    ) else {
+        message
+            .reply(InputMessage::html(t("invalid_command").replace(
+                "{cmd}",
+                &crate::utils::escape_html(format!("{} <keep id> <drop id>", splitted[0])),
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    if keep_id == drop_id {
+        message.reply(InputMessage::html(t("merge_same"))).await?;
+
+        return Ok(());
+    }
+
+    let (Some(mut kept), Some(dropped)) = (
+        Character::select_by_id(conn, keep_id).await?,
+        Character::select_by_id(conn, drop_id).await?,
+    ) else {
+        message
+            .reply(InputMessage::html(t("unknown_character")))
+            .await?;
+
+        return Ok(());
+    };
+
+    // The dropped character stays findable by its names
+    for alias in std::iter::once(&dropped.name).chain(dropped.aliases.iter()) {
+        add_alias(&kept.name, &mut kept.aliases, alias);
+    }
+
+    let tx = conn.acquire_begin().await?;
+    if let Err(e) = merge_into(&tx, &kept, drop_id).await {
+        tx.rollback().await?;
+        return Err(e.into());
+    }
+    tx.commit().await?;
+
+    message
+        .reply(InputMessage::html(
+            t("characters_merged")
+                .replace("{dropped}", &crate::utils::escape_html(&dropped.name))
+                .replace("{drop_id}", &drop_id.to_string())
+                .replace("{kept}", &crate::utils::escape_html(&kept.name))
+                .replace("{keep_id}", &keep_id.to_string()),
+        ))
+        .await?;
+
+    Ok(())
+}
+
+// Everything pointing to the dropped character is moved to the kept one before deleting it
+async fn merge_into(executor: &dyn Executor, kept: &Character, drop_id: i64) -> rbatis::Result<()> {
+    Character::merge_references(executor, kept.id, drop_id).await?;
+
+    // The legacy table may not exist anymore
+    if let Ok(rows) = UserCharacters::select_all(executor).await {
+        for mut user_characters in rows {
+            if replace_id(&mut user_characters.characters_id, drop_id, kept.id) {
+                UserCharacters::update_by_id(
+                    executor,
+                    &user_characters,
+                    user_characters.user_id,
+                    user_characters.group_id,
+                )
+                .await?;
+            }
+        }
+    }
+
+    Character::update_by_id(executor, kept, kept.id).await?;
+    Character::delete_by_id(executor, drop_id).await?;
+
+    Ok(())
+}
+
+// Returns whether the list had the old ID
+fn replace_id(ids: &mut Vec<i64>, old_id: i64, new_id: i64) -> bool {
+    if !ids.contains(&old_id) {
+        return false;
+    }
+
+    ids.retain(|id| *id != old_id);
+    if !ids.contains(&new_id) {
+        ids.push(new_id);
+    }
+
+    true
+}
@@ -1,10 +1,23 @@
+mod catalog;
 mod character;
+mod event;
+mod import;
+mod merge;
 mod series;
+mod sync;
+mod tag;
+mod variant;
 
 use grammers_friendly::Router;
 
 pub fn router() -> Router {
     Router::default()
+        .add_sub_router(catalog::router())
         .add_sub_router(character::router())
+        .add_sub_router(event::router())
+        .add_sub_router(import::router())
+        .add_sub_router(merge::router())
         .add_sub_router(series::router())
+        .add_sub_router(sync::router())
+        .add_sub_router(tag::router())
 }
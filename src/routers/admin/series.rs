@@ -5,7 +5,7 @@ use grammers_client::{button, reply_markup, Client, InputMessage, Update};
 use grammers_friendly::prelude::*;
 
 use crate::{
-    database::models::{Character, Media, Series},
+    database::models::{Character, CharacterSeries, Media, Series},
     modules::{Conversation, Database, I18n},
     Result,
 };
@@ -61,12 +61,66 @@ async fn add_series(client: &mut Client, update: &mut Update, data: &mut Data) -
         (sent, Some(response)) => {
             let conn = db.get_conn();
 
+            let title = response.text().trim().to_string();
+
+            let candidates = Series::select_all(conn)
+                .await?
+                .into_iter()
+                .filter(|series| {
+                    std::iter::once(&series.title)
+                        .chain(series.aliases.iter())
+                        .any(|other| crate::search::is_similar_name(&title, other))
+                })
+                .map(|series| (series.id, series.title))
+                .collect::<Vec<_>>();
+
+            match super::merge::confirm_new(&conv, &i18n, &message, sender, &title, &candidates)
+                .await?
+            {
+                super::merge::Duplicate::Create => {}
+                super::merge::Duplicate::Merge(series_id) => {
+                    sent.delete().await?;
+                    let _ = response.delete().await;
+
+                    if let Some(mut series) = Series::select_by_id(conn, series_id).await? {
+                        super::merge::add_alias(&series.title, &mut series.aliases, &title);
+                        Series::update_by_id(conn, &series, series.id).await?;
+
+                        message
+                            .edit(
+                                InputMessage::html(
+                                    t("duplicate_merged")
+                                        .replace("{name}", &crate::utils::escape_html(&title))
+                                        .replace(
+                                            "{target}",
+                                            &crate::utils::escape_html(&series.title),
+                                        ),
+                                )
+                                .reply_markup(
+                                    &reply_markup::inline(vec![vec![button::inline(
+                                        t("continue_button"),
+                                        format!("series edit {}", series.id),
+                                    )]]),
+                                ),
+                            )
+                            .await?;
+                    }
+
+                    return Ok(());
+                }
+                super::merge::Duplicate::Cancel => {
+                    sent.delete().await?;
+                    let _ = response.delete().await;
+
+                    return Ok(());
+                }
+            }
+
             let last_id = Series::select_last(conn).await?.map_or(0, |serie| serie.id);
 
-            let title = response.text();
             let mut series = Series {
                 id: last_id + 1,
-                title: title.to_string(),
+                title,
                 ..Default::default()
             };
             Series::insert(conn, &series).await?;
@@ -110,9 +164,11 @@ async fn add_series(client: &mut Client, update: &mut Update, data: &mut Data) -
             {
                 (sent, Some(response)) => {
                     let photo = response.photo().unwrap();
-                    let bytes = crate::utils::download_tele_photo(client, photo).await?;
+                    let bytes = crate::images::normalize(
+                        &crate::utils::download_tele_photo(client, photo).await?,
+                    )?;
 
-                    series.banner = Some(bytes.clone());
+                    series.banner_hash = Some(crate::images::store(conn, &bytes).await?);
                     match Series::update_by_id(conn, &series, series.id).await {
                         Ok(_) => {
                             sent.edit(InputMessage::html(
@@ -839,10 +895,12 @@ async fn edit_series(client: &mut Client, update: &mut Update, data: &mut Data)
                         {
                             (sent, Some(response)) => {
                                 let photo = response.photo().unwrap();
-                                let bytes =
-                                    crate::utils::download_tele_photo(client, photo).await?;
+                                let bytes = crate::images::normalize(
+                                    &crate::utils::download_tele_photo(client, photo).await?,
+                                )?;
 
-                                series.banner = Some(bytes.clone());
+                                series.banner_hash =
+                                    Some(crate::images::store(conn, &bytes).await?);
 
                                 match Series::update_by_id(conn, &series, series_id).await {
                                     Ok(_) => {
@@ -1017,14 +1075,42 @@ async fn edit_series(client: &mut Client, update: &mut Update, data: &mut Data)
                                                     Character::select_by_id(conn, *character_id)
                                                         .await?
                                                 {
-                                                    if character.series_id != series_id {
-                                                        character.series_id = series_id;
-                                                        Character::update_by_id(
+                                                    if CharacterSeries::select_by_id(
+                                                        conn,
+                                                        *character_id,
+                                                        series_id,
+                                                    )
+                                                    .await?
+                                                    .is_none()
+                                                    {
+                                                        // Characters without a series are displayed
+                                                        // with this one
+                                                        if Series::select_by_id(
                                                             conn,
-                                                            &character,
-                                                            *character_id,
+                                                            character.series_id,
                                                         )
-                                                        .await?;
+                                                        .await?
+                                                        .is_none()
+                                                        {
+                                                            character.series_id = series_id;
+                                                            Character::update_by_id(
+                                                                conn,
+                                                                &character,
+                                                                *character_id,
+                                                            )
+                                                            .await?;
+                                                        } else {
+                                                            let character_series =
+                                                                CharacterSeries {
+                                                                    character_id: *character_id,
+                                                                    series_id,
+                                                                };
+                                                            CharacterSeries::insert(
+                                                                conn,
+                                                                &character_series,
+                                                            )
+                                                            .await?;
+                                                        }
 
                                                         characters_name.push(character.name);
                                                     }
@@ -1126,14 +1212,34 @@ async fn edit_series(client: &mut Client, update: &mut Update, data: &mut Data)
                                                             characters.remove(index);
                                                         }
 
-                                                        character.series_id = 0;
-                                                        Character::update_by_id(
+                                                        CharacterSeries::delete_by_id(
                                                             conn,
-                                                            &character,
                                                             character_id,
+                                                            series_id,
                                                         )
                                                         .await?;
 
+                                                        // Another of its series is displayed
+                                                        // instead
+                                                        if character.series_id == series_id {
+                                                            character.series_id =
+                                                                CharacterSeries::select_by_character(
+                                                                    conn,
+                                                                    character_id,
+                                                                )
+                                                                .await?
+                                                                .first()
+                                                                .map_or(0, |character_series| {
+                                                                    character_series.series_id
+                                                                });
+                                                            Character::update_by_id(
+                                                                conn,
+                                                                &character,
+                                                                character_id,
+                                                            )
+                                                            .await?;
+                                                        }
+
                                                         characters =
                                                             Character::select_page_by_series(
                                                                 conn,
@@ -1278,6 +1384,123 @@ async fn edit_series(client: &mut Client, update: &mut Update, data: &mut Data)
                             .await?;
                         return Ok(());
                     }
+                    "parent" => {
+                        let field = t("parent");
+                        let timeout = 15;
+
+                        match conv
+                            .ask_message(
+                                chat,
+                                sender,
+                                InputMessage::html(
+                                    t("ask_field")
+                                        .replace("{field}", &field)
+                                        .replace("{timeout}", &timeout.to_string()),
+                                ),
+                                crate::filters::sudoers(),
+                                Duration::from_secs(timeout),
+                            )
+                            .await?
+                        {
+                            (sent, Some(response)) => {
+                                // Zero makes the series the root of its own franchise
+                                let text = match response.text().trim().parse::<i64>() {
+                                    Ok(0) => {
+                                        series.parent_id = None;
+                                        Series::update_by_id(conn, &series, series_id).await?;
+
+                                        t("field_updated").replace("{field}", &field.to_lowercase())
+                                    }
+                                    Ok(parent_id) => {
+                                        if Series::select_by_id(conn, parent_id).await?.is_none() {
+                                            t("unknown_series")
+                                        } else if Series::is_ancestor_of(conn, series_id, parent_id)
+                                            .await?
+                                        {
+                                            t("franchise_cycle")
+                                        } else {
+                                            series.parent_id = Some(parent_id);
+                                            Series::update_by_id(conn, &series, series_id).await?;
+
+                                            t("field_updated")
+                                                .replace("{field}", &field.to_lowercase())
+                                        }
+                                    }
+                                    Err(_) => t("invalid_id"),
+                                };
+                                sent.edit(InputMessage::html(text)).await?;
+
+                                tokio::time::sleep(Duration::from_secs(2)).await;
+                                sent.delete().await?;
+                                let _ = response.delete().await;
+                            }
+                            (sent, None) => {
+                                sent.edit(InputMessage::html(
+                                    t("operation_cancelled").replace("{reason}", &t("timeout")),
+                                ))
+                                .await?;
+
+                                tokio::time::sleep(Duration::from_secs(2)).await;
+                                sent.delete().await?;
+
+                                return Ok(());
+                            }
+                        }
+                    }
+                    "anilist_id" => {
+                        let field = t("anilist_id");
+                        let timeout = 15;
+
+                        match conv
+                            .ask_message(
+                                chat,
+                                sender,
+                                InputMessage::html(
+                                    t("ask_field")
+                                        .replace("{field}", &field)
+                                        .replace("{timeout}", &timeout.to_string()),
+                                ),
+                                crate::filters::sudoers(),
+                                Duration::from_secs(timeout),
+                            )
+                            .await
+                            .unwrap()
+                        {
+                            (sent, Some(response)) => {
+                                // Zero unlinks the series
+                                match response.text().trim().parse::<i64>() {
+                                    Ok(anilist_id) => {
+                                        series.anilist_id = Some(anilist_id).filter(|id| *id > 0);
+                                        Series::update_by_id(conn, &series, series_id).await?;
+
+                                        sent.edit(InputMessage::html(
+                                            t("field_updated")
+                                                .replace("{field}", &field.to_lowercase()),
+                                        ))
+                                        .await?;
+                                    }
+                                    Err(_) => {
+                                        sent.edit(InputMessage::html(t("invalid_id"))).await?;
+                                    }
+                                }
+
+                                tokio::time::sleep(Duration::from_secs(2)).await;
+                                sent.delete().await?;
+                                let _ = response.delete().await;
+                            }
+                            (sent, None) => {
+                                sent.edit(InputMessage::html(
+                                    t("operation_cancelled").replace("{reason}", &t("timeout")),
+                                ))
+                                .await?;
+
+                                tokio::time::sleep(Duration::from_secs(2)).await;
+                                sent.delete().await?;
+
+                                return Ok(());
+                            }
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -1289,6 +1512,8 @@ async fn edit_series(client: &mut Client, update: &mut Update, data: &mut Data)
                 "banner",
                 "media_type",
                 "characters",
+                "parent",
+                "anilist_id",
             ];
             let buttons = fields
                 .into_iter()
@@ -0,0 +1,153 @@
+use grammers_client::{button, reply_markup, Client, InputMessage, Update};
+use grammers_friendly::prelude::*;
+use rbatis::RBatis;
+
+use crate::{
+    database::models::{Character, Series, SyncChange, SyncKind},
+    modules::{Database, I18n},
+    Config, Result,
+};
+
+pub fn router() -> Router {
+    Router::default()
+        .add_handler(Handler::new_message(
+            sync,
+            macros::command!("/!.", "sync").and(crate::filters::sudoers()),
+        ))
+        .add_handler(Handler::callback_query(
+            review_change,
+            filters::query("sync action:str id:int").and(crate::filters::sudoers()),
+        ))
+}
+
+async fn sync(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+    let config = data.get_module::<Config>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let message = update.get_message().unwrap();
+
+    let conn = db.get_conn();
+
+    let splitted = message.text().split_whitespace().collect::<Vec<&str>>();
+    if splitted.get(1).is_some_and(|arg| *arg == "run") {
+        let sent = message
+            .reply(InputMessage::html(t("anilist_syncing")))
+            .await?;
+
+        let changes = crate::tasks::sync_entries(conn, &config).await?;
+        sent.edit(InputMessage::html(
+            t(if config.anilist.auto_apply {
+                "anilist_sync_applied"
+            } else {
+                "anilist_sync_queued"
+            })
+            .replace("{changes}", &changes.to_string()),
+        ))
+        .await?;
+
+        return Ok(());
+    }
+
+    message
+        .reply(next_change_message(conn, &i18n).await?)
+        .await?;
+
+    Ok(())
+}
+
+async fn review_change(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let query = update.get_query().unwrap();
+    let message = query.load_message().await?;
+
+    let splitted = utils::split_query(query.data());
+    let change_id = splitted[2].parse::<i64>().unwrap();
+
+    let conn = db.get_conn();
+
+    if let Some(sync_change) = SyncChange::select_by_id(conn, change_id).await? {
+        if splitted[1] == "apply" {
+            if let Err(e) = crate::tasks::apply_change(conn, &sync_change).await {
+                log::error!("failed to apply sync change {}: {}", sync_change.id, e);
+                query
+                    .answer()
+                    .alert(t("error_occurred").replace("{field}", &sync_change.field))
+                    .send()
+                    .await?;
+
+                return Ok(());
+            }
+        }
+
+        SyncChange::delete_by_id(conn, change_id).await?;
+    }
+
+    message
+        .edit(next_change_message(conn, &i18n).await?)
+        .await?;
+
+    Ok(())
+}
+
+// Shows the oldest change waiting for review, with buttons to apply or reject it
+async fn next_change_message(conn: &mut RBatis, i18n: &I18n) -> Result<InputMessage> {
+    let t = |key| i18n.get(key);
+
+    let Some(sync_change) = SyncChange::select_first(conn).await? else {
+        return Ok(InputMessage::html(t("anilist_no_changes")));
+    };
+
+    let (object, name) = match sync_change.kind {
+        SyncKind::Character => (
+            t("character"),
+            Character::select_by_id(conn, sync_change.target_id)
+                .await?
+                .map(|character| character.name),
+        ),
+        SyncKind::Series => (
+            t("series"),
+            Series::select_by_id(conn, sync_change.target_id)
+                .await?
+                .map(|series| series.title),
+        ),
+    };
+
+    // Aliases are stored as JSON arrays
+    let display = |value: &str| match serde_json::from_str::<Vec<String>>(value) {
+        Ok(aliases) => aliases.join(", "),
+        Err(_) => value.to_string(),
+    };
+
+    Ok(InputMessage::html(
+        t("anilist_sync_change")
+            .replace("{object}", &object)
+            .replace("{id}", &sync_change.target_id.to_string())
+            .replace(
+                "{name}",
+                &crate::utils::escape_html(name.unwrap_or_else(|| t("unknown"))),
+            )
+            .replace("{field}", &sync_change.field)
+            .replace(
+                "{old}",
+                &crate::utils::escape_html(display(&sync_change.old_value)),
+            )
+            .replace(
+                "{new}",
+                &crate::utils::escape_html(display(&sync_change.new_value)),
+            ),
+    )
+    .reply_markup(&reply_markup::inline(vec![vec![
+        button::inline(t("apply_button"), format!("sync apply {}", sync_change.id)),
+        button::inline(
+            t("reject_button"),
+            format!("sync reject {}", sync_change.id),
+        ),
+    ]])))
+}
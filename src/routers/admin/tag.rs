@@ -0,0 +1,206 @@
+use grammers_client::{Client, InputMessage, Update};
+use grammers_friendly::prelude::*;
+use rbatis::executor::Executor;
+
+use crate::{
+    database::models::{Character, CharacterTag, GroupTag, Tag, TagName},
+    modules::{Database, I18n},
+    Result,
+};
+
+pub fn router() -> Router {
+    Router::default().add_handler(Handler::new_message(
+        manage_tags,
+        macros::command!("/!.", "tags").and(crate::filters::sudoers()),
+    ))
+}
+
+async fn manage_tags(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+    let conn = db.get_conn();
+
+    let message = update.get_message().unwrap();
+
+    let splitted = message.text().split_whitespace().collect::<Vec<&str>>();
+
+    let (Some(action), Some(name)) = (splitted.get(1), splitted.get(2)) else {
+        message
+            .reply(InputMessage::html(t("invalid_command").replace(
+                "{cmd}",
+                &crate::utils::escape_html(format!(
+                    "{} add|delete <tag> | name <tag> <language> <name> | attach|detach <tag> <ids>",
+                    splitted[0]
+                )),
+            )))
+            .await?;
+
+        return Ok(());
+    };
+    let name = name.to_lowercase();
+
+    if *action == "add" {
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        {
+            message
+                .reply(InputMessage::html(t("invalid_tag_name")))
+                .await?;
+        } else if Tag::select_by_name(conn, &name).await?.is_some() {
+            message
+                .reply(InputMessage::html(t("tag_exists").replace("{tag}", &name)))
+                .await?;
+        } else {
+            let tag = Tag {
+                id: Tag::select_last(conn).await?.map_or(0, |tag| tag.id) + 1,
+                name,
+            };
+            Tag::insert(conn, &tag).await?;
+
+            message
+                .reply(InputMessage::html(
+                    t("tag_created")
+                        .replace("{tag}", &tag.name)
+                        .replace("{id}", &tag.id.to_string()),
+                ))
+                .await?;
+        }
+
+        return Ok(());
+    }
+
+    let Some(tag) = Tag::select_by_name(conn, &name).await? else {
+        message
+            .reply(InputMessage::html(
+                t("unknown_tag").replace("{tag}", &crate::utils::escape_html(name)),
+            ))
+            .await?;
+
+        return Ok(());
+    };
+
+    match *action {
+        "delete" => {
+            let tx = conn.acquire_begin().await?;
+            if let Err(e) = delete_tag(&tx, tag.id).await {
+                tx.rollback().await?;
+                return Err(e.into());
+            }
+            tx.commit().await?;
+
+            message
+                .reply(InputMessage::html(
+                    t("tag_deleted").replace("{tag}", &tag.name),
+                ))
+                .await?;
+        }
+        "name" => {
+            let locales = i18n.locales();
+            let (Some(locale), true) = (splitted.get(3), splitted.len() > 4) else {
+                message
+                    .reply(InputMessage::html(t("invalid_command").replace(
+                        "{cmd}",
+                        &crate::utils::escape_html(format!(
+                            "{} name <tag> <language> <name>",
+                            splitted[0]
+                        )),
+                    )))
+                    .await?;
+
+                return Ok(());
+            };
+
+            if !locales.iter().any(|l| l == locale) {
+                message
+                    .reply(InputMessage::html(
+                        t("unknown_locale")
+                            .replace("{locale}", &crate::utils::escape_html(*locale))
+                            .replace("{locales}", &locales.join(", ")),
+                    ))
+                    .await?;
+
+                return Ok(());
+            }
+
+            let tag_name = TagName {
+                tag_id: tag.id,
+                language_code: locale.to_string(),
+                name: splitted[4..].join(" "),
+            };
+            TagName::delete_by_id(conn, tag.id, locale).await?;
+            TagName::insert(conn, &tag_name).await?;
+
+            message
+                .reply(InputMessage::html(
+                    t("tag_named")
+                        .replace("{tag}", &tag.name)
+                        .replace("{name}", &crate::utils::escape_html(&tag_name.name))
+                        .replace("{locale}", locale),
+                ))
+                .await?;
+        }
+        "attach" | "detach" => {
+            let ids = crate::utils::parse_ids(&splitted[3..].join(" "));
+
+            let mut count = 0;
+            for id in ids.into_iter() {
+                if Character::select_by_id(conn, id).await?.is_none() {
+                    continue;
+                }
+
+                let exists = CharacterTag::select_by_id(conn, id, tag.id)
+                    .await?
+                    .is_some();
+                if *action == "attach" && !exists {
+                    let character_tag = CharacterTag {
+                        character_id: id,
+                        tag_id: tag.id,
+                    };
+                    CharacterTag::insert(conn, &character_tag).await?;
+                    count += 1;
+                } else if *action == "detach" && exists {
+                    CharacterTag::delete_by_id(conn, id, tag.id).await?;
+                    count += 1;
+                }
+            }
+
+            message
+                .reply(InputMessage::html(
+                    t(if *action == "attach" {
+                        "tag_attached"
+                    } else {
+                        "tag_detached"
+                    })
+                    .replace("{tag}", &tag.name)
+                    .replace("{count}", &count.to_string()),
+                ))
+                .await?;
+        }
+        _ => {
+            message
+                .reply(InputMessage::html(t("invalid_command").replace(
+                    "{cmd}",
+                    &crate::utils::escape_html(format!(
+                        "{} add|delete|name|attach|detach <tag>",
+                        splitted[0]
+                    )),
+                )))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+// The tag is removed from the characters and the group pools too
+async fn delete_tag(executor: &dyn Executor, tag_id: i64) -> rbatis::Result<()> {
+    CharacterTag::delete_by_tag(executor, tag_id).await?;
+    GroupTag::delete_by_tag(executor, tag_id).await?;
+    TagName::delete_by_tag(executor, tag_id).await?;
+    Tag::delete_by_id(executor, tag_id).await?;
+
+    Ok(())
+}
@@ -0,0 +1,339 @@
+use std::time::Duration;
+
+use grammers_client::{button, reply_markup, types::Chat, Client, InputMessage, Update};
+use grammers_friendly::prelude::*;
+use rbatis::executor::Executor;
+
+use crate::{
+    database::models::{Character, CharacterVariant},
+    modules::{Conversation, Database, I18n},
+    Result,
+};
+
+// Called by the `variants` field of the character edit wizard, with the queries:
+// `char edit <id> variants [add|<variant id> [up|down|remove]]`
+pub async fn edit_variants(
+    client: &mut Client,
+    update: &mut Update,
+    data: &mut Data,
+) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+    let conv = data.get_module::<Conversation>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let chat = update.get_chat().unwrap();
+    let query = update.get_query().unwrap();
+    let sender = query.sender();
+    let message = query.load_message().await?;
+
+    let splitted = utils::split_query(query.data());
+    let character_id = splitted[2].parse::<i64>().unwrap();
+
+    let conn = db.get_conn();
+
+    let Some(character) = Character::select_by_id(conn, character_id).await? else {
+        message
+            .edit(InputMessage::html(t("unknown_character")))
+            .await?;
+
+        return Ok(());
+    };
+    let mut variants = CharacterVariant::select_by_character(conn, character_id).await?;
+
+    match splitted.get(4).map(|action| action.as_str()) {
+        Some("add") => {
+            if variants.len() >= CharacterVariant::MAX_VARIANTS {
+                let sent = message
+                    .reply(InputMessage::html(
+                        t("max_variants")
+                            .replace("{max}", &CharacterVariant::MAX_VARIANTS.to_string()),
+                    ))
+                    .await?;
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                sent.delete().await?;
+
+                return Ok(());
+            }
+
+            let timeout = 30;
+            let image = match conv
+                .ask_photo(
+                    chat.clone(),
+                    sender,
+                    InputMessage::html(
+                        t("ask_field")
+                            .replace("{field}", &t("photo"))
+                            .replace("{timeout}", &timeout.to_string()),
+                    ),
+                    crate::filters::sudoers(),
+                    Duration::from_secs(timeout),
+                )
+                .await?
+            {
+                (sent, Some(response)) => {
+                    let photo = response.photo().unwrap();
+                    let bytes = crate::images::normalize(
+                        &crate::utils::download_tele_photo(client, photo).await?,
+                    )?;
+
+                    sent.delete().await?;
+                    let _ = response.delete().await;
+
+                    bytes
+                }
+                (sent, None) => {
+                    cancel(&i18n, sent).await?;
+
+                    return Ok(());
+                }
+            };
+
+            let Some(artist) =
+                ask_text(&conv, &i18n, chat.clone(), sender, t("artist_name")).await?
+            else {
+                return Ok(());
+            };
+            let Some(image_link) =
+                ask_text(&conv, &i18n, chat.clone(), sender, t("image_link")).await?
+            else {
+                return Ok(());
+            };
+            let Some(rarity) =
+                ask_text(&conv, &i18n, chat.clone(), sender, t("ask_rarity")).await?
+            else {
+                return Ok(());
+            };
+
+            // `0` makes the variant as common as the default art
+            let rarity = match rarity.parse::<u8>() {
+                Ok(0) => None,
+                Ok(rarity) => Some(rarity),
+                Err(_) => {
+                    let sent = message
+                        .reply(InputMessage::html(t("invalid_rarity")))
+                        .await?;
+                    tokio::time::sleep(Duration::from_secs(2)).await;
+                    sent.delete().await?;
+
+                    return Ok(());
+                }
+            };
+
+            let image_hash = crate::images::store(conn, &image).await?;
+            super::character::warn_similar_images(
+                &i18n,
+                &message,
+                &crate::images::similar_characters(conn, &image_hash, character_id).await?,
+            )
+            .await?;
+
+            let variant = CharacterVariant {
+                id: CharacterVariant::select_last(conn)
+                    .await?
+                    .map_or(0, |variant| variant.id)
+                    + 1,
+                character_id,
+                position: variants.last().map_or(1, |variant| variant.position + 1),
+                image_hash: Some(image_hash),
+                artist,
+                image_link,
+                rarity,
+            };
+            CharacterVariant::insert(conn, &variant).await?;
+            variants.push(variant);
+
+            let sent = message
+                .reply(InputMessage::html(
+                    t("variant_added").replace("{name}", &character.name),
+                ))
+                .await?;
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            sent.delete().await?;
+        }
+        Some(variant_id) => {
+            let variant_id = variant_id.parse::<i64>().unwrap_or(0);
+            let Some(index) = variants.iter().position(|variant| variant.id == variant_id) else {
+                return Ok(());
+            };
+
+            match splitted.get(5).map(|action| action.as_str()) {
+                Some(action @ ("up" | "down")) => {
+                    let target = if action == "up" {
+                        index.checked_sub(1)
+                    } else {
+                        Some(index + 1).filter(|target| *target < variants.len())
+                    };
+
+                    if let Some(target) = target {
+                        variants.swap(index, target);
+
+                        // Positions are renumbered so repeated ones can't get stuck
+                        for (position, variant) in variants.iter_mut().enumerate() {
+                            variant.position = position as u16 + 1;
+                            CharacterVariant::update_by_id(conn, variant, variant.id).await?;
+                        }
+                    }
+                }
+                Some("remove") => {
+                    let tx = conn.acquire_begin().await?;
+                    if let Err(e) = remove_variant(&tx, variant_id).await {
+                        tx.rollback().await?;
+                        return Err(e.into());
+                    }
+                    tx.commit().await?;
+
+                    variants.remove(index);
+                }
+                _ => {
+                    let variant = &variants[index];
+
+                    message
+                        .edit(
+                            InputMessage::html(variant_info(&i18n, index + 1, variant))
+                                .reply_markup(&reply_markup::inline(vec![
+                                    vec![
+                                        button::inline(
+                                            "⬆",
+                                            format!(
+                                                "char edit {0} variants {1} up",
+                                                character_id, variant.id
+                                            ),
+                                        ),
+                                        button::inline(
+                                            "⬇",
+                                            format!(
+                                                "char edit {0} variants {1} down",
+                                                character_id, variant.id
+                                            ),
+                                        ),
+                                    ],
+                                    vec![button::inline(
+                                        t("delete_button"),
+                                        format!(
+                                            "char edit {0} variants {1} remove",
+                                            character_id, variant.id
+                                        ),
+                                    )],
+                                    vec![button::inline(
+                                        t("back_button"),
+                                        format!("char edit {} variants", character_id),
+                                    )],
+                                ])),
+                        )
+                        .await?;
+
+                    return Ok(());
+                }
+            }
+        }
+        None => {}
+    }
+
+    let buttons = variants
+        .iter()
+        .enumerate()
+        .map(|(index, variant)| {
+            button::inline(
+                format!("🎨 {0}. {1}", index + 1, variant.artist),
+                format!("char edit {0} variants {1}", character_id, variant.id),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut buttons = utils::split_kb_to_columns(buttons, 1);
+
+    buttons.extend(vec![
+        vec![button::inline(
+            t("add_button"),
+            format!("char edit {} variants add", character_id),
+        )],
+        vec![button::inline(
+            t("back_button"),
+            format!("char edit {}", character_id),
+        )],
+    ]);
+
+    message
+        .edit(
+            InputMessage::html(t("variant_list").replace("{name}", &character.name))
+                .reply_markup(&reply_markup::inline(buttons)),
+        )
+        .await?;
+
+    Ok(())
+}
+
+fn variant_info(i18n: &I18n, index: usize, variant: &CharacterVariant) -> String {
+    i18n.get("variant_info")
+        .replace("{index}", &index.to_string())
+        .replace("{artist}", &crate::utils::escape_html(&variant.artist))
+        .replace("{link}", &crate::utils::escape_html(&variant.image_link))
+        .replace(
+            "{rarity}",
+            &variant
+                .rarity
+                .map_or_else(|| "—".to_string(), |rarity| format!("1/{}", rarity)),
+        )
+}
+
+// Returns `None` when the time ran out
+async fn ask_text(
+    conv: &Conversation,
+    i18n: &I18n,
+    chat: Chat,
+    sender: &Chat,
+    field: String,
+) -> Result<Option<String>> {
+    let timeout = 15;
+
+    match conv
+        .ask_message(
+            chat,
+            sender,
+            InputMessage::html(
+                i18n.get("ask_field")
+                    .replace("{field}", &field)
+                    .replace("{timeout}", &timeout.to_string()),
+            ),
+            crate::filters::sudoers(),
+            Duration::from_secs(timeout),
+        )
+        .await?
+    {
+        (sent, Some(response)) => {
+            let text = response.text().trim().to_string();
+
+            sent.delete().await?;
+            let _ = response.delete().await;
+
+            Ok(Some(text))
+        }
+        (sent, None) => {
+            cancel(i18n, sent).await?;
+
+            Ok(None)
+        }
+    }
+}
+
+async fn cancel(i18n: &I18n, sent: grammers_client::types::Message) -> Result<()> {
+    sent.edit(InputMessage::html(
+        i18n.get("operation_cancelled")
+            .replace("{reason}", &i18n.get("timeout")),
+    ))
+    .await?;
+
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    sent.delete().await?;
+
+    Ok(())
+}
+
+// Whoever had the variant keeps the character with the default art
+async fn remove_variant(executor: &dyn Executor, variant_id: i64) -> rbatis::Result<()> {
+    CharacterVariant::unlink(executor, variant_id).await?;
+    CharacterVariant::delete_by_id(executor, variant_id).await?;
+
+    Ok(())
+}
@@ -0,0 +1,414 @@
+use grammers_client::{
+    button, reply_markup,
+    types::{Chat, Message},
+    Client, InputMessage, Update,
+};
+use grammers_friendly::prelude::*;
+use rbatis::{executor::Executor, RBatis};
+
+use crate::{
+    database::models::{Auction, Character, OwnedCharacter, Wallet},
+    modules::{Database, I18n},
+    utils::PhotoSource,
+    Config, Result,
+};
+
+pub fn router() -> Router {
+    Router::default()
+        .add_handler(Handler::new_message(
+            start_auction,
+            macros::command!("/!.", "auction"),
+        ))
+        .add_handler(Handler::new_message(
+            bid_command,
+            macros::command!("/!.", "bid").and(filters::reply()),
+        ))
+        .add_handler(Handler::callback_query(
+            bid_button,
+            filters::query("auction bid id:int amount:int"),
+        ))
+}
+
+async fn start_auction(client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+    let config = data.get_module::<Config>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let chat = update.get_chat().unwrap();
+    let sender = update.get_sender().unwrap();
+    let message = update.get_message().unwrap();
+
+    let Chat::Group(ref group) = chat else {
+        message.reply(InputMessage::html(t("not_a_group"))).await?;
+
+        return Ok(());
+    };
+
+    let splitted = message.text().split_whitespace().collect::<Vec<&str>>();
+
+    let (Some(character_id), Some(starting_bid), Some(duration)) = (
+        splitted.get(1).and_then(|text| text.parse::<i64>().ok()),
+        splitted.get(2).and_then(|text| text.parse::<i64>().ok()),
+        splitted
+            .get(3)
+            .and_then(|text| crate::utils::parse_duration(text)),
+    ) else {
+        message
+            .reply(InputMessage::html(t("invalid_command").replace(
+                "{cmd}",
+                &crate::utils::escape_html(format!(
+                    "{} <id> <starting bid> <duration>",
+                    splitted[0]
+                )),
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    if starting_bid <= 0 {
+        message.reply(InputMessage::html(t("bid_too_low"))).await?;
+
+        return Ok(());
+    }
+
+    if duration < config.auctions.min_duration || duration > config.auctions.max_duration {
+        message
+            .reply(InputMessage::html(
+                t("invalid_auction_duration")
+                    .replace(
+                        "{min}",
+                        &crate::utils::format_duration(config.auctions.min_duration),
+                    )
+                    .replace(
+                        "{max}",
+                        &crate::utils::format_duration(config.auctions.max_duration),
+                    ),
+            ))
+            .await?;
+
+        return Ok(());
+    }
+
+    let conn = db.get_conn();
+
+    let seller_id = sender.id();
+    let group_id = group.id();
+
+    if OwnedCharacter::select_by_id(conn, seller_id, group_id, character_id)
+        .await?
+        .is_none()
+    {
+        message
+            .reply(InputMessage::html(t("character_not_owned")))
+            .await?;
+
+        return Ok(());
+    }
+
+    if Auction::select_by_character(conn, group_id, seller_id, character_id)
+        .await?
+        .is_some()
+    {
+        message
+            .reply(InputMessage::html(t("already_auctioned")))
+            .await?;
+
+        return Ok(());
+    }
+
+    let Some(character) = Character::select_by_id(conn, character_id).await? else {
+        message
+            .reply(InputMessage::html(t("unknown_character")))
+            .await?;
+
+        return Ok(());
+    };
+
+    let mut auction = Auction {
+        id: 0,
+        chat: chat.pack().to_bytes().to_vec(),
+        group_id,
+        seller_id,
+        character_id,
+        message_id: 0,
+        bid: starting_bid,
+        bidder_id: None,
+        ends_at: chrono::Utc::now().timestamp() + duration,
+    };
+    // The buttons need the ID, the message is set once it's sent
+    auction.id = Auction::insert_new(conn, &auction).await?;
+
+    let input_message =
+        InputMessage::html(construct_auction_info(conn, &i18n, &auction, &character).await?)
+            .reply_markup(&reply_markup::inline(auction_buttons(
+                &auction,
+                config.auctions.min_increment,
+            )));
+
+    let sent = match crate::utils::send_photo(
+        client,
+        conn,
+        PhotoSource::Character(&character, None),
+        |file| {
+            message.reply(match file {
+                Some(file) => input_message.clone().copy_media(&file),
+                None => input_message.clone(),
+            })
+        },
+    )
+    .await
+    {
+        Ok(sent) => sent,
+        Err(e) => {
+            Auction::delete_by_id(conn, auction.id).await?;
+            return Err(e);
+        }
+    };
+
+    auction.message_id = sent.id();
+    Auction::update_by_id(conn, &auction, auction.id).await?;
+
+    Ok(())
+}
+
+async fn bid_command(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+    let config = data.get_module::<Config>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let chat = update.get_chat().unwrap();
+    let sender = update.get_sender().unwrap();
+    let message = update.get_message().unwrap();
+
+    let Ok(Some(reply_message)) = message.get_reply().await else {
+        return Ok(());
+    };
+
+    let splitted = message.text().split_whitespace().collect::<Vec<&str>>();
+
+    let Some(amount) = splitted.get(1).and_then(|text| text.parse::<i64>().ok()) else {
+        message
+            .reply(InputMessage::html(t("invalid_command").replace(
+                "{cmd}",
+                &crate::utils::escape_html(format!("{} <amount>", splitted[0])),
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    let conn = db.get_conn();
+
+    let Some(auction) = Auction::select_by_message_id(conn, chat.id(), reply_message.id()).await?
+    else {
+        message
+            .reply(InputMessage::html(t("unknown_auction")))
+            .await?;
+
+        return Ok(());
+    };
+
+    let key = place_bid(
+        conn,
+        &i18n,
+        &config,
+        &reply_message,
+        &auction,
+        sender.id(),
+        amount,
+    )
+    .await?;
+    message.reply(InputMessage::html(t(key))).await?;
+
+    Ok(())
+}
+
+async fn bid_button(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+    let config = data.get_module::<Config>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let query = update.get_query().unwrap();
+    let sender = query.sender();
+    let message = query.load_message().await?;
+
+    let splitted = utils::split_query(query.data());
+    let auction_id = splitted[2].parse::<i64>().unwrap();
+    let amount = splitted[3].parse::<i64>().unwrap();
+
+    let conn = db.get_conn();
+
+    let key = match Auction::select_by_id(conn, auction_id).await? {
+        Some(auction) => {
+            place_bid(
+                conn,
+                &i18n,
+                &config,
+                &message,
+                &auction,
+                sender.id(),
+                amount,
+            )
+            .await?
+        }
+        None => "unknown_auction",
+    };
+    query.answer().alert(t(key)).send().await?;
+
+    Ok(())
+}
+
+// Takes the coins from the new bidder and gives them back to the previous one,
+// then refreshes the auction message and lets the outbid user know
+async fn place_bid(
+    conn: &mut RBatis,
+    i18n: &I18n,
+    config: &Config,
+    message: &Message,
+    auction: &Auction,
+    bidder_id: i64,
+    amount: i64,
+) -> Result<&'static str> {
+    if chrono::Utc::now().timestamp() >= auction.ends_at {
+        return Ok("auction_ended");
+    }
+
+    if bidder_id == auction.seller_id {
+        return Ok("own_auction");
+    }
+
+    if amount < auction.min_bid(config.auctions.min_increment) {
+        return Ok("bid_too_low");
+    }
+
+    let tx = conn.acquire_begin().await?;
+    let key = match transfer_bid(&tx, auction, bidder_id, amount).await {
+        Ok(key) => key,
+        Err(e) => {
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+
+    if key != "bid_placed" {
+        tx.rollback().await?;
+
+        return Ok(key);
+    }
+    tx.commit().await?;
+
+    let Some(updated) = Auction::select_by_id(conn, auction.id).await? else {
+        return Ok(key);
+    };
+
+    if let Some(character) = Character::select_by_id(conn, updated.character_id).await? {
+        message
+            .edit(
+                InputMessage::html(construct_auction_info(conn, i18n, &updated, &character).await?)
+                    .reply_markup(&reply_markup::inline(auction_buttons(
+                        &updated,
+                        config.auctions.min_increment,
+                    ))),
+            )
+            .await?;
+
+        if let Some(previous_bidder_id) = auction.bidder_id.filter(|id| *id != bidder_id) {
+            message
+                .reply(InputMessage::html(
+                    i18n.get("auction_outbid")
+                        .replace(
+                            "{user}",
+                            &crate::utils::mention_user(conn, previous_bidder_id).await?,
+                        )
+                        .replace("{name}", &crate::utils::escape_html(&character.name))
+                        .replace("{bid}", &updated.bid.to_string()),
+                ))
+                .await?;
+        }
+    }
+
+    Ok(key)
+}
+
+async fn transfer_bid(
+    executor: &dyn Executor,
+    auction: &Auction,
+    bidder_id: i64,
+    amount: i64,
+) -> rbatis::Result<&'static str> {
+    if let Some(previous_bidder_id) = auction.bidder_id {
+        Wallet::apply(
+            executor,
+            previous_bidder_id,
+            auction.bid,
+            &format!("auction refund {}", auction.id),
+        )
+        .await?;
+    }
+
+    if !Wallet::apply(
+        executor,
+        bidder_id,
+        -amount,
+        &format!("auction bid {}", auction.id),
+    )
+    .await?
+    {
+        return Ok("not_enough_coins");
+    }
+
+    if !auction.raise_bid(executor, amount, bidder_id).await? {
+        return Ok("bid_too_low");
+    }
+
+    Ok("bid_placed")
+}
+
+pub async fn construct_auction_info(
+    conn: &mut RBatis,
+    i18n: &I18n,
+    auction: &Auction,
+    character: &Character,
+) -> Result<String> {
+    let t = |key| i18n.get(key);
+
+    let bidder = match auction.bidder_id {
+        Some(bidder_id) => crate::utils::mention_user(conn, bidder_id).await?,
+        None => t("no_bids"),
+    };
+
+    Ok(t("auction")
+        .replace("{id}", &character.id.to_string())
+        .replace("{name}", &crate::utils::escape_html(&character.name))
+        .replace(
+            "{seller}",
+            &crate::utils::mention_user(conn, auction.seller_id).await?,
+        )
+        .replace("{bid}", &auction.bid.to_string())
+        .replace("{bidder}", &bidder)
+        .replace(
+            "{ends_at}",
+            &crate::utils::format_timestamp(auction.ends_at),
+        ))
+}
+
+fn auction_buttons(auction: &Auction, increment: i64) -> Vec<Vec<button::Inline>> {
+    let min_bid = auction.min_bid(increment);
+
+    vec![[min_bid, min_bid + increment * 4, min_bid + increment * 9]
+        .into_iter()
+        .map(|amount| {
+            button::inline(
+                format!("💰 {}", amount),
+                format!("auction bid {0} {1}", auction.id, amount),
+            )
+        })
+        .collect()]
+}
@@ -1,9 +1,12 @@
-use grammers_client::{button, reply_markup, Client, InputMessage, Update};
+use grammers_client::{button, reply_markup, Client, InputMessage, InvocationError, Update};
 use grammers_friendly::prelude::*;
+use rbatis::RBatis;
 
 use crate::{
-    database::models::{Character, Series},
+    database::models::{Character, CharacterVariant, Like, LikeKind, Series},
     modules::{Database, I18n},
+    search::CharacterFilter,
+    utils::PhotoSource,
     Result,
 };
 
@@ -22,14 +25,22 @@ pub fn router() -> Router {
             see_character,
             filters::query("char id:int"),
         ))
+        .add_handler(Handler::callback_query(
+            see_variant,
+            filters::query("cvariant id:int index:int"),
+        ))
         .add_handler(Handler::callback_query(
             like_character,
-            filters::query("clike id:int"),
+            filters::query("clike id:int").or(filters::query("clike id:int index:int")),
         ))
         .add_handler(Handler::new_message(
             search_characters,
             macros::command!("/!.", "cs").or(macros::command!("/!.", "ps")),
         ))
+        .add_handler(Handler::callback_query(
+            search_characters_page,
+            filters::query("csearch page:int"),
+        ))
 }
 
 async fn see_character(client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
@@ -86,26 +97,20 @@ async fn see_character(client: &mut Client, update: &mut Update, data: &mut Data
                 splitted[1] = splitted[1..].join(" ");
                 splitted.truncate(2);
 
-                if let Some(character) = Character::select_by_name(conn, &splitted[1]).await? {
-                    Some(character)
-                } else {
-                    None
-                }
+                Character::search(conn, &splitted[1], 1, 1)
+                    .await?
+                    .into_iter()
+                    .next()
             }
         } {
             let is_like = splitted[0].contains("like");
+            let is_sudoer = !is_like && crate::filters::sudoers().is_ok(client, update).await;
 
-            let mut buttons = vec![vec![button::inline(
-                format!("❤ {}", character.liked_by.len()),
-                format!("clike {}", character.id),
-            )]];
-
-            if !is_like && crate::filters::sudoers().is_ok(client, update).await {
-                buttons.push(vec![
-                    button::inline(t("edit_button"), format!("char edit {}", character.id)),
-                    button::inline(t("delete_button"), format!("char delete {}", character.id)),
-                ]);
-            }
+            let variant_count = CharacterVariant::select_by_character(conn, character.id)
+                .await?
+                .len();
+            let likes = Like::count_by_target(conn, &LikeKind::Character, character.id).await?;
+            let buttons = character_buttons(&i18n, &character, likes, 0, variant_count, is_sudoer);
 
             let input_message = InputMessage::html(crate::utils::construct_character_info(
                 &character,
@@ -113,20 +118,31 @@ async fn see_character(client: &mut Client, update: &mut Update, data: &mut Data
             ))
             .reply_markup(&reply_markup::inline(buttons));
 
-            match {
-                let input_message = input_message.clone();
-
-                if query.is_some() {
-                    message.edit(input_message).await.err()
-                } else {
-                    let file = crate::utils::upload_photo(client, character.clone(), conn)
-                        .await?
-                        .unwrap();
-                    message.reply(input_message.photo(file)).await.err()
+            let error = if query.is_some() {
+                message.edit(input_message.clone()).await.err()
+            } else {
+                match crate::utils::send_photo(
+                    client,
+                    conn,
+                    PhotoSource::Character(&character, None),
+                    |file| {
+                        message.reply(match file {
+                            Some(file) => input_message.clone().copy_media(&file),
+                            None => input_message.clone(),
+                        })
+                    },
+                )
+                .await
+                {
+                    Ok(_) => None,
+                    // Broken images are already rejected while uploading them
+                    Err(e) => Some(*e.downcast::<InvocationError>()?),
                 }
-            } {
+            };
+
+            match error {
                 Some(e) if e.is("FILE_PARTS_MISSING") || e.is("FILE_PARTS_INVALID") => {
-                    character.image = None;
+                    character.image_hash = None;
                     Character::update_by_id(conn, &character, character.id).await?;
 
                     if query.is_some() {
@@ -147,6 +163,101 @@ async fn see_character(client: &mut Client, update: &mut Update, data: &mut Data
     Ok(())
 }
 
+// Flips between the default art of a character, the index 0, and its variants
+async fn see_variant(client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let conn = db.get_conn();
+
+    let query = update.get_query().unwrap();
+    let message = query.load_message().await?;
+
+    let splitted = utils::split_query(query.data());
+    let character_id = splitted[1].parse::<i64>().unwrap();
+    let index = splitted[2].parse::<usize>().unwrap_or(0);
+
+    if let Some(character) = Character::select_by_id(conn, character_id).await? {
+        let variants = CharacterVariant::select_by_character(conn, character.id).await?;
+        let index = index.min(variants.len());
+        let variant = index.checked_sub(1).and_then(|index| variants.get(index));
+
+        let shown = variant.map_or_else(|| character.clone(), |variant| variant.apply(&character));
+        let is_sudoer =
+            !splitted[0].contains("like") && crate::filters::sudoers().is_ok(client, update).await;
+        let likes = Like::count_by_target(conn, &LikeKind::Character, character.id).await?;
+        let buttons = character_buttons(&i18n, &character, likes, index, variants.len(), is_sudoer);
+
+        let input_message = InputMessage::html(crate::utils::construct_character_info(
+            &shown,
+            Series::select_by_id(conn, character.series_id).await?,
+        ))
+        .reply_markup(&reply_markup::inline(buttons));
+
+        crate::utils::send_photo(
+            client,
+            conn,
+            PhotoSource::Character(&character, variant),
+            |file| {
+                message.edit(match file {
+                    Some(file) => input_message.clone().copy_media(&file),
+                    None => input_message.clone(),
+                })
+            },
+        )
+        .await?;
+    }
+    query.answer().send().await?;
+
+    Ok(())
+}
+
+fn character_buttons(
+    i18n: &I18n,
+    character: &Character,
+    likes: usize,
+    index: usize,
+    variant_count: usize,
+    is_sudoer: bool,
+) -> Vec<Vec<button::Inline>> {
+    let t = |key| i18n.get(key);
+
+    let mut buttons = vec![vec![button::inline(
+        format!("❤ {}", likes),
+        format!("clike {} {}", character.id, index),
+    )]];
+
+    if variant_count > 0 {
+        let mut row = Vec::new();
+        if index > 0 {
+            row.push(button::inline(
+                "⬅",
+                format!("cvariant {} {}", character.id, index - 1),
+            ));
+        }
+        row.push(button::inline(
+            format!("🎨 {}/{}", index + 1, variant_count + 1),
+            format!("cvariant {} {}", character.id, index),
+        ));
+        if index < variant_count {
+            row.push(button::inline(
+                "➡",
+                format!("cvariant {} {}", character.id, index + 1),
+            ));
+        }
+        buttons.push(row);
+    }
+
+    if is_sudoer {
+        buttons.push(vec![
+            button::inline(t("edit_button"), format!("char edit {}", character.id)),
+            button::inline(t("delete_button"), format!("char delete {}", character.id)),
+        ]);
+    }
+
+    buttons
+}
+
 async fn like_character(client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
     let mut db = data.get_module::<Database>().unwrap();
 
@@ -160,17 +271,12 @@ async fn like_character(client: &mut Client, update: &mut Update, data: &mut Dat
             let conn = db.get_conn();
             let sender_id = sender.id();
 
-            if let Some(mut character) = Character::select_by_id(conn, id).await? {
-                let mut liked_by = character.liked_by;
-
-                if liked_by.contains(&sender_id) {
-                    liked_by.retain(|id| *id != sender_id);
-                } else {
-                    liked_by.push(sender.id());
-                }
-
-                character.liked_by = liked_by;
-                match Character::update_by_id(conn, &character, character.id).await {
+            if let Some(character) = Character::select_by_id(conn, id).await? {
+                match Like::toggle(conn, sender_id, &LikeKind::Character, character.id).await {
+                    // Likes sent while seeing an alternate art keep showing it
+                    Ok(_) if splitted.get(2).is_some_and(|index| index != "0") => {
+                        see_variant(client, update, data).await?
+                    }
                     Ok(_) => see_character(client, update, data).await?,
                     Err(_) => {}
                 }
@@ -195,52 +301,146 @@ async fn search_characters(
 
     let message = update.get_message().unwrap();
 
-    let mut splitted = message.text().split_whitespace().collect::<Vec<&str>>();
+    let splitted = message.text().split_whitespace().collect::<Vec<&str>>();
 
-    if splitted.len() > 1 {
-        if splitted[0].contains("cs") || splitted[0].contains("ps") {
-            splitted.insert(1, "s");
-        }
+    let Some(search) = search_text(message.text()) else {
+        message
+            .reply(InputMessage::html(t("invalid_command").replace(
+                "{cmd}",
+                &crate::utils::escape_html(format!("{} <name> [filters]", splitted[0])),
+            )))
+            .await?;
 
-        if splitted.len() <= 2 {
+        return Ok(());
+    };
+
+    let filter = match CharacterFilter::parse(&search) {
+        Ok(filter) => filter,
+        Err(e) => {
             message
-                .reply(InputMessage::html(t("invalid_command").replace(
-                    "{cmd}",
-                    &crate::utils::escape_html(format!("{} s <name>", splitted[0])),
-                )))
+                .reply(InputMessage::html(
+                    i18n.get(e.locale_key())
+                        .replace("{field}", &crate::utils::escape_html(e.field()))
+                        .replace("{value}", &crate::utils::escape_html(e.value())),
+                ))
                 .await?;
 
             return Ok(());
         }
+    };
 
-        let name = splitted[2..].join(" ");
-        let mut text = t("search_results").replace("{search}", &name) + "\n\n";
+    message
+        .reply(construct_search_page(conn, &i18n, &search, &filter, 1).await?)
+        .await?;
 
-        let characters = Character::select_page_by_name(conn, &name, 1, 15).await?;
-        if characters.is_empty() {
-            text = t("no_results").replace("{search}", &name);
-        } else {
-            let space_count = characters
-                .iter()
-                .map(|character| character.id.to_string().len())
-                .max()
-                .unwrap_or(0);
-
-            for character in characters.iter() {
-                text +=
-                    &crate::utils::construct_character_partial_info(&character, false, space_count);
-            }
-        }
+    Ok(())
+}
+
+async fn search_characters_page(
+    _client: &mut Client,
+    update: &mut Update,
+    data: &mut Data,
+) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+    let conn = db.get_conn();
+
+    let query = update.get_query().unwrap();
+    let message = query.load_message().await?;
+
+    let splitted = utils::split_query(query.data());
+    let page = splitted[1].parse::<u16>().unwrap_or(1).max(1);
+
+    // The search is read again from the command the results replied to
+    let search = match message.get_reply().await {
+        Ok(Some(command)) => search_text(command.text()),
+        _ => None,
+    };
+    let Some((search, filter)) = search.and_then(|search| {
+        CharacterFilter::parse(&search)
+            .ok()
+            .map(|filter| (search, filter))
+    }) else {
+        query.answer().alert(t("search_expired")).send().await?;
+
+        return Ok(());
+    };
+
+    message
+        .edit(construct_search_page(conn, &i18n, &search, &filter, page).await?)
+        .await?;
+    query.answer().send().await?;
+
+    Ok(())
+}
+
+// `/cs <search>` and `/c s <search>` both become `<search>`
+fn search_text(text: &str) -> Option<String> {
+    let mut splitted = text.split_whitespace().collect::<Vec<&str>>();
+
+    if splitted
+        .first()
+        .is_some_and(|command| command.contains("cs") || command.contains("ps"))
+    {
+        splitted.insert(1, "s");
+    }
 
-        message.reply(InputMessage::html(text)).await?;
+    if splitted.len() <= 2 {
+        None
     } else {
-        message
-            .reply(InputMessage::html(t("invalid_command").replace(
-                "{cmd}",
-                &crate::utils::escape_html(format!("{} <name>", splitted[0])),
-            )))
-            .await?;
+        Some(splitted[2..].join(" "))
     }
+}
 
-    Ok(())
+async fn construct_search_page(
+    conn: &mut RBatis,
+    i18n: &I18n,
+    search: &str,
+    filter: &CharacterFilter,
+    page: u16,
+) -> Result<InputMessage> {
+    let t = |key| i18n.get(key);
+
+    let per_page = 15;
+    let search = crate::utils::escape_html(search);
+
+    let total = Character::count_by_filter(conn, filter).await?;
+    if total == 0 {
+        return Ok(InputMessage::html(
+            t("no_results").replace("{search}", &search),
+        ));
+    }
+
+    let total_pages = total.div_ceil(per_page as usize);
+    let page = page.min(total_pages as u16);
+
+    let characters = Character::search_by_filter(conn, filter, page, per_page).await?;
+    let space_count = characters
+        .iter()
+        .map(|character| character.id.to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    let mut text = t("search_results").replace("{search}", &search) + "\n\n";
+    for character in characters.iter() {
+        text += &crate::utils::construct_character_partial_info(character, false, space_count);
+    }
+    text += &format!("\n🔖 | {}/{}", page, total_pages);
+
+    let mut buttons = Vec::new();
+    if page > 1 {
+        buttons.push(button::inline("⬅", format!("csearch {}", page - 1)));
+    }
+    if (page as usize) < total_pages {
+        buttons.push(button::inline("➡", format!("csearch {}", page + 1)));
+    }
+
+    let mut input_message = InputMessage::html(text);
+    if !buttons.is_empty() {
+        input_message = input_message.reply_markup(&reply_markup::inline(vec![buttons]));
+    }
+
+    Ok(input_message)
 }
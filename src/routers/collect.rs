@@ -2,22 +2,31 @@ use std::time::Duration;
 
 use grammers_client::{
     button, reply_markup,
-    types::{Chat, InputMessage},
+    types::{Chat, InputMessage, Message},
     Client, Update,
 };
 use grammers_friendly::prelude::*;
+use rbatis::{executor::Executor, RBatis};
 
 use crate::{
-    database::models::{Character, GroupCharacter, Series, UserCharacters},
+    database::models::{
+        Character, GroupCharacter, Inventory, OwnedCharacter, Quiz, RaidParticipant, Series,
+        SpawnKind, User,
+    },
     modules::{Conversation, Database, I18n},
-    Result,
+    Config, Result,
 };
 
 pub fn router() -> Router {
-    Router::default().add_handler(Handler::new_message(
-        collect_character,
-        filters::private().not().and(filters::reply()),
-    ))
+    Router::default()
+        .add_handler(Handler::new_message(
+            collect_character,
+            filters::private().not().and(filters::reply()),
+        ))
+        .add_handler(Handler::callback_query(
+            raid_progress,
+            filters::query("raid progress"),
+        ))
 }
 
 async fn collect_character(
@@ -41,291 +50,259 @@ async fn collect_character(
         if let Ok(Some(reply_message)) = message.get_reply().await {
             let conn = db.get_conn();
 
-            if let Some(mut group_character) =
-                GroupCharacter::select_last_by_id(conn, group_id).await?
+            // Look up the character spawned by the replied message
+            if let Some(group_character) =
+                GroupCharacter::select_by_message_id(conn, group_id, reply_message.id()).await?
             {
-                // Check if the character match is the same as the user's reply
-                if group_character.last_message_id == reply_message.id() {
-                    let mut text = t("not_a_character");
-
-                    if let Some(character) =
-                        Character::select_by_id(conn, group_character.character_id).await?
-                    {
-                        let guess = message.text().trim().to_lowercase();
-                        let sender = message.sender().unwrap();
-
-                        // Check if character is available
-                        if group_character.available {
-                            if message.via_bot_id().is_some()
-                                || match sender {
-                                    Chat::User(ref user) => user.is_bot(),
-                                    _ => false,
-                                }
-                            {
-                                // Delete group last character
-                                GroupCharacter::delete_by_id(
-                                    conn,
-                                    group_id,
-                                    group_character.character_id,
-                                )
+                let mut text = t("not_a_character");
+
+                if let Some(character) =
+                    Character::select_by_id(conn, group_character.character_id).await?
+                {
+                    let guess = message.text().trim().to_lowercase();
+                    let sender = message.sender().unwrap();
+
+                    // Check if character is available
+                    if group_character.available {
+                        if message.via_bot_id().is_some()
+                            || match sender {
+                                Chat::User(ref user) => user.is_bot(),
+                                _ => false,
+                            }
+                        {
+                            // Delete the cheated character
+                            GroupCharacter::delete_by_message_id(
+                                conn,
+                                group_id,
+                                group_character.last_message_id,
+                            )
+                            .await?;
+
+                            message
+                                .reply(InputMessage::html(
+                                    t("guess_cheated").replace("{name}", &character.name),
+                                ))
                                 .await?;
 
-                                message
-                                    .reply(InputMessage::html(
-                                        t("guess_cheated").replace("{name}", &character.name),
-                                    ))
-                                    .await?;
+                            return Ok(());
+                        }
 
-                                return Ok(());
-                            }
+                        if guess.is_empty() || message.media().is_some() {
+                            message
+                                .reply(InputMessage::html(t("invalid_guess")))
+                                .await?;
 
-                            if guess.is_empty() || message.media().is_some() {
-                                message
-                                    .reply(InputMessage::html(t("invalid_guess")))
-                                    .await?;
+                            return Ok(());
+                        }
 
-                                return Ok(());
+                        let name = character.name.trim().to_lowercase();
+                        let mut names = vec![name];
+                        character
+                            .aliases
+                            .iter()
+                            .for_each(|alias| names.push(alias.to_lowercase()));
+
+                        if crate::utils::guess_matches(&guess, names) {
+                            if group_character.kind == SpawnKind::Raid {
+                                return join_raid(
+                                    conn,
+                                    &i18n,
+                                    &message,
+                                    &reply_message,
+                                    group_character,
+                                    &character,
+                                    &guess,
+                                )
+                                .await;
                             }
 
-                            let name = character.name.trim().to_lowercase();
-                            let mut names = vec![name];
-                            character
-                                .aliases
-                                .iter()
-                                .for_each(|alias| names.push(alias.to_lowercase()));
+                            let user_id = sender.id();
 
-                            if guess_matches(&guess, names) {
-                                let user_id = sender.id();
+                            let owned_characters =
+                                OwnedCharacter::select_by_user(conn, user_id, group_id).await?;
 
-                                if let Some(mut user_characters) =
-                                    UserCharacters::select_or_insert_by_id(conn, user_id, group_id)
+                            // Duplicates are collected as XP even with a full collection
+                            if !owned_characters
+                                .iter()
+                                .any(|owned| owned.character_id == character.id)
+                                && owned_characters.len()
+                                    >= Inventory::select_or_default(conn, user_id)
                                         .await?
-                                {
-                                    let characters = &mut user_characters.characters_id;
-
-                                    if characters.contains(&character.id) {
-                                        text = t("has_character");
-                                    } else if characters.len() == 9 {
-                                        let timeout = 10;
-
-                                        let sent = message
-                                            .reply(
-                                                InputMessage::html(
-                                                    t("max_characters")
-                                                        .replace("{timeout}", &timeout.to_string()),
-                                                )
-                                                .reply_markup(&reply_markup::inline(vec![vec![
-                                                    button::inline(t("yes_button"), "yes"),
-                                                    button::inline(t("no_button"), "no"),
-                                                ]])),
+                                        .capacity()
+                            {
+                                let timeout = 10;
+
+                                let sent =
+                                    message
+                                        .reply(
+                                            InputMessage::html(
+                                                t("max_characters")
+                                                    .replace("{timeout}", &timeout.to_string()),
                                             )
-                                            .await?;
+                                            .reply_markup(&reply_markup::inline(vec![vec![
+                                                button::inline(t("yes_button"), "yes"),
+                                                button::inline(t("no_button"), "no"),
+                                            ]])),
+                                        )
+                                        .await?;
 
-                                        match conv
-                                            .wait_for_update(
-                                                &user,
-                                                filters::query("[yes|no]"),
-                                                Duration::from_secs(timeout),
-                                            )
-                                            .await
-                                            .unwrap()
-                                        {
-                                            Some(update) => {
-                                                if let Some(query) = update.get_query() {
-                                                    let splitted = utils::split_query(query.data());
-
-                                                    match splitted[0].as_str() {
-                                                        "yes" => {
-                                                            let timeout = 10;
-
-                                                            let buttons = {
-                                                                let mut buttons = Vec::new();
-
-                                                                for id in characters.iter() {
-                                                                    if let Some(character) =
-                                                                        Character::select_by_id(
-                                                                            conn, *id,
-                                                                        )
-                                                                        .await?
-                                                                    {
-                                                                        buttons.push(
-                                                                            button::inline(
-                                                                                format!(
-                                                                                    "{0}. {1}",
-                                                                                    character.id,
-                                                                                    character.name
-                                                                                ),
-                                                                                character
-                                                                                    .id
-                                                                                    .to_string(),
-                                                                            ),
-                                                                        );
-                                                                    }
-                                                                }
+                                match conv
+                                    .wait_for_update(
+                                        &user,
+                                        filters::query("[yes|no]"),
+                                        Duration::from_secs(timeout),
+                                    )
+                                    .await
+                                    .unwrap()
+                                {
+                                    Some(update) => {
+                                        if let Some(query) = update.get_query() {
+                                            let splitted = utils::split_query(query.data());
+
+                                            match splitted[0].as_str() {
+                                                "yes" => {
+                                                    let timeout = 10;
+
+                                                    let buttons = {
+                                                        let mut buttons = Vec::new();
+
+                                                        for owned in owned_characters.iter() {
+                                                            if let Some(character) =
+                                                                Character::select_by_id(
+                                                                    conn,
+                                                                    owned.character_id,
+                                                                )
+                                                                .await?
+                                                            {
+                                                                buttons.push(button::inline(
+                                                                    format!(
+                                                                        "{0}. {1} (Lv. {2})",
+                                                                        character.id,
+                                                                        character.name,
+                                                                        owned.level
+                                                                    ),
+                                                                    character.id.to_string(),
+                                                                ));
+                                                            }
+                                                        }
 
-                                                                buttons
-                                                            };
-                                                            let buttons =
-                                                                utils::split_kb_to_columns(
-                                                                    buttons, 2,
+                                                        buttons
+                                                    };
+                                                    let buttons =
+                                                        utils::split_kb_to_columns(buttons, 2);
+
+                                                    sent.edit(
+                                                        InputMessage::html(
+                                                            t("select_character").replace(
+                                                                "{timeout}",
+                                                                &timeout.to_string(),
+                                                            ),
+                                                        )
+                                                        .reply_markup(&reply_markup::inline(
+                                                            buttons,
+                                                        )),
+                                                    )
+                                                    .await?;
+
+                                                    let mut query = owned_characters
+                                                        .iter()
+                                                        .map(|owned| owned.character_id.to_string())
+                                                        .collect::<Vec<String>>()
+                                                        .join("|");
+                                                    query.insert(0, '[');
+                                                    query.push(']');
+
+                                                    match conv
+                                                        .wait_for_update(
+                                                            &user,
+                                                            filters::query(&query),
+                                                            Duration::from_secs(timeout),
+                                                        )
+                                                        .await
+                                                        .unwrap()
+                                                    {
+                                                        Some(update) => {
+                                                            if let Some(query) = update.get_query()
+                                                            {
+                                                                let splitted = utils::split_query(
+                                                                    query.data(),
                                                                 );
 
-                                                            sent.edit(
-                                                                InputMessage::html(
-                                                                    t("select_character").replace(
-                                                                        "{timeout}",
-                                                                        &timeout.to_string(),
-                                                                    ),
-                                                                )
-                                                                .reply_markup(
-                                                                    &reply_markup::inline(buttons),
-                                                                ),
-                                                            )
-                                                            .await?;
+                                                                if let Ok(id) =
+                                                                    splitted[0].parse::<i64>()
+                                                                {
+                                                                    // Swap the selected character for the new one
+                                                                    let text = swap_text(
+                                                                        conn,
+                                                                        &i18n,
+                                                                        user_id,
+                                                                        &group_character,
+                                                                        &character,
+                                                                        id,
+                                                                    )
+                                                                    .await?;
 
-                                                            let mut query = characters
-                                                                .iter()
-                                                                .map(|id| id.to_string())
-                                                                .collect::<Vec<String>>()
-                                                                .join("|");
-                                                            query.insert(0, '[');
-                                                            query.push(']');
-
-                                                            match conv
-                                                                .wait_for_update(
-                                                                    &user,
-                                                                    filters::query(&query),
-                                                                    Duration::from_secs(timeout),
-                                                                )
-                                                                .await
-                                                                .unwrap()
-                                                            {
-                                                                Some(update) => {
-                                                                    if let Some(query) =
-                                                                        update.get_query()
-                                                                    {
-                                                                        let splitted =
-                                                                            utils::split_query(
-                                                                                query.data(),
-                                                                            );
-
-                                                                        if let Ok(id) = splitted[0]
-                                                                            .parse::<i64>(
-                                                                        ) {
-                                                                            for character_id in
-                                                                                characters
-                                                                                    .iter_mut()
-                                                                            {
-                                                                                if *character_id
-                                                                                    == id
-                                                                                {
-                                                                                    *character_id =
-                                                                                        character.id;
-                                                                                }
-                                                                            }
-
-                                                                            UserCharacters::update_by_id(
-                                                                                conn,
-                                                                                &user_characters,
-                                                                                user_id,
-                                                                                group_id,
-                                                                            ).await?;
-
-                                                                            // Update character availability
-                                                                            group_character
-                                                                                .available = false;
-                                                                            GroupCharacter::update_by_id(
-                                                                                conn,
-                                                                                &group_character,
-                                                                                group_id,
-                                                                                group_character.character_id,
-                                                                            )
-                                                                            .await?;
-
-                                                                            if let Some(old_character) =
-                                                                                Character::select_by_id(
-                                                                                    conn,
-                                                                                    id,
-                                                                                ).await? {
-                                                                                    sent.edit(InputMessage::html(t(
-                                                                                        "character_swapped")
-                                                                                            .replace("{old_id}", &old_character.id.to_string())
-                                                                                            .replace("{old_name}", &old_character.name)
-                                                                                            .replace("{new_id}", &character.id.to_string())
-                                                                                            .replace("{new_name}", &character.name)
-                                                                                    )).await?;
-                                                                                    }
-                                                                        }
-                                                                    }
-                                                                }
-                                                                None => {
                                                                     sent.edit(InputMessage::html(
-                                                                        t("timeouted_operation"),
+                                                                        text,
                                                                     ))
                                                                     .await?;
                                                                 }
                                                             }
                                                         }
-                                                        "no" => {
-                                                            sent.delete().await?;
+                                                        None => {
+                                                            sent.edit(InputMessage::html(t(
+                                                                "timeouted_operation",
+                                                            )))
+                                                            .await?;
                                                         }
-                                                        _ => {}
                                                     }
                                                 }
-                                            }
-                                            None => {
-                                                sent.edit(InputMessage::html(t(
-                                                    "timeouted_operation",
-                                                )))
-                                                .await?;
+                                                "no" => {
+                                                    sent.delete().await?;
+                                                }
+                                                _ => {}
                                             }
                                         }
-
-                                        return Ok(());
-                                    } else {
-                                        let series_title = if let Some(series) =
-                                            Series::select_by_id(conn, character.series_id).await?
-                                        {
-                                            series.title
-                                        } else {
-                                            t("unknown")
-                                        };
-                                        text = t("character_collected")
-                                            .replace("{name}", &character.name)
-                                            .replace("{series}", &series_title);
-
-                                        // Add character to user's collection
-                                        characters.push(character.id);
-                                        UserCharacters::update_by_id(
-                                            conn,
-                                            &user_characters,
-                                            user_id,
-                                            group_id,
-                                        )
-                                        .await?;
-
-                                        // Update character availability
-                                        group_character.available = false;
-                                        GroupCharacter::update_by_id(
-                                            conn,
-                                            &group_character,
-                                            group_id,
-                                            group_character.character_id,
-                                        )
-                                        .await?;
+                                    }
+                                    None => {
+                                        sent.edit(InputMessage::html(t("timeouted_operation")))
+                                            .await?;
                                     }
                                 }
-                            } else {
-                                text = t("wrong_character");
+
+                                return Ok(());
                             }
+
+                            text = match collect_spawn(
+                                conn,
+                                user_id,
+                                &group_character,
+                                &character,
+                                None,
+                            )
+                            .await?
+                            {
+                                Some(collected) => {
+                                    collected_text(conn, &i18n, &character, collected).await?
+                                }
+                                None => t("expired_character"),
+                            };
                         } else {
-                            text = t("expired_character");
+                            text = t("wrong_character");
                         }
+                    } else {
+                        text = t("expired_character");
                     }
-
-                    // Send the reply message
-                    message.reply(InputMessage::html(text)).await?;
                 }
+
+                // Send the reply message
+                message.reply(InputMessage::html(text)).await?;
+            } else if let Some(quiz) =
+                Quiz::select_by_message_id(conn, group_id, reply_message.id()).await?
+            {
+                let config = data.get_module::<Config>().unwrap();
+
+                return super::quiz::answer_quiz(conn, &i18n, &config, &message, quiz).await;
             }
         }
     }
@@ -333,28 +310,379 @@ async fn collect_character(
     Ok(())
 }
 
-fn guess_matches(guess: &str, names: Vec<String>) -> bool {
-    let guess_splitted = guess.split_whitespace().collect::<Vec<&str>>();
+// How a collected spawn ended up in the collection
+enum Collected {
+    New,
+    // A copy was already owned, the spawn was turned into XP for it, with whether it leveled up
+    Duplicate(OwnedCharacter, bool),
+}
 
-    if guess_splitted.len() == 1 {
-        for name in names.iter() {
-            if name.len() == 1 {
-                return guess == *name;
-            } else {
-                for part in name.split_whitespace() {
-                    if guess == part {
-                        return true;
-                    }
-                }
-            }
+// Closes the spawn and gives the character in a single transaction, so concurrent guesses
+// can't both collect it, `swapped` is the owned character given up to make room for it.
+// Returns `None` when another guess already took the spawn
+async fn collect_spawn(
+    conn: &mut RBatis,
+    user_id: i64,
+    group_character: &GroupCharacter,
+    character: &Character,
+    swapped: Option<i64>,
+) -> Result<Option<Collected>> {
+    let tx = conn.acquire_begin().await?;
+    let collected = match grant_spawn(&tx, user_id, group_character, character, swapped).await {
+        Ok(Some(collected)) => collected,
+        Ok(None) => {
+            tx.rollback().await?;
+            return Ok(None);
+        }
+        Err(e) => {
+            tx.rollback().await?;
+            return Err(e.into());
         }
+    };
+    tx.commit().await?;
+
+    Ok(Some(collected))
+}
+
+async fn grant_spawn(
+    executor: &dyn Executor,
+    user_id: i64,
+    group_character: &GroupCharacter,
+    character: &Character,
+    swapped: Option<i64>,
+) -> rbatis::Result<Option<Collected>> {
+    let group_id = group_character.group_id;
+    if !GroupCharacter::close(executor, group_id, group_character.last_message_id).await? {
+        return Ok(None);
+    }
+
+    // A copy collected at the same moment turns this one into XP, and nothing is swapped for it
+    if OwnedCharacter::insert_new(
+        executor,
+        &OwnedCharacter::new(user_id, group_id, character.id, group_character.variant_id),
+    )
+    .await?
+    {
+        if let Some(id) = swapped {
+            OwnedCharacter::delete_by_id(executor, user_id, group_id, id).await?;
+        }
+
+        return Ok(Some(Collected::New));
+    }
+
+    Ok(crate::utils::add_duplicate_xp(
+        executor,
+        user_id,
+        group_id,
+        character,
+        group_character.variant_id,
+    )
+    .await?
+    .map(|(owned_character, leveled_up)| Collected::Duplicate(owned_character, leveled_up)))
+}
+
+// Swaps the owned character for the spawned one, returns the reply
+async fn swap_text(
+    conn: &mut RBatis,
+    i18n: &I18n,
+    user_id: i64,
+    group_character: &GroupCharacter,
+    character: &Character,
+    old_id: i64,
+) -> Result<String> {
+    let Some(collected) =
+        collect_spawn(conn, user_id, group_character, character, Some(old_id)).await?
+    else {
+        return Ok(i18n.get("expired_character"));
+    };
+
+    if let Collected::New = collected {
+        if let Some(old_character) = Character::select_by_id(conn, old_id).await? {
+            return Ok(i18n
+                .get("character_swapped")
+                .replace("{old_id}", &old_character.id.to_string())
+                .replace("{old_name}", &old_character.name)
+                .replace("{new_id}", &character.id.to_string())
+                .replace("{new_name}", &character.name));
+        }
+    }
+
+    collected_text(conn, i18n, character, collected).await
+}
+
+async fn collected_text(
+    conn: &mut RBatis,
+    i18n: &I18n,
+    character: &Character,
+    collected: Collected,
+) -> Result<String> {
+    Ok(match collected {
+        Collected::New => {
+            let series_title =
+                if let Some(series) = Series::select_by_id(conn, character.series_id).await? {
+                    series.title
+                } else {
+                    i18n.get("unknown")
+                };
+
+            i18n.get("character_collected")
+                .replace("{name}", &character.name)
+                .replace("{series}", &series_title)
+        }
+        Collected::Duplicate(owned_character, leveled_up) => if leveled_up {
+            i18n.get("character_leveled_up")
+                .replace("{level}", &owned_character.level.to_string())
+        } else {
+            i18n.get("character_xp_gained")
+        }
+        .replace("{name}", &character.name)
+        .replace("{xp}", &crate::utils::duplicate_xp(character).to_string()),
+    })
+}
+
+async fn raid_progress(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let chat = update.get_chat().unwrap();
+    let query = update.get_query().unwrap();
+    let message = query.load_message().await?;
+
+    let conn = db.get_conn();
+
+    if let Some(group_character) =
+        GroupCharacter::select_by_message_id(conn, chat.id(), message.id()).await?
+    {
+        let remaining = (group_character.expires_at - chrono::Utc::now().timestamp()).max(0);
+        let participants = raid_participants(
+            conn,
+            group_character.group_id,
+            group_character.last_message_id,
+        )
+        .await?;
+        let participants = participant_names(conn, &participants).await?;
+
+        query
+            .answer()
+            .alert(
+                t("raid_progress")
+                    .replace("{progress}", &participants.len().to_string())
+                    .replace("{required}", &group_character.required.to_string())
+                    .replace("{participants}", &participants.join(", "))
+                    .replace("{remaining}", &remaining.to_string()),
+            )
+            .send()
+            .await?;
     } else {
-        for name in names.iter() {
-            if name.contains(guess) {
-                return true;
+        query.answer().alert(t("expired_character")).send().await?;
+    }
+
+    Ok(())
+}
+
+async fn join_raid(
+    conn: &mut RBatis,
+    i18n: &I18n,
+    message: &Message,
+    reply_message: &Message,
+    group_character: GroupCharacter,
+    character: &Character,
+    guess: &str,
+) -> Result<()> {
+    let t = |key| i18n.get(key);
+
+    let group_id = group_character.group_id;
+    let message_id = group_character.last_message_id;
+    let user_id = message.sender().unwrap().id();
+
+    if chrono::Utc::now().timestamp() >= group_character.expires_at {
+        GroupCharacter::delete_by_message_id(conn, group_id, message_id).await?;
+
+        message
+            .reply(InputMessage::html(t("expired_character")))
+            .await?;
+
+        return Ok(());
+    }
+
+    let joined = RaidParticipant::select_by_raid(conn, group_id, message_id).await?;
+    if joined
+        .iter()
+        .any(|participant| participant.user_id == user_id)
+    {
+        message
+            .reply(InputMessage::html(t("raid_already_joined")))
+            .await?;
+
+        return Ok(());
+    }
+
+    // Each participant has to guess a different part of the name, while there are parts left
+    let parts = character
+        .name
+        .to_lowercase()
+        .split_whitespace()
+        .map(String::from)
+        .collect::<Vec<String>>();
+    if joined.iter().any(|participant| participant.guess == guess)
+        && parts
+            .iter()
+            .any(|part| !joined.iter().any(|participant| &participant.guess == part))
+    {
+        message
+            .reply(InputMessage::html(t("raid_part_taken")))
+            .await?;
+
+        return Ok(());
+    }
+
+    let participant = RaidParticipant {
+        group_id,
+        message_id,
+        user_id,
+        guess: guess.to_string(),
+        joined_at: chrono::Utc::now().timestamp(),
+    };
+    if !RaidParticipant::join(conn, &participant).await? {
+        // Someone else's join completed the raid meanwhile, or this one arrived twice
+        let key = if raid_participants(conn, group_id, message_id)
+            .await?
+            .contains(&user_id)
+        {
+            "raid_already_joined"
+        } else {
+            "expired_character"
+        };
+        message.reply(InputMessage::html(t(key))).await?;
+
+        return Ok(());
+    }
+
+    let participants = raid_participants(conn, group_id, message_id).await?;
+
+    if participants.len() >= group_character.required as usize {
+        // Only the join that completes the raid hands out the rewards
+        let tx = conn.acquire_begin().await?;
+        let without_room = match reward_raid(&tx, &group_character, character, &participants).await
+        {
+            Ok(Some(without_room)) => without_room,
+            Ok(None) => {
+                tx.rollback().await?;
+                return Ok(());
             }
+            Err(e) => {
+                tx.rollback().await?;
+                return Err(e.into());
+            }
+        };
+        tx.commit().await?;
+
+        let series_title =
+            if let Some(series) = Series::select_by_id(conn, character.series_id).await? {
+                series.title
+            } else {
+                t("unknown")
+            };
+        let mut text = t("raid_defeated")
+            .replace("{name}", &character.name)
+            .replace("{series}", &series_title)
+            .replace(
+                "{participants}",
+                &participant_names(conn, &participants).await?.join(", "),
+            );
+        if !without_room.is_empty() {
+            text += &t("raid_no_room").replace(
+                "{participants}",
+                &participant_names(conn, &without_room).await?.join(", "),
+            );
+        }
+
+        reply_message
+            .edit(InputMessage::html(crate::utils::construct_raid_info(
+                i18n,
+                &group_character,
+                participants.len(),
+            )))
+            .await?;
+        message.reply(InputMessage::html(text)).await?;
+    } else {
+        // Show the progress on the spawn message
+        reply_message
+            .edit(
+                InputMessage::html(crate::utils::construct_raid_info(
+                    i18n,
+                    &group_character,
+                    participants.len(),
+                ))
+                .reply_markup(&reply_markup::inline(vec![vec![button::inline(
+                    t("raid_progress_button"),
+                    "raid progress",
+                )]])),
+            )
+            .await?;
+        message
+            .reply(InputMessage::html(
+                t("raid_joined")
+                    .replace("{progress}", &participants.len().to_string())
+                    .replace("{required}", &group_character.required.to_string()),
+            ))
+            .await?;
+    }
+
+    Ok(())
+}
+
+// Closes the raid and gives every participant the character, or XP when they already own it,
+// returns the participants without room for it or `None` when the raid was already closed
+async fn reward_raid(
+    executor: &dyn Executor,
+    group_character: &GroupCharacter,
+    character: &Character,
+    participants: &[i64],
+) -> rbatis::Result<Option<Vec<i64>>> {
+    let group_id = group_character.group_id;
+    if !GroupCharacter::close(executor, group_id, group_character.last_message_id).await? {
+        return Ok(None);
+    }
+
+    let mut without_room = Vec::new();
+    for participant in participants.iter() {
+        if !crate::utils::grant_character(
+            executor,
+            *participant,
+            group_id,
+            character,
+            group_character.variant_id,
+        )
+        .await?
+        {
+            without_room.push(*participant);
         }
     }
 
-    false
+    Ok(Some(without_room))
+}
+
+// The members who joined the raid, in the order they joined
+async fn raid_participants(conn: &mut RBatis, group_id: i64, message_id: i32) -> Result<Vec<i64>> {
+    Ok(RaidParticipant::select_by_raid(conn, group_id, message_id)
+        .await?
+        .into_iter()
+        .map(|participant| participant.user_id)
+        .collect())
+}
+
+async fn participant_names(conn: &mut RBatis, participants: &[i64]) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+
+    for participant in participants.iter() {
+        names.push(match User::select_by_id(conn, *participant).await? {
+            Some(user) => crate::utils::escape_html(user.full_name),
+            None => participant.to_string(),
+        });
+    }
+
+    Ok(names)
 }
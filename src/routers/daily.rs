@@ -0,0 +1,269 @@
+use grammers_client::{types::Chat, Client, InputMessage, Update};
+use grammers_friendly::prelude::*;
+use rand::{thread_rng, Rng};
+use rbatis::executor::Executor;
+
+use crate::{
+    database::models::{Character, DailyStreak, DuelRecord, Group, Series, User, Wallet},
+    middlewares::SendCharacter,
+    modules::{Database, I18n},
+    Config, Result,
+};
+
+pub fn router() -> Router {
+    Router::default()
+        .add_handler(Handler::new_message(
+            daily,
+            macros::command!("/!.", "daily"),
+        ))
+        .add_handler(Handler::new_message(
+            balance,
+            macros::command!("/!.", "balance")
+                .or(macros::command!("/!.", "wallet"))
+                .or(macros::command!("/!.", "profile")),
+        ))
+        .add_handler(Handler::new_message(
+            set_timezone,
+            macros::command!("timezone")
+                .or(macros::command!("tz"))
+                .and(filters::private().or(filters::admin())),
+        ))
+}
+
+async fn daily(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+    let config = data.get_module::<Config>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let chat = update.get_chat().unwrap();
+    let sender = update.get_sender().unwrap();
+    let message = update.get_message().unwrap();
+
+    let conn = db.get_conn();
+    let user_id = sender.id();
+
+    // The user's timezone has priority over the group's one
+    let utc_offset = match User::select_by_id(conn, user_id)
+        .await?
+        .and_then(|user| user.utc_offset)
+    {
+        Some(utc_offset) => utc_offset,
+        None => match chat {
+            Chat::Group(ref group) => Group::select_by_id(conn, group.id())
+                .await?
+                .and_then(|group| group.utc_offset)
+                .unwrap_or(0),
+            _ => 0,
+        },
+    };
+
+    let period_seconds = config.daily.period_hours.max(1) * 3600;
+    let local_now = chrono::Utc::now().timestamp() + utc_offset as i64 * 60;
+    let period = local_now.div_euclid(period_seconds);
+
+    let already_claimed = t("daily_already_claimed").replace(
+        "{time}",
+        &crate::utils::format_duration((period + 1) * period_seconds - local_now),
+    );
+
+    // Spares picking a character, the claim itself is checked again when saved
+    if let Some(last_streak) = DailyStreak::select_by_id(conn, user_id).await? {
+        if last_streak.last_period >= period {
+            message.reply(InputMessage::html(already_claimed)).await?;
+
+            return Ok(());
+        }
+    }
+
+    // Occasionally the reward is a guaranteed character pull, which needs a group collection
+    let is_pull = matches!(chat, Chat::Group(_))
+        && thread_rng().gen_bool(config.daily.pull_chance.clamp(0.0, 1.0));
+
+    let mut pull = None;
+    if is_pull {
+        if let Chat::Group(ref group) = chat {
+            if let Some((character, _)) =
+                SendCharacter::pick_character(conn, Some(group.id()), 0, None).await?
+            {
+                pull = Some((group.id(), character));
+            }
+        }
+    }
+
+    let tx = conn.acquire_begin().await?;
+    let (streak, pulled, amount) = match claim_daily(
+        &tx,
+        &config,
+        user_id,
+        period,
+        pull.as_ref()
+            .map(|(group_id, character)| (*group_id, character)),
+    )
+    .await
+    {
+        Ok(Some(claimed)) => claimed,
+        Ok(None) => {
+            tx.rollback().await?;
+            message.reply(InputMessage::html(already_claimed)).await?;
+
+            return Ok(());
+        }
+        Err(e) => {
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+    tx.commit().await?;
+
+    let mut text = match pull {
+        Some((_, character)) if pulled => {
+            let series_title =
+                if let Some(series) = Series::select_by_id(conn, character.series_id).await? {
+                    series.title
+                } else {
+                    t("unknown")
+                };
+
+            t("daily_pull")
+                .replace("{name}", &character.name)
+                .replace("{series}", &series_title)
+        }
+        _ => t("daily_claimed").replace("{amount}", &amount.to_string()),
+    };
+
+    text += &t("daily_streak").replace("{streak}", &streak.to_string());
+    message.reply(InputMessage::html(text)).await?;
+
+    Ok(())
+}
+
+async fn balance(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let sender = update.get_sender().unwrap();
+    let message = update.get_message().unwrap();
+
+    let conn = db.get_conn();
+
+    let balance = Wallet::select_by_id(conn, sender.id())
+        .await?
+        .map_or(0, |wallet| wallet.balance);
+    let streak = DailyStreak::select_by_id(conn, sender.id())
+        .await?
+        .map_or(0, |daily_streak| daily_streak.streak);
+    let record = DuelRecord::select_by_id(conn, sender.id())
+        .await?
+        .unwrap_or_default();
+
+    message
+        .reply(InputMessage::html(
+            t("balance")
+                .replace("{balance}", &balance.to_string())
+                .replace("{streak}", &streak.to_string())
+                + &t("duel_record")
+                    .replace("{wins}", &record.wins.to_string())
+                    .replace("{losses}", &record.losses.to_string()),
+        ))
+        .await?;
+
+    Ok(())
+}
+
+async fn set_timezone(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let chat = update.get_chat().unwrap();
+    let message = update.get_message().unwrap();
+
+    let splitted = message.text().split_whitespace().collect::<Vec<&str>>();
+
+    let Some(utc_offset) = splitted
+        .get(1)
+        .and_then(|text| crate::utils::parse_utc_offset(text))
+    else {
+        message
+            .reply(InputMessage::html(t("invalid_command").replace(
+                "{cmd}",
+                &crate::utils::escape_html(format!("{} <+HH:MM|-HH:MM>", splitted[0])),
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    let conn = db.get_conn();
+
+    match chat {
+        Chat::User(u) => {
+            if let Some(mut user) = User::select_by_id(conn, u.id()).await? {
+                user.utc_offset = Some(utc_offset);
+                User::update_by_id(conn, &user, user.id).await?;
+            }
+        }
+        Chat::Group(g) => {
+            if let Some(mut group) = Group::select_by_id(conn, g.id()).await? {
+                group.utc_offset = Some(utc_offset);
+                Group::update_by_id(conn, &group, group.id).await?;
+            }
+        }
+        Chat::Channel(_) => return Ok(()),
+    }
+
+    message
+        .reply(InputMessage::html(t("timezone_set").replace(
+            "{timezone}",
+            &crate::utils::format_utc_offset(utc_offset),
+        )))
+        .await?;
+
+    Ok(())
+}
+
+// Claims the period and gives its reward, the pulled character when there is room for it or
+// the coins otherwise, returns the streak, whether the character was given and the coins
+// given, or `None` when the period was already claimed
+async fn claim_daily(
+    executor: &dyn Executor,
+    config: &Config,
+    user_id: i64,
+    period: i64,
+    pull: Option<(i64, &Character)>,
+) -> rbatis::Result<Option<(u32, bool, i64)>> {
+    if !DailyStreak::claim(executor, user_id, period).await? {
+        return Ok(None);
+    }
+
+    let streak = DailyStreak::select_by_id(executor, user_id)
+        .await?
+        .map_or(1, |daily_streak| daily_streak.streak);
+
+    if let Some((group_id, character)) = pull {
+        if crate::utils::grant_character(executor, user_id, group_id, character, None).await? {
+            return Ok(Some((streak, true, 0)));
+        }
+    }
+
+    let amount = daily_reward(
+        config.daily.reward,
+        streak,
+        config.daily.streak_bonus,
+        config.daily.max_streak,
+    );
+    Wallet::apply(executor, user_id, amount, "daily").await?;
+
+    Ok(Some((streak, false, amount)))
+}
+
+fn daily_reward(reward: i64, streak: u32, streak_bonus: f64, max_streak: u32) -> i64 {
+    let multiplier = 1.0 + streak_bonus * streak.min(max_streak).saturating_sub(1) as f64;
+
+    (reward as f64 * multiplier).round() as i64
+}
@@ -0,0 +1,620 @@
+use grammers_client::{button, reply_markup, types::Chat, Client, InputMessage, Update};
+use grammers_friendly::prelude::*;
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
+use rbatis::{executor::Executor, RBatis};
+
+use crate::{
+    database::models::{Character, Duel, DuelRecord, OwnedCharacter, User, Wallet},
+    modules::{Database, I18n},
+    Config, Result,
+};
+
+pub fn router() -> Router {
+    Router::default()
+        .add_handler(Handler::new_message(
+            start_duel,
+            macros::command!("/!.", "duel"),
+        ))
+        .add_handler(Handler::callback_query(
+            pick_character,
+            filters::query("duel pick id:int character_id:int"),
+        ))
+        .add_handler(Handler::callback_query(
+            cancel_duel,
+            filters::query("duel cancel id:int"),
+        ))
+}
+
+async fn start_duel(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+    let config = data.get_module::<Config>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let chat = update.get_chat().unwrap();
+    let sender = update.get_sender().unwrap();
+    let message = update.get_message().unwrap();
+
+    let Chat::Group(group) = chat else {
+        message.reply(InputMessage::html(t("not_a_group"))).await?;
+
+        return Ok(());
+    };
+
+    let conn = db.get_conn();
+
+    let splitted = message.text().split_whitespace().collect::<Vec<&str>>();
+    let stake = splitted
+        .iter()
+        .skip(1)
+        .find_map(|text| text.parse::<i64>().ok())
+        .unwrap_or(0);
+
+    let opponent_id = match splitted.iter().find(|text| text.starts_with('@')) {
+        Some(username) => User::select_by_username(conn, username.trim_start_matches('@'))
+            .await?
+            .map(|user| user.id),
+        None => match message.get_reply().await {
+            Ok(Some(reply_message)) => reply_message.sender().map(|sender| sender.id()),
+            _ => None,
+        },
+    };
+
+    let Some(opponent_id) = opponent_id else {
+        message
+            .reply(InputMessage::html(t("invalid_command").replace(
+                "{cmd}",
+                &crate::utils::escape_html(format!("{} <@user> [stake]", splitted[0])),
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    let challenger_id = sender.id();
+    let group_id = group.id();
+
+    if opponent_id == challenger_id {
+        message
+            .reply(InputMessage::html(t("duel_yourself")))
+            .await?;
+
+        return Ok(());
+    }
+
+    if !(0..=config.duels.max_stake).contains(&stake) {
+        message
+            .reply(InputMessage::html(
+                t("invalid_stake").replace("{max}", &config.duels.max_stake.to_string()),
+            ))
+            .await?;
+
+        return Ok(());
+    }
+
+    let now = chrono::Utc::now().timestamp();
+
+    for user_id in [challenger_id, opponent_id] {
+        if let Some(duel) = Duel::select_by_user(conn, group_id, user_id).await? {
+            if now - duel.created_at < config.duels.timeout {
+                message
+                    .reply(InputMessage::html(t("already_dueling")))
+                    .await?;
+
+                return Ok(());
+            }
+
+            // Stale duels are cancelled so they don't block new ones
+            close_duel(conn, &duel).await?;
+        }
+
+        if OwnedCharacter::select_by_user(conn, user_id, group_id)
+            .await?
+            .is_empty()
+        {
+            message
+                .reply(InputMessage::html(t("duel_no_characters")))
+                .await?;
+
+            return Ok(());
+        }
+    }
+
+    let seed = thread_rng().gen::<i64>();
+
+    let mut duel = Duel {
+        id: 0,
+        group_id,
+        challenger_id,
+        opponent_id,
+        stake,
+        seed,
+        challenger_character: None,
+        opponent_character: None,
+        created_at: now,
+    };
+    duel.id = Duel::insert_new(conn, &duel).await?;
+
+    message
+        .reply(
+            InputMessage::html(construct_duel_info(conn, &i18n, &duel).await?).reply_markup(
+                &reply_markup::inline(duel_buttons(conn, &i18n, &duel).await?),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+async fn pick_character(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+    let config = data.get_module::<Config>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let query = update.get_query().unwrap();
+    let sender = query.sender();
+    let message = query.load_message().await?;
+
+    let splitted = utils::split_query(query.data());
+    let duel_id = splitted[2].parse::<i64>().unwrap();
+    let character_id = splitted[3].parse::<i64>().unwrap();
+
+    let conn = db.get_conn();
+
+    let Some(duel) = Duel::select_by_id(conn, duel_id).await? else {
+        query.answer().alert(t("unknown_duel")).send().await?;
+
+        return Ok(());
+    };
+
+    let user_id = sender.id();
+    let (is_challenger, pick) = if user_id == duel.challenger_id {
+        (true, duel.challenger_character)
+    } else if user_id == duel.opponent_id {
+        (false, duel.opponent_character)
+    } else {
+        query.answer().alert(t("not_your_duel")).send().await?;
+
+        return Ok(());
+    };
+
+    if pick.is_some() {
+        query
+            .answer()
+            .alert(t("duel_already_picked"))
+            .send()
+            .await?;
+
+        return Ok(());
+    }
+
+    if OwnedCharacter::select_by_id(conn, user_id, duel.group_id, character_id)
+        .await?
+        .is_none()
+    {
+        query
+            .answer()
+            .alert(t("character_not_owned"))
+            .send()
+            .await?;
+
+        return Ok(());
+    }
+
+    let tx = conn.acquire_begin().await?;
+    let key = match save_pick(&tx, &duel, user_id, is_challenger, character_id).await {
+        Ok(key) => key,
+        Err(e) => {
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+
+    if key != "duel_picked" {
+        tx.rollback().await?;
+        query.answer().alert(t(key)).send().await?;
+
+        return Ok(());
+    }
+    tx.commit().await?;
+
+    query.answer().text(t(key)).send().await?;
+
+    // The other side may have picked meanwhile, or the duel been cancelled
+    let Some(duel) = Duel::select_by_id(conn, duel_id).await? else {
+        return Ok(());
+    };
+
+    if let (Some(challenger_character), Some(opponent_character)) =
+        (duel.challenger_character, duel.opponent_character)
+    {
+        let (Some(challenger_character), Some(opponent_character)) = (
+            Character::select_by_id(conn, challenger_character).await?,
+            Character::select_by_id(conn, opponent_character).await?,
+        ) else {
+            close_duel(conn, &duel).await?;
+            message
+                .edit(InputMessage::html(t("duel_cancelled")))
+                .await?;
+
+            return Ok(());
+        };
+
+        let challenger = fighter(
+            conn,
+            &config,
+            &duel,
+            duel.challenger_id,
+            &challenger_character,
+        )
+        .await?;
+        let opponent = fighter(conn, &config, &duel, duel.opponent_id, &opponent_character).await?;
+        let outcome = resolve_duel(challenger, opponent, config.duels.luck, duel.seed as u64);
+
+        let (winner_id, loser_id) = if outcome.challenger_won {
+            (duel.challenger_id, duel.opponent_id)
+        } else {
+            (duel.opponent_id, duel.challenger_id)
+        };
+
+        let tx = conn.acquire_begin().await?;
+        match finish_duel(&tx, &duel, winner_id, loser_id).await {
+            Ok(true) => tx.commit().await?,
+            // Whoever picked last at the same time already resolved it
+            Ok(false) => {
+                tx.rollback().await?;
+                return Ok(());
+            }
+            Err(e) => {
+                tx.rollback().await?;
+                return Err(e.into());
+            }
+        }
+
+        let mut text = construct_duel_info(conn, &i18n, &duel).await?
+            + "\n\n"
+            + &t("duel_result")
+                .replace(
+                    "{challenger_character}",
+                    &crate::utils::escape_html(&challenger_character.name),
+                )
+                .replace("{challenger_power}", &outcome.challenger_power.to_string())
+                .replace(
+                    "{opponent_character}",
+                    &crate::utils::escape_html(&opponent_character.name),
+                )
+                .replace("{opponent_power}", &outcome.opponent_power.to_string())
+                .replace(
+                    "{winner}",
+                    &crate::utils::mention_user(conn, winner_id).await?,
+                );
+        if duel.stake > 0 {
+            text += &t("duel_prize").replace("{prize}", &(duel.stake * 2).to_string());
+        }
+
+        message.edit(InputMessage::html(text)).await?;
+    } else {
+        message
+            .edit(
+                InputMessage::html(construct_duel_info(conn, &i18n, &duel).await?).reply_markup(
+                    &reply_markup::inline(duel_buttons(conn, &i18n, &duel).await?),
+                ),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn cancel_duel(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let query = update.get_query().unwrap();
+    let sender = query.sender();
+    let message = query.load_message().await?;
+
+    let splitted = utils::split_query(query.data());
+    let duel_id = splitted[2].parse::<i64>().unwrap();
+
+    let conn = db.get_conn();
+
+    let Some(duel) = Duel::select_by_id(conn, duel_id).await? else {
+        query.answer().alert(t("unknown_duel")).send().await?;
+
+        return Ok(());
+    };
+
+    if sender.id() != duel.challenger_id && sender.id() != duel.opponent_id {
+        query.answer().alert(t("not_your_duel")).send().await?;
+
+        return Ok(());
+    }
+
+    close_duel(conn, &duel).await?;
+    message
+        .edit(InputMessage::html(t("duel_cancelled")))
+        .await?;
+
+    Ok(())
+}
+
+// Removes a duel, giving back the stakes already paid
+async fn close_duel(conn: &mut RBatis, duel: &Duel) -> Result<()> {
+    let tx = conn.acquire_begin().await?;
+    match refund_duel(&tx, duel).await {
+        Ok(_) => tx.commit().await?,
+        Err(e) => {
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    }
+
+    Ok(())
+}
+
+async fn refund_duel(executor: &dyn Executor, duel: &Duel) -> rbatis::Result<()> {
+    // The stakes are refunded from the saved picks, a pick made since the duel was read counts
+    let Some(duel) = Duel::select_by_id(executor, duel.id).await? else {
+        return Ok(());
+    };
+    if !Duel::remove(executor, duel.id).await? {
+        return Ok(());
+    }
+
+    if duel.stake > 0 {
+        for (user_id, pick) in [
+            (duel.challenger_id, duel.challenger_character),
+            (duel.opponent_id, duel.opponent_character),
+        ] {
+            if pick.is_some() {
+                Wallet::apply(
+                    executor,
+                    user_id,
+                    duel.stake,
+                    &format!("duel refund {}", duel.id),
+                )
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn save_pick(
+    executor: &dyn Executor,
+    duel: &Duel,
+    user_id: i64,
+    is_challenger: bool,
+    character_id: i64,
+) -> rbatis::Result<&'static str> {
+    // Only the first pick of each side is saved and pays the stake
+    if !Duel::set_pick(executor, duel.id, is_challenger, character_id).await? {
+        return Ok("duel_already_picked");
+    }
+
+    if duel.stake > 0
+        && !Wallet::apply(
+            executor,
+            user_id,
+            -duel.stake,
+            &format!("duel stake {}", duel.id),
+        )
+        .await?
+    {
+        return Ok("not_enough_coins");
+    }
+
+    Ok("duel_picked")
+}
+
+async fn finish_duel(
+    executor: &dyn Executor,
+    duel: &Duel,
+    winner_id: i64,
+    loser_id: i64,
+) -> rbatis::Result<bool> {
+    if !Duel::remove(executor, duel.id).await? {
+        return Ok(false);
+    }
+
+    if duel.stake > 0 {
+        Wallet::apply(
+            executor,
+            winner_id,
+            duel.stake * 2,
+            &format!("duel prize {}", duel.id),
+        )
+        .await?;
+    }
+
+    DuelRecord::add_result(executor, winner_id, true).await?;
+    DuelRecord::add_result(executor, loser_id, false).await?;
+
+    Ok(true)
+}
+
+async fn fighter(
+    conn: &mut RBatis,
+    config: &Config,
+    duel: &Duel,
+    user_id: i64,
+    character: &Character,
+) -> Result<Fighter> {
+    let level = if config.duels.use_level {
+        OwnedCharacter::select_by_id(conn, user_id, duel.group_id, character.id)
+            .await?
+            .map(|owned_character| owned_character.level)
+    } else {
+        None
+    };
+
+    Ok(Fighter {
+        stars: character.stars,
+        level,
+    })
+}
+
+async fn construct_duel_info(conn: &mut RBatis, i18n: &I18n, duel: &Duel) -> Result<String> {
+    let t = |key| i18n.get(key);
+
+    let picked = |pick: Option<i64>| if pick.is_some() { "✅" } else { "⏳" };
+
+    let mut text = t("duel")
+        .replace(
+            "{challenger}",
+            &crate::utils::mention_user(conn, duel.challenger_id).await?,
+        )
+        .replace(
+            "{opponent}",
+            &crate::utils::mention_user(conn, duel.opponent_id).await?,
+        )
+        .replace("{challenger_status}", picked(duel.challenger_character))
+        .replace("{opponent_status}", picked(duel.opponent_character));
+    if duel.stake > 0 {
+        text += &t("duel_stake").replace("{stake}", &duel.stake.to_string());
+    }
+
+    Ok(text)
+}
+
+// Each side gets a row of its own characters, the picks are checked against the presser
+async fn duel_buttons(
+    conn: &mut RBatis,
+    i18n: &I18n,
+    duel: &Duel,
+) -> Result<Vec<Vec<button::Inline>>> {
+    let mut buttons = Vec::new();
+
+    for (symbol, user_id, pick) in [
+        ("🔴", duel.challenger_id, duel.challenger_character),
+        ("🔵", duel.opponent_id, duel.opponent_character),
+    ] {
+        if pick.is_some() {
+            continue;
+        }
+
+        let mut row = Vec::new();
+        for owned_character in OwnedCharacter::select_by_user(conn, user_id, duel.group_id)
+            .await?
+            .iter()
+        {
+            if let Some(character) =
+                Character::select_by_id(conn, owned_character.character_id).await?
+            {
+                row.push(button::inline(
+                    format!(
+                        "{0} {1} {2}⭐",
+                        symbol,
+                        crate::utils::shorten_text(&character.name, 15),
+                        character.stars
+                    ),
+                    format!("duel pick {0} {1}", duel.id, character.id),
+                ));
+            }
+        }
+        buttons.extend(utils::split_kb_to_columns(row, 3));
+    }
+
+    buttons.push(vec![button::inline(
+        i18n.get("cancel_button"),
+        format!("duel cancel {}", duel.id),
+    )]);
+
+    Ok(buttons)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fighter {
+    pub stars: u8,
+    pub level: Option<u32>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DuelOutcome {
+    pub challenger_power: u32,
+    pub opponent_power: u32,
+    pub challenger_won: bool,
+}
+
+// Each side's power comes from the stars, the level (when used) and a luck roll taken
+// from the seed, so a duel always ends the same way; the challenger must beat the opponent
+pub fn resolve_duel(challenger: Fighter, opponent: Fighter, luck: u32, seed: u64) -> DuelOutcome {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let challenger_power = base_power(challenger) + rng.gen_range(0..=luck);
+    let opponent_power = base_power(opponent) + rng.gen_range(0..=luck);
+
+    DuelOutcome {
+        challenger_power,
+        opponent_power,
+        challenger_won: challenger_power > opponent_power,
+    }
+}
+
+fn base_power(fighter: Fighter) -> u32 {
+    fighter.stars as u32 * 20 + fighter.level.unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fighter(stars: u8, level: Option<u32>) -> Fighter {
+        Fighter { stars, level }
+    }
+
+    #[test]
+    fn same_seed_same_outcome() {
+        let challenger = fighter(3, Some(5));
+        let opponent = fighter(3, Some(7));
+
+        for seed in 0..50 {
+            assert_eq!(
+                resolve_duel(challenger, opponent, 30, seed),
+                resolve_duel(challenger, opponent, 30, seed)
+            );
+        }
+    }
+
+    #[test]
+    fn more_stars_win_without_luck() {
+        let outcome = resolve_duel(fighter(4, None), fighter(2, None), 0, 42);
+
+        assert_eq!(outcome.challenger_power, 80);
+        assert_eq!(outcome.opponent_power, 40);
+        assert!(outcome.challenger_won);
+    }
+
+    #[test]
+    fn level_breaks_even_stars() {
+        let outcome = resolve_duel(fighter(3, Some(1)), fighter(3, Some(10)), 0, 7);
+
+        assert!(!outcome.challenger_won);
+    }
+
+    #[test]
+    fn ties_go_to_the_opponent() {
+        let outcome = resolve_duel(fighter(3, None), fighter(3, None), 0, 1);
+
+        assert_eq!(outcome.challenger_power, outcome.opponent_power);
+        assert!(!outcome.challenger_won);
+    }
+
+    #[test]
+    fn luck_stays_within_bounds() {
+        for seed in 0..200 {
+            let outcome = resolve_duel(fighter(1, Some(2)), fighter(5, None), 30, seed);
+
+            assert!((22..=52).contains(&outcome.challenger_power));
+            assert!((100..=130).contains(&outcome.opponent_power));
+            assert!(!outcome.challenger_won);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use grammers_client::{button, reply_markup, Client, InputMessage, Update};
+use grammers_friendly::prelude::*;
+use rbatis::RBatis;
+
+use crate::{
+    database::models::{Event, Series},
+    modules::{Database, I18n},
+    Result,
+};
+
+pub fn router() -> Router {
+    Router::default().add_handler(Handler::new_message(
+        see_events,
+        macros::command!("/!.", "event").or(macros::command!("events")),
+    ))
+}
+
+async fn see_events(client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let message = update.get_message().unwrap();
+
+    let conn = db.get_conn();
+    let now = chrono::Utc::now().timestamp();
+
+    let events = Event::select_not_ended(conn, now).await?;
+    let is_sudoer = crate::filters::sudoers().is_ok(client, update).await;
+
+    let mut text = if events.is_empty() {
+        t("no_events")
+    } else {
+        t("events_title") + "\n\n"
+    };
+    let mut buttons = Vec::new();
+
+    for event in events.iter() {
+        text += &construct_event_info(conn, &i18n, event, now).await?;
+
+        if is_sudoer {
+            buttons.push(vec![button::inline(
+                format!("{0} {1}", t("delete_button"), event.name),
+                format!("event delete {}", event.id),
+            )]);
+        }
+    }
+
+    if is_sudoer {
+        buttons.push(vec![button::inline(t("add_button"), "event add")]);
+    }
+
+    let mut input_message = InputMessage::html(text);
+    if !buttons.is_empty() {
+        input_message = input_message.reply_markup(&reply_markup::inline(buttons));
+    }
+
+    message.reply(input_message).await?;
+
+    Ok(())
+}
+
+async fn construct_event_info(
+    conn: &mut RBatis,
+    i18n: &I18n,
+    event: &Event,
+    now: i64,
+) -> Result<String> {
+    let t = |key| i18n.get(key);
+
+    let mut text = format!(
+        "🎉 <code>{0}</code>. <b>{1}</b> — {2}\n📅 <code>{3}</code> → <code>{4}</code> (UTC)\n🚀 {5}%\n",
+        event.id,
+        crate::utils::escape_html(&event.name),
+        if event.is_active(now) {
+            t("event_active")
+        } else {
+            t("event_upcoming")
+        },
+        crate::utils::format_timestamp(event.starts_at),
+        crate::utils::format_timestamp(event.ends_at),
+        event.boost,
+    );
+
+    if !event.boosted_series.is_empty() {
+        let mut titles = Vec::new();
+        for series_id in event.boosted_series.iter() {
+            if let Some(series) = Series::select_by_id(conn, *series_id).await? {
+                titles.push(format!(
+                    "{0} <i>{1}</i>",
+                    crate::utils::media_type_symbol(&series.media_type),
+                    series.title
+                ));
+            }
+        }
+
+        text += &format!("📚 {}\n", titles.join(", "));
+    }
+
+    if !event.boosted_characters.is_empty() {
+        text += &t("event_boosted_characters")
+            .replace("{count}", &event.boosted_characters.len().to_string());
+    }
+
+    if !event.exclusive_characters.is_empty() {
+        text += &t("event_exclusive_characters")
+            .replace("{count}", &event.exclusive_characters.len().to_string());
+    }
+
+    Ok(text + "\n")
+}
@@ -0,0 +1,221 @@
+use grammers_client::{grammers_tl_types as tl, Client, Update};
+use grammers_friendly::prelude::*;
+
+use crate::{
+    database::models::{Character, PhotoReference, Series},
+    modules::Database,
+    Result,
+};
+
+const PAGE_SIZE: u16 = 10;
+
+pub fn router() -> Router {
+    Router::default().add_handler(Handler::inline_query(
+        answer_inline,
+        crate::filters::inline(),
+    ))
+}
+
+// Answers `@bot <name>` with the matching characters and series, the offset is the next page.
+// Only photos already uploaded are shown, the others are uploaded in the background so the
+// answer doesn't wait for them
+async fn answer_inline(client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let Update::InlineQuery(query) = update else {
+        return Ok(());
+    };
+
+    let mut db = data.get_module::<Database>().unwrap();
+    let conn = db.get_conn();
+
+    let text = query.text().trim().to_string();
+    let page = query.offset().parse::<u16>().unwrap_or(1).max(1);
+
+    let mut characters = Vec::new();
+    let mut series_list = Vec::new();
+    let mut next_offset = String::new();
+
+    if !text.is_empty() {
+        characters = Character::search(conn, &text, page, PAGE_SIZE).await?;
+        series_list = Series::search(conn, &text, page, PAGE_SIZE).await?;
+
+        if characters.len() == PAGE_SIZE as usize || series_list.len() == PAGE_SIZE as usize {
+            next_offset = (page + 1).to_string();
+        }
+    }
+
+    // Every result has its article, shown when its photo is not uploaded yet
+    let mut entries = Vec::new();
+    for character in characters.iter() {
+        let caption = crate::utils::construct_character_info(
+            character,
+            Series::select_by_id(conn, character.series_id).await?,
+        );
+        entries.push((
+            format!("character_{}", character.id),
+            character.name.clone(),
+            caption,
+        ));
+    }
+    for series in series_list.iter() {
+        let caption = crate::utils::construct_series_info(
+            series,
+            Character::count_by_series(conn, series.id).await?,
+            true,
+        );
+        entries.push((
+            format!("series_{}", series.id),
+            series.title.clone(),
+            caption,
+        ));
+    }
+
+    let mut results = Vec::new();
+    let mut references = Vec::new();
+    let mut uncached = Vec::new();
+    for (key, title, caption) in entries.iter() {
+        match PhotoReference::select_by_key(conn, key).await? {
+            Some(reference) => {
+                results.push(photo_result(key.clone(), input_photo(&reference), caption));
+                references.push(reference);
+            }
+            None => {
+                results.push(article_result(key.clone(), title, caption));
+                uncached.push(key.clone());
+            }
+        }
+    }
+
+    // Telegram would keep showing the articles after the photos are uploaded
+    let cache_time = if uncached.is_empty() { 300 } else { 0 };
+    match query
+        .answer(results)
+        .cache_time(cache_time)
+        .next_offset(next_offset.clone())
+        .send()
+        .await
+    {
+        // The stored references expired, the page is shown without photos while they're
+        // uploaded again
+        Err(e) if e.is("FILE_REFERENCE_*") => {
+            for reference in references.iter() {
+                PhotoReference::delete_by_key(conn, &reference.key).await?;
+                uncached.push(reference.key.clone());
+            }
+
+            query
+                .answer(
+                    entries
+                        .iter()
+                        .map(|(key, title, caption)| article_result(key.clone(), title, caption))
+                        .collect::<Vec<_>>(),
+                )
+                .cache_time(0)
+                .next_offset(next_offset)
+                .send()
+                .await?;
+        }
+        result => result?,
+    }
+
+    let characters = characters
+        .into_iter()
+        .filter(|character| {
+            character.image_hash.is_some()
+                && uncached.contains(&format!("character_{}", character.id))
+        })
+        .collect::<Vec<_>>();
+    let series_list = series_list
+        .into_iter()
+        .filter(|series| {
+            series.banner_hash.is_some() && uncached.contains(&format!("series_{}", series.id))
+        })
+        .collect::<Vec<_>>();
+    if !characters.is_empty() || !series_list.is_empty() {
+        tokio::spawn(upload_photos(
+            client.clone(),
+            db.clone(),
+            characters,
+            series_list,
+        ));
+    }
+
+    Ok(())
+}
+
+// Uploads the photos missing from an answer, so the next ones can show them
+async fn upload_photos(
+    mut client: Client,
+    mut db: Database,
+    characters: Vec<Character>,
+    series_list: Vec<Series>,
+) {
+    let conn = db.get_conn();
+
+    for character in characters {
+        let id = character.id;
+        if let Err(e) = crate::utils::character_reference(&mut client, character, None, conn).await
+        {
+            log::warn!("failed to upload the photo of character {}: {}", id, e);
+        }
+    }
+
+    for series in series_list.iter() {
+        if let Err(e) = crate::utils::series_reference(&mut client, series, conn).await {
+            log::warn!("failed to upload the banner of series {}: {}", series.id, e);
+        }
+    }
+}
+
+fn input_photo(reference: &PhotoReference) -> tl::enums::InputPhoto {
+    tl::types::InputPhoto {
+        id: reference.photo_id,
+        access_hash: reference.access_hash,
+        file_reference: reference.file_reference.clone(),
+    }
+    .into()
+}
+
+fn photo_result(
+    id: String,
+    photo: tl::enums::InputPhoto,
+    caption: &str,
+) -> tl::enums::InputBotInlineResult {
+    let (message, entities) = grammers_client::parsers::parse_html_message(caption);
+
+    tl::types::InputBotInlineResultPhoto {
+        id,
+        r#type: "photo".to_string(),
+        photo,
+        send_message: tl::types::InputBotInlineMessageMediaAuto {
+            invert_media: false,
+            message,
+            entities: Some(entities),
+            reply_markup: None,
+        }
+        .into(),
+    }
+    .into()
+}
+
+fn article_result(id: String, title: &str, caption: &str) -> tl::enums::InputBotInlineResult {
+    let (message, entities) = grammers_client::parsers::parse_html_message(caption);
+
+    tl::types::InputBotInlineResult {
+        id,
+        r#type: "article".to_string(),
+        title: Some(title.to_string()),
+        description: Some(message.lines().next().unwrap_or_default().to_string()),
+        url: None,
+        thumb: None,
+        content: None,
+        send_message: tl::types::InputBotInlineMessageText {
+            no_webpage: true,
+            invert_media: false,
+            message,
+            entities: Some(entities),
+            reply_markup: None,
+        }
+        .into(),
+    }
+    .into()
+}
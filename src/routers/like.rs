@@ -0,0 +1,325 @@
+use grammers_client::{button, reply_markup, Client, InputMessage, Update};
+use grammers_friendly::prelude::*;
+
+use crate::{
+    database::models::{Character, Like, LikeKind, Media, Series},
+    modules::{Database, I18n},
+    Result,
+};
+
+const LIKES_PER_PAGE: u16 = 15;
+const RANKING_SIZE: u16 = 10;
+
+// The days the recent rankings go back, `0` ranks every like
+const PERIODS: [i64; 3] = [0, 30, 7];
+const MEDIA_TYPES: [&str; 7] = [
+    "anime",
+    "game",
+    "manga",
+    "manhua",
+    "manhwa",
+    "light_novel",
+    "visual_novel",
+];
+
+pub fn router() -> Router {
+    Router::default()
+        .add_handler(Handler::new_message(
+            list_likes,
+            macros::command!("/!.", "likes"),
+        ))
+        .add_handler(Handler::callback_query(
+            list_likes,
+            filters::query("likes sender:int page:int"),
+        ))
+        .add_handler(Handler::new_message(
+            popular,
+            macros::command!("/!.", "popular"),
+        ))
+        .add_handler(Handler::callback_query(
+            popular,
+            filters::query("popular sender:int kind:str period:int media:str"),
+        ))
+}
+
+// The characters and series the user liked, the latest first
+async fn list_likes(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+    let conn = db.get_conn();
+
+    let query = update.get_query();
+    let sender = update.get_sender().unwrap();
+    let sender_id = sender.id();
+    let message = if let Some(ref query) = query {
+        query.load_message().await?
+    } else {
+        update.get_message().unwrap()
+    };
+
+    let mut page = 1;
+    if let Some(ref query) = query {
+        let splitted = utils::split_query(query.data());
+
+        if let Ok(user_id) = splitted[1].parse::<i64>() {
+            if user_id != sender_id {
+                return Ok(());
+            }
+        }
+
+        page = splitted[2].parse::<u16>().unwrap_or(1).max(1);
+    }
+
+    let total_likes = Like::count_by_user(conn, sender_id).await?;
+    if total_likes == 0 {
+        message.reply(InputMessage::html(t("no_likes"))).await?;
+
+        return Ok(());
+    }
+    let total_pages = ((total_likes as f64) / (LIKES_PER_PAGE as f64)).ceil() as u16;
+    let page = page.min(total_pages);
+
+    let mut text = t("likes_title").replace("{count}", &total_likes.to_string()) + "\n\n";
+    for like in Like::select_page_by_user(conn, sender_id, page, LIKES_PER_PAGE).await? {
+        match like.kind {
+            LikeKind::Character => {
+                if let Some(character) = Character::select_by_id(conn, like.target_id).await? {
+                    text += &crate::utils::construct_character_partial_info(&character, false, 0);
+                }
+            }
+            LikeKind::Series => {
+                if let Some(series) = Series::select_by_id(conn, like.target_id).await? {
+                    text += &format!(
+                        "{0} <code>{1}</code>. <b>{2}</b>\n",
+                        crate::utils::media_type_symbol(&series.media_type),
+                        series.id,
+                        crate::utils::escape_html(&series.title)
+                    );
+                }
+            }
+        }
+    }
+    text += &format!("\n🔖 | {}/{}", page, total_pages);
+
+    let mut buttons = Vec::new();
+    if page > 1 {
+        buttons.push(button::inline(
+            "⬅",
+            format!("likes {0} {1}", sender_id, page - 1),
+        ));
+    }
+    if page < total_pages {
+        buttons.push(button::inline(
+            "➡",
+            format!("likes {0} {1}", sender_id, page + 1),
+        ));
+    }
+
+    let mut input_message = InputMessage::html(text);
+    if !buttons.is_empty() {
+        input_message = input_message.reply_markup(&reply_markup::inline(vec![buttons]));
+    }
+
+    if query.is_some() {
+        message.edit(input_message).await?;
+    } else {
+        message.reply(input_message).await?;
+    }
+
+    Ok(())
+}
+
+// Rankings of the most liked characters or series, of all time or of the last days, the
+// entries open their cards
+async fn popular(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+    let conn = db.get_conn();
+
+    let query = update.get_query();
+    let sender = update.get_sender().unwrap();
+    let sender_id = sender.id();
+    let message = if let Some(ref query) = query {
+        query.load_message().await?
+    } else {
+        update.get_message().unwrap()
+    };
+
+    let mut kind = LikeKind::Character;
+    let mut period = 0;
+    let mut media_name = "all";
+
+    let splitted = query
+        .as_ref()
+        .map(|query| utils::split_query(query.data()))
+        .unwrap_or_default();
+    if splitted.len() >= 5 {
+        if let Ok(user_id) = splitted[1].parse::<i64>() {
+            if user_id != sender_id {
+                return Ok(());
+            }
+        }
+
+        if splitted[2] == "series" {
+            kind = LikeKind::Series;
+        }
+        if let Some(days) = splitted[3]
+            .parse::<i64>()
+            .ok()
+            .filter(|days| PERIODS.contains(days))
+        {
+            period = days;
+        }
+        if let Some(name) = MEDIA_TYPES.iter().find(|name| **name == splitted[4]) {
+            media_name = *name;
+        }
+    }
+
+    let kind_name = match kind {
+        LikeKind::Character => "character",
+        LikeKind::Series => "series",
+    };
+    let media = crate::search::parse_enum::<Media>(media_name);
+    let since = if period > 0 {
+        chrono::Utc::now().timestamp() - period * 24 * 60 * 60
+    } else {
+        0
+    };
+
+    let period_label = |days: i64| {
+        if days > 0 {
+            t("popular_last_days").replace("{days}", &days.to_string())
+        } else {
+            t("popular_all_time")
+        }
+    };
+    let media_label = |name: &str| {
+        if name == "all" {
+            i18n.get("all_media_button")
+        } else {
+            i18n.get(format!("{}_button", name))
+        }
+    };
+
+    let mut text = format!(
+        "{0}\n<i>{1} | {2}</i>\n\n",
+        match kind {
+            LikeKind::Character => t("popular_characters"),
+            LikeKind::Series => t("popular_series"),
+        },
+        period_label(period),
+        media_label(media_name)
+    );
+
+    let mut entries = Vec::new();
+    for (position, ranking) in
+        Like::select_ranking(conn, &kind, since, media.as_ref(), RANKING_SIZE)
+            .await?
+            .into_iter()
+            .enumerate()
+    {
+        let (name, callback) = match kind {
+            LikeKind::Character => match Character::select_by_id(conn, ranking.target_id).await? {
+                Some(character) => (character.name, format!("char {}", character.id)),
+                None => continue,
+            },
+            LikeKind::Series => match Series::select_by_id(conn, ranking.target_id).await? {
+                Some(series) => (
+                    series.title,
+                    format!("series {0} {1} {2}", series.id, sender_id, 1),
+                ),
+                None => continue,
+            },
+        };
+
+        text += &format!(
+            "<b>{0}.</b> {1} — ❤ <code>{2}</code>\n",
+            position + 1,
+            crate::utils::escape_html(&name),
+            ranking.likes
+        );
+        entries.push(button::inline(
+            format!(
+                "{0}. {1}",
+                position + 1,
+                crate::utils::shorten_text(name, 24)
+            ),
+            callback,
+        ));
+    }
+
+    if entries.is_empty() {
+        text += &t("popular_empty");
+    }
+
+    // The current choice of each row is marked
+    let option = |label: String, is_current: bool| {
+        if is_current {
+            format!("• {} •", label)
+        } else {
+            label
+        }
+    };
+
+    let mut buttons = utils::split_kb_to_columns(entries, 2);
+    buttons.push(
+        [
+            ("character", "popular_characters_button"),
+            ("series", "popular_series_button"),
+        ]
+        .into_iter()
+        .map(|(name, key)| {
+            button::inline(
+                option(i18n.get(key), name == kind_name),
+                format!(
+                    "popular {0} {1} {2} {3}",
+                    sender_id, name, period, media_name
+                ),
+            )
+        })
+        .collect(),
+    );
+    buttons.push(
+        PERIODS
+            .into_iter()
+            .map(|days| {
+                button::inline(
+                    option(period_label(days), days == period),
+                    format!(
+                        "popular {0} {1} {2} {3}",
+                        sender_id, kind_name, days, media_name
+                    ),
+                )
+            })
+            .collect(),
+    );
+    buttons.extend(utils::split_kb_to_columns(
+        std::iter::once("all")
+            .chain(MEDIA_TYPES)
+            .map(|name| {
+                button::inline(
+                    option(media_label(name), name == media_name),
+                    format!(
+                        "popular {0} {1} {2} {3}",
+                        sender_id, kind_name, period, name
+                    ),
+                )
+            })
+            .collect(),
+        4,
+    ));
+
+    let input_message = InputMessage::html(text).reply_markup(&reply_markup::inline(buttons));
+
+    if query.is_some() {
+        message.edit(input_message).await?;
+    } else {
+        message.reply(input_message).await?;
+    }
+
+    Ok(())
+}
@@ -4,8 +4,9 @@ use grammers_client::{
 use grammers_friendly::prelude::*;
 
 use crate::{
-    database::models::{Character, Series, UserCharacters},
+    database::models::{Character, CharacterVariant, OwnedCharacter, Series},
     modules::{Database, I18n},
+    utils::PhotoSource,
     Result,
 };
 
@@ -52,74 +53,99 @@ async fn list_characters_individually(
                 let conn = db.get_conn();
                 let sender_id = sender.id();
 
-                if let Some(user_characters) =
-                    UserCharacters::select_by_id(conn, sender.id(), group.id()).await?
-                {
-                    let index = {
-                        if let Some(ref query) = query {
-                            let splitted = utils::split_query(query.data());
+                let owned_characters =
+                    OwnedCharacter::select_by_user(conn, sender_id, group.id()).await?;
 
-                            if let Ok(user_id) = splitted[1].parse::<i64>() {
-                                if user_id != sender_id {
-                                    return Ok(());
-                                }
-                            }
+                let index = {
+                    if let Some(ref query) = query {
+                        let splitted = utils::split_query(query.data());
 
-                            splitted[2].parse::<usize>().unwrap_or(1)
-                        } else {
-                            1
-                        }
-                    };
-
-                    if let Some(character_id) = user_characters.characters_id.get(index - 1) {
-                        if let Some(character) =
-                            Character::select_by_id(conn, *character_id).await?
-                        {
-                            let total = user_characters.characters_id.len();
-
-                            let mut caption = crate::utils::construct_character_info(
-                                &character,
-                                Series::select_by_id(conn, character.series_id).await?,
-                            );
-                            caption += &format!("\n\n🔖 | {}/{}", index, total);
-
-                            let mut buttons = Vec::new();
-
-                            if index > 1 {
-                                buttons.push(button::inline(
-                                    "⬅",
-                                    format!("list {0} {1}", sender_id, index - 1),
-                                ));
-                            }
-                            if index < total {
-                                buttons.push(button::inline(
-                                    "➡",
-                                    format!("list {0} {1}", sender_id, index + 1),
-                                ));
+                        if let Ok(user_id) = splitted[1].parse::<i64>() {
+                            if user_id != sender_id {
+                                return Ok(());
                             }
+                        }
 
-                            let mut input_message = InputMessage::html(caption);
-                            if buttons.len() >= 1 {
-                                input_message = input_message
-                                    .reply_markup(&reply_markup::inline(vec![buttons]));
-                            }
+                        splitted[2].parse::<usize>().unwrap_or(1)
+                    } else {
+                        1
+                    }
+                };
 
-                            if let Some(file) =
-                                crate::utils::upload_photo(client, character, conn).await?
-                            {
-                                input_message = input_message.photo(file);
+                if let Some(owned_character) = index
+                    .checked_sub(1)
+                    .and_then(|index| owned_characters.get(index))
+                {
+                    if let Some(character) =
+                        Character::select_by_id(conn, owned_character.character_id).await?
+                    {
+                        let total = owned_characters.len();
+
+                        // Collected alternate art is shown instead of the default one
+                        let variant = match owned_character.variant_id {
+                            Some(variant_id) => {
+                                CharacterVariant::select_by_id(conn, variant_id).await?
                             }
+                            None => None,
+                        };
+                        let shown = variant
+                            .as_ref()
+                            .map_or_else(|| character.clone(), |variant| variant.apply(&character));
+
+                        let mut caption = crate::utils::construct_character_info(
+                            &shown,
+                            Series::select_by_id(conn, character.series_id).await?,
+                        );
+                        caption += &format!(
+                            "\n{}\n\n🔖 | {}/{}",
+                            crate::utils::construct_owned_info(owned_character),
+                            index,
+                            total
+                        );
+
+                        let mut buttons = Vec::new();
+
+                        if index > 1 {
+                            buttons.push(button::inline(
+                                "⬅",
+                                format!("list {0} {1}", sender_id, index - 1),
+                            ));
+                        }
+                        if index < total {
+                            buttons.push(button::inline(
+                                "➡",
+                                format!("list {0} {1}", sender_id, index + 1),
+                            ));
+                        }
 
-                            if query.is_some() {
-                                message.edit(input_message).await?;
-                            } else {
-                                message.reply(input_message).await?;
-                            }
+                        let mut input_message = InputMessage::html(caption);
+                        if buttons.len() >= 1 {
+                            input_message =
+                                input_message.reply_markup(&reply_markup::inline(vec![buttons]));
                         }
-                    } else {
-                        message
-                            .reply(InputMessage::html(t("no_characters")))
-                            .await?;
+
+                        let message = &message;
+                        let is_query = query.is_some();
+                        crate::utils::send_photo(
+                            client,
+                            conn,
+                            PhotoSource::Character(&character, variant.as_ref()),
+                            |file| {
+                                let input_message = match file {
+                                    Some(file) => input_message.clone().copy_media(&file),
+                                    None => input_message.clone(),
+                                };
+
+                                async move {
+                                    if is_query {
+                                        message.edit(input_message).await
+                                    } else {
+                                        message.reply(input_message).await.map(|_| ())
+                                    }
+                                }
+                            },
+                        )
+                        .await?;
                     }
                 } else {
                     message
@@ -151,24 +177,53 @@ async fn list_characters(client: &mut Client, update: &mut Update, data: &mut Da
     if let Chat::Group(group) = chat {
         let conn = db.get_conn();
 
-        if let Some(user_characters) =
-            UserCharacters::select_by_id(conn, sender.id(), group.id()).await?
-        {
-            let mut medias = Vec::new();
+        let owned_characters =
+            OwnedCharacter::select_by_user(conn, sender.id(), group.id()).await?;
+
+        if !owned_characters.is_empty() {
+            let mut entries = Vec::new();
+
+            for owned_character in owned_characters {
+                if let Some(character) =
+                    Character::select_by_id(conn, owned_character.character_id).await?
+                {
+                    let variant = match owned_character.variant_id {
+                        Some(variant_id) => {
+                            CharacterVariant::select_by_id(conn, variant_id).await?
+                        }
+                        None => None,
+                    };
+                    let shown = variant
+                        .as_ref()
+                        .map_or_else(|| character.clone(), |variant| variant.apply(&character));
 
-            for character_id in user_characters.characters_id {
-                if let Some(character) = Character::select_by_id(conn, character_id).await? {
                     let caption = crate::utils::construct_character_info(
-                        &character,
+                        &shown,
                         Series::select_by_id(conn, character.series_id).await?,
-                    );
-                    if let Some(file) = crate::utils::upload_photo(client, character, conn).await? {
-                        medias.push(InputMedia::html(caption).photo(file));
-                    }
+                    ) + "\n"
+                        + &crate::utils::construct_owned_info(&owned_character);
+                    entries.push((character, variant, caption));
                 }
             }
 
-            message.reply_album(medias).await?;
+            let sources = entries
+                .iter()
+                .map(|(character, variant, _)| PhotoSource::Character(character, variant.as_ref()))
+                .collect::<Vec<_>>();
+
+            // Characters without art are left out of the album
+            crate::utils::send_photos(client, conn, &sources, |files| {
+                message.reply_album(
+                    files
+                        .into_iter()
+                        .zip(&entries)
+                        .filter_map(|(file, (_, _, caption))| {
+                            file.map(|file| InputMedia::html(caption.clone()).copy_media(&file))
+                        })
+                        .collect(),
+                )
+            })
+            .await?;
         } else {
             message
                 .reply(InputMessage::html(t("no_characters")))
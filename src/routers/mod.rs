@@ -1,19 +1,39 @@
 mod admin;
+mod auction;
 mod character;
 mod collect;
+mod daily;
+mod duel;
+mod event;
 mod help;
+mod inline;
 mod language;
+mod like;
 mod list;
+mod quiz;
+mod ranking;
 mod send_character;
 mod series;
+mod shop;
 mod start;
+mod tag;
 
 pub use admin::router as admin;
+pub use auction::router as auction;
 pub use character::router as character;
 pub use collect::router as collect;
+pub use daily::router as daily;
+pub use duel::router as duel;
+pub use event::router as event;
 pub use help::router as help;
+pub use inline::router as inline;
 pub use language::router as language;
+pub use like::router as like;
 pub use list::router as list;
+pub use quiz::router as quiz;
+pub use ranking::router as ranking;
 pub use send_character::router as send_character;
 pub use series::router as series;
+pub use shop::router as shop;
 pub use start::router as start;
+pub use tag::router as tag;
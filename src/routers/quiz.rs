@@ -0,0 +1,283 @@
+use grammers_client::{
+    types::{Chat, Message},
+    Client, InputMessage, Update,
+};
+use grammers_friendly::prelude::*;
+use rbatis::{executor::Executor, RBatis};
+
+use crate::{
+    database::models::{Quiz, QuizScore, Series, User, Wallet},
+    modules::{Database, I18n},
+    utils::PhotoSource,
+    Config, Result,
+};
+
+// How many characters the collage of a series without a banner shows
+const COLLAGE_SIZE: u16 = 4;
+
+pub fn router() -> Router {
+    Router::default().add_handler(Handler::new_message(
+        start_quiz,
+        macros::command!("/!.", "quiz"),
+    ))
+}
+
+async fn start_quiz(client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+    let config = data.get_module::<Config>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let chat = update.get_chat().unwrap();
+    let message = update.get_message().unwrap();
+
+    let Chat::Group(group) = chat else {
+        message.reply(InputMessage::html(t("not_a_group"))).await?;
+
+        return Ok(());
+    };
+
+    let group_id = group.id();
+    let conn = db.get_conn();
+
+    let splitted = message.text().split_whitespace().collect::<Vec<&str>>();
+    if splitted.get(1).is_some_and(|arg| *arg == "top") {
+        return quiz_ranking(conn, &i18n, &message, group_id).await;
+    }
+
+    let now = chrono::Utc::now().timestamp();
+
+    if let Some(quiz) = Quiz::select_by_id(conn, group_id).await? {
+        if now < quiz.expires_at {
+            message
+                .respond(InputMessage::html(t("quiz_running")).reply_to(Some(quiz.message_id)))
+                .await?;
+
+            return Ok(());
+        }
+
+        Quiz::delete_by_id(conn, group_id).await?;
+    }
+
+    let Some(series) = Series::select_random_for_quiz(conn).await? else {
+        message
+            .reply(InputMessage::html(t("no_quiz_series")))
+            .await?;
+
+        return Ok(());
+    };
+
+    let input_message = InputMessage::html(
+        t("quiz")
+            .replace("{timeout}", &config.quiz.timeout.to_string())
+            .replace("{reward}", &config.quiz.reward.to_string()),
+    );
+
+    // The banner is preferred, otherwise a collage of some of the series characters is shown
+    let sent = if series.banner_hash.is_some() {
+        let message = &message;
+        crate::utils::send_photo(client, conn, PhotoSource::Series(&series), |file| {
+            let input_message = input_message.clone();
+
+            async move {
+                match file {
+                    Some(file) => message
+                        .reply(input_message.copy_media(&file))
+                        .await
+                        .map(Some),
+                    None => Ok(None),
+                }
+            }
+        })
+        .await?
+    } else {
+        match crate::utils::upload_series_collage(client, &series, conn, COLLAGE_SIZE).await? {
+            Some(file) => Some(message.reply(input_message.copy_media(&file)).await?),
+            None => None,
+        }
+    };
+
+    let Some(sent) = sent else {
+        message
+            .reply(InputMessage::html(t("no_quiz_series")))
+            .await?;
+
+        return Ok(());
+    };
+
+    Quiz::insert(
+        conn,
+        &Quiz {
+            group_id,
+            series_id: series.id,
+            message_id: sent.id(),
+            expires_at: now + config.quiz.timeout,
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+// Checks a reply to the quiz message, called from the collect handler
+pub async fn answer_quiz(
+    conn: &mut RBatis,
+    i18n: &I18n,
+    config: &Config,
+    message: &Message,
+    quiz: Quiz,
+) -> Result<()> {
+    let t = |key| i18n.get(key);
+
+    let Some(series) = Series::select_by_id(conn, quiz.series_id).await? else {
+        Quiz::delete_by_id(conn, quiz.group_id).await?;
+
+        return Ok(());
+    };
+
+    if chrono::Utc::now().timestamp() >= quiz.expires_at {
+        Quiz::delete_by_id(conn, quiz.group_id).await?;
+
+        message
+            .reply(InputMessage::html(
+                t("quiz_expired").replace("{title}", &crate::utils::escape_html(&series.title)),
+            ))
+            .await?;
+
+        return Ok(());
+    }
+
+    let guess = message.text().trim().to_lowercase();
+    if guess.is_empty() || message.media().is_some() {
+        message
+            .reply(InputMessage::html(t("invalid_guess")))
+            .await?;
+
+        return Ok(());
+    }
+
+    let mut names = vec![series.title.trim().to_lowercase()];
+    series
+        .aliases
+        .iter()
+        .for_each(|alias| names.push(alias.to_lowercase()));
+
+    if !crate::utils::guess_matches(&guess, names) {
+        message.reply(InputMessage::html(t("quiz_wrong"))).await?;
+
+        return Ok(());
+    }
+
+    let user_id = message.sender().unwrap().id();
+
+    let tx = conn.acquire_begin().await?;
+    let score = match reward_answer(&tx, &quiz, user_id, config.quiz.reward).await {
+        Ok(score) => score,
+        Err(e) => {
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+    tx.commit().await?;
+
+    // Someone else answered first
+    let Some(score) = score else {
+        return Ok(());
+    };
+
+    message
+        .reply(InputMessage::html(
+            t("quiz_correct")
+                .replace("{title}", &crate::utils::escape_html(&series.title))
+                .replace("{reward}", &config.quiz.reward.to_string())
+                .replace("{score}", &score.to_string()),
+        ))
+        .await?;
+
+    Ok(())
+}
+
+async fn reward_answer(
+    executor: &dyn Executor,
+    quiz: &Quiz,
+    user_id: i64,
+    reward: i64,
+) -> rbatis::Result<Option<u32>> {
+    if Quiz::delete_by_id(executor, quiz.group_id)
+        .await?
+        .rows_affected
+        == 0
+    {
+        return Ok(None);
+    }
+
+    let score = match QuizScore::select_by_id(executor, quiz.group_id, user_id).await? {
+        Some(mut quiz_score) => {
+            quiz_score.score += 1;
+            QuizScore::update_by_id(executor, &quiz_score, quiz.group_id, user_id).await?;
+
+            quiz_score.score
+        }
+        None => {
+            QuizScore::insert(
+                executor,
+                &QuizScore {
+                    group_id: quiz.group_id,
+                    user_id,
+                    score: 1,
+                },
+            )
+            .await?;
+
+            1
+        }
+    };
+
+    Wallet::apply(
+        executor,
+        user_id,
+        reward,
+        &format!("quiz {}", quiz.series_id),
+    )
+    .await?;
+
+    Ok(Some(score))
+}
+
+async fn quiz_ranking(
+    conn: &mut RBatis,
+    i18n: &I18n,
+    message: &Message,
+    group_id: i64,
+) -> Result<()> {
+    let t = |key| i18n.get(key);
+
+    let scores = QuizScore::select_ranking_by_group(conn, group_id, 10).await?;
+
+    if scores.is_empty() {
+        message.reply(InputMessage::html(t("no_ranking"))).await?;
+
+        return Ok(());
+    }
+
+    let mut text = t("quiz_ranking_title") + "\n\n";
+
+    for (position, quiz_score) in scores.iter().enumerate() {
+        let name = match User::select_by_id(conn, quiz_score.user_id).await? {
+            Some(user) => crate::utils::escape_html(user.full_name),
+            None => quiz_score.user_id.to_string(),
+        };
+
+        text += &format!(
+            "<b>{0}.</b> {1} — 🧠 <code>{2}</code>\n",
+            position + 1,
+            name,
+            quiz_score.score
+        );
+    }
+
+    message.reply(InputMessage::html(text)).await?;
+
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use grammers_client::{types::Chat, Client, InputMessage, Update};
+use grammers_friendly::prelude::*;
+
+use crate::{
+    database::models::{OwnedCharacter, User},
+    modules::{Database, I18n},
+    Result,
+};
+
+pub fn router() -> Router {
+    Router::default().add_handler(Handler::new_message(
+        ranking,
+        macros::command!("/!.", "top").or(macros::command!("ranking")),
+    ))
+}
+
+async fn ranking(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let chat = update.get_chat().unwrap();
+    let message = update.get_message().unwrap();
+
+    if let Chat::Group(group) = chat {
+        let conn = db.get_conn();
+
+        let rankings = OwnedCharacter::select_ranking_by_group(conn, group.id(), 10).await?;
+
+        if rankings.is_empty() {
+            message.reply(InputMessage::html(t("no_ranking"))).await?;
+        } else {
+            let mut text = t("ranking_title") + "\n\n";
+
+            for (position, ranking) in rankings.iter().enumerate() {
+                let name = match User::select_by_id(conn, ranking.user_id).await? {
+                    Some(user) => crate::utils::escape_html(user.full_name),
+                    None => ranking.user_id.to_string(),
+                };
+
+                text += &format!(
+                    "<b>{0}.</b> {1} — 🎖 <code>{2}</code> | 👥 <code>{3}</code>\n",
+                    position + 1,
+                    name,
+                    ranking.levels,
+                    ranking.characters
+                );
+            }
+
+            message.reply(InputMessage::html(text)).await?;
+        }
+    } else {
+        message.reply(InputMessage::html(t("not_a_group"))).await?;
+    }
+
+    Ok(())
+}
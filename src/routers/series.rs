@@ -1,12 +1,16 @@
-use grammers_client::{button, reply_markup, Client, InputMessage, Update};
+use grammers_client::{button, reply_markup, types::Chat, Client, InputMessage, Update};
 use grammers_friendly::prelude::*;
 
 use crate::{
-    database::models::{Character, Series},
+    database::models::{Character, GroupSeries, Like, LikeKind, OwnedCharacter, Series},
     modules::{Database, I18n},
+    utils::PhotoSource,
     Result,
 };
 
+// How many other series of the franchise get a button
+const MAX_RELATED: usize = 6;
+
 pub fn router() -> Router {
     Router::default()
         .add_handler(Handler::new_message(
@@ -37,6 +41,11 @@ pub fn router() -> Router {
             search_series,
             macros::command!("/!.", "ss"),
         ))
+        .add_handler(Handler::new_message(
+            spawn_series,
+            macros::command!("/!.", "spawnseries")
+                .and(filters::private().not().and(filters::admin())),
+        ))
 }
 
 async fn see_serie(client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
@@ -98,7 +107,10 @@ async fn see_serie(client: &mut Client, update: &mut Update, data: &mut Data) ->
                 splitted[1] = splitted[1..].join(" ");
                 splitted.truncate(2);
 
-                Series::select_by_title(conn, &splitted[1]).await?
+                Series::search(conn, &splitted[1], 1, 1)
+                    .await?
+                    .into_iter()
+                    .next()
             }
         } {
             let char_per_page = 15;
@@ -108,6 +120,10 @@ async fn see_serie(client: &mut Client, update: &mut Update, data: &mut Data) ->
             let total_pages = ((total_characters as f64) / (char_per_page as f64)).ceil() as usize;
             let mut buttons = Vec::new();
 
+            // Sequels, spin-offs and adaptations count together towards the franchise
+            let franchise = Series::select_franchise(conn, series.id).await?;
+            let franchise_ids = franchise.iter().map(|serie| serie.id).collect::<Vec<i64>>();
+
             if splitted.len() > 2 {
                 if let Ok(user_id) = splitted[2].parse::<i64>() {
                     if user_id != sender_id {
@@ -157,11 +173,31 @@ async fn see_serie(client: &mut Client, update: &mut Update, data: &mut Data) ->
             let mut buttons = vec![
                 buttons,
                 vec![button::inline(
-                    format!("❤ {}", series.liked_by.len()),
+                    format!(
+                        "❤ {}",
+                        Like::count_by_target(conn, &LikeKind::Series, series.id).await?
+                    ),
                     format!("slike {}", series.id),
                 )],
             ];
 
+            let related = franchise
+                .iter()
+                .filter(|serie| serie.id != series.id)
+                .take(MAX_RELATED)
+                .map(|serie| {
+                    button::inline(
+                        format!(
+                            "{0} {1}",
+                            crate::utils::media_type_symbol(&serie.media_type),
+                            crate::utils::shorten_text(&serie.title, 24)
+                        ),
+                        format!("series {0} {1} {2}", serie.id, sender_id, 1),
+                    )
+                })
+                .collect::<Vec<_>>();
+            buttons.extend(utils::split_kb_to_columns(related, 2));
+
             if !is_like && crate::filters::sudoers().is_ok(client, update).await {
                 buttons.push(vec![
                     button::inline(t("edit_button"), format!("series edit {}", series.id)),
@@ -169,6 +205,29 @@ async fn see_serie(client: &mut Client, update: &mut Update, data: &mut Data) ->
                 ]);
             }
 
+            if let Some(Chat::Group(group)) = update.get_chat() {
+                let owned =
+                    OwnedCharacter::count_by_series(conn, sender_id, group.id(), &[series.id])
+                        .await?;
+                caption += &t("series_completion")
+                    .replace("{owned}", &owned.to_string())
+                    .replace("{total}", &total_characters.to_string());
+
+                if franchise.len() > 1 {
+                    let owned = OwnedCharacter::count_by_series(
+                        conn,
+                        sender_id,
+                        group.id(),
+                        &franchise_ids,
+                    )
+                    .await?;
+                    let total = Character::count_by_series_ids(conn, &franchise_ids).await?;
+                    caption += &t("franchise_completion")
+                        .replace("{owned}", &owned.to_string())
+                        .replace("{total}", &total.to_string());
+                }
+            }
+
             caption += &format!("\n🔖 | {}/{}", index, total_pages);
 
             let mut input_message = InputMessage::html(caption);
@@ -180,14 +239,13 @@ async fn see_serie(client: &mut Client, update: &mut Update, data: &mut Data) ->
             if query.is_some() {
                 message.edit(input_message).await?;
             } else {
-                if let Some(file) = crate::utils::upload_banner(client, series.clone(), conn)
-                    .await
-                    .unwrap()
-                {
-                    message.reply(input_message.photo(file)).await?;
-                } else {
-                    message.reply(input_message).await?;
-                }
+                crate::utils::send_photo(client, conn, PhotoSource::Series(&series), |file| {
+                    message.reply(match file {
+                        Some(file) => input_message.clone().copy_media(&file),
+                        None => input_message.clone(),
+                    })
+                })
+                .await?;
             }
         } else {
             message
@@ -252,10 +310,12 @@ async fn see_serie_characters(
                 splitted[2] = splitted[2..].join(" ");
                 splitted.truncate(3);
 
-                Series::select_by_title(conn, &splitted[2]).await?
+                Series::search(conn, &splitted[2], 1, 1)
+                    .await?
+                    .into_iter()
+                    .next()
             }
         } {
-            let mut file = None;
             let mut index = 1;
             let characters_count = Character::count_by_series(conn, series.id).await?;
             let mut buttons = Vec::new();
@@ -288,8 +348,6 @@ async fn see_serie_characters(
             }
 
             for character in characters.iter() {
-                file = crate::utils::upload_photo(client, character.clone(), conn).await?;
-
                 caption += &(crate::utils::construct_character_partial_info(&character, true, 0)
                     + &crate::utils::construct_series_info(&series, 0, false));
             }
@@ -326,15 +384,28 @@ async fn see_serie_characters(
                 input_message = input_message.reply_markup(&reply_markup::inline(vec![buttons]));
             }
 
-            if let Some(file) = file {
-                input_message = input_message.photo(file);
-            }
-
-            if query.is_some() {
-                message.edit(input_message).await?;
-            } else {
-                message.reply(input_message).await?;
-            }
+            let message = &message;
+            let is_query = query.is_some();
+            crate::utils::send_photo(
+                client,
+                conn,
+                PhotoSource::Character(&characters[0], None),
+                |file| {
+                    let input_message = match file {
+                        Some(file) => input_message.clone().copy_media(&file),
+                        None => input_message.clone(),
+                    };
+
+                    async move {
+                        if is_query {
+                            message.edit(input_message).await
+                        } else {
+                            message.reply(input_message).await.map(|_| ())
+                        }
+                    }
+                },
+            )
+            .await?;
         } else {
             message
                 .reply(InputMessage::html(t("unknown_series")))
@@ -365,17 +436,8 @@ async fn like_series(client: &mut Client, update: &mut Update, data: &mut Data)
             let conn = db.get_conn();
             let sender_id = sender.id();
 
-            if let Some(mut series) = Series::select_by_id(conn, id).await? {
-                let mut liked_by = series.liked_by;
-
-                if liked_by.contains(&sender_id) {
-                    liked_by.retain(|id| *id != sender_id);
-                } else {
-                    liked_by.push(sender.id());
-                }
-
-                series.liked_by = liked_by;
-                match Series::update_by_id(conn, &series, series.id).await {
+            if let Some(series) = Series::select_by_id(conn, id).await? {
+                match Like::toggle(conn, sender_id, &LikeKind::Series, series.id).await {
                     Ok(_) => see_serie(client, update, data).await?,
                     Err(_) => {}
                 }
@@ -417,7 +479,7 @@ async fn search_series(_client: &mut Client, update: &mut Update, data: &mut Dat
         let title = splitted[2..].join(" ");
         let mut text = t("search_results").replace("{search}", &title) + "\n\n";
 
-        let series = Series::select_page_by_title(conn, &title, 1, 15).await?;
+        let series = Series::search(conn, &title, 1, 15).await?;
         if series.is_empty() {
             text = t("no_results").replace("{search}", &title);
         } else {
@@ -456,3 +518,106 @@ async fn search_series(_client: &mut Client, update: &mut Update, data: &mut Dat
 
     Ok(())
 }
+
+// Group admins can restrict the spawns to whole franchises, each given by any of its series
+async fn spawn_series(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+    let conn = db.get_conn();
+
+    let chat = update.get_chat().unwrap();
+    let message = update.get_message().unwrap();
+
+    let Chat::Group(group) = chat else {
+        message.reply(InputMessage::html(t("not_a_group"))).await?;
+
+        return Ok(());
+    };
+
+    let splitted = message.text().split_whitespace().collect::<Vec<&str>>();
+
+    if splitted.len() == 1 {
+        let mut titles = Vec::new();
+        for group_series in GroupSeries::select_by_group(conn, group.id()).await? {
+            if let Some(series) = Series::select_by_id(conn, group_series.series_id).await? {
+                titles.push(format!(
+                    "<b>{}</b>",
+                    crate::utils::escape_html(&series.title)
+                ));
+            }
+        }
+
+        let text = if titles.is_empty() {
+            t("spawn_series_none")
+        } else {
+            t("spawn_series").replace("{series}", &titles.join(", "))
+        };
+        message.reply(InputMessage::html(text)).await?;
+
+        return Ok(());
+    }
+
+    if splitted[1] == "0" {
+        GroupSeries::delete_by_group(conn, group.id()).await?;
+        message
+            .reply(InputMessage::html(t("spawn_series_cleared")))
+            .await?;
+
+        return Ok(());
+    }
+
+    let mut series_list = Vec::new();
+    for id in splitted[1..].iter() {
+        match id.parse::<i64>() {
+            Ok(id) => match Series::select_by_id(conn, id).await? {
+                Some(series) => series_list.push(series),
+                None => {
+                    message
+                        .reply(InputMessage::html(t("unknown_series")))
+                        .await?;
+
+                    return Ok(());
+                }
+            },
+            Err(_) => {
+                message.reply(InputMessage::html(t("invalid_id"))).await?;
+
+                return Ok(());
+            }
+        }
+    }
+
+    let mut series_ids = Vec::new();
+    GroupSeries::delete_by_group(conn, group.id()).await?;
+    for series in series_list.iter() {
+        let group_series = GroupSeries {
+            group_id: group.id(),
+            series_id: series.id,
+        };
+        GroupSeries::insert(conn, &group_series).await?;
+
+        for serie in Series::select_franchise(conn, series.id).await? {
+            if !series_ids.contains(&serie.id) {
+                series_ids.push(serie.id);
+            }
+        }
+    }
+
+    let count = Character::count_by_series_ids(conn, &series_ids).await?;
+    let titles = series_list
+        .iter()
+        .map(|series| format!("<b>{}</b>", crate::utils::escape_html(&series.title)))
+        .collect::<Vec<String>>();
+
+    message
+        .reply(InputMessage::html(
+            t("spawn_series_set")
+                .replace("{series}", &titles.join(", "))
+                .replace("{count}", &count.to_string()),
+        ))
+        .await?;
+
+    Ok(())
+}
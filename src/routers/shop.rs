@@ -0,0 +1,357 @@
+use std::time::Duration;
+
+use grammers_client::{button, reply_markup, types::Chat, Client, InputMessage, Update};
+use grammers_friendly::prelude::*;
+use rbatis::executor::Executor;
+
+use crate::{
+    database::models::{Character, GroupCharacter, Inventory, Series, Wallet},
+    middlewares::SendCharacter,
+    modules::{Conversation, Database, I18n},
+    Config, ItemEffect, Result, ShopItem,
+};
+
+pub fn router() -> Router {
+    Router::default()
+        .add_handler(Handler::new_message(shop, macros::command!("/!.", "shop")))
+        .add_handler(Handler::callback_query(
+            buy_item,
+            filters::query("shop buy id:str"),
+        ))
+        .add_handler(Handler::new_message(
+            use_hint,
+            macros::command!("/!.", "hint").and(filters::reply()),
+        ))
+}
+
+async fn shop(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+    let config = data.get_module::<Config>().unwrap();
+
+    let t = |key: &str| i18n.get(key);
+
+    let sender = update.get_sender().unwrap();
+    let message = update.get_message().unwrap();
+
+    let conn = db.get_conn();
+
+    let balance = Wallet::select_by_id(conn, sender.id())
+        .await?
+        .map_or(0, |wallet| wallet.balance);
+
+    let mut text = t("shop_title").replace("{balance}", &balance.to_string()) + "\n\n";
+    let mut buttons = Vec::new();
+
+    for item in config.shop.items.iter() {
+        let key = item.effect.key();
+
+        text += &format!(
+            "<b>{0}</b> — 💰 <code>{1}</code>\n<i>{2}</i>\n\n",
+            t(&format!("item_{}", key)),
+            item.price,
+            t(&format!("item_{}_description", key))
+        );
+        buttons.push(button::inline(
+            format!("{0} (💰 {1})", t(&format!("item_{}", key)), item.price),
+            format!("shop buy {}", item.id),
+        ));
+    }
+
+    message
+        .reply(InputMessage::html(text).reply_markup(&reply_markup::inline(
+            utils::split_kb_to_columns(buttons, 2),
+        )))
+        .await?;
+
+    Ok(())
+}
+
+async fn buy_item(client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+    let conv = data.get_module::<Conversation>().unwrap();
+    let config = data.get_module::<Config>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let chat = update.get_chat().unwrap();
+    let query = update.get_query().unwrap();
+    let sender = query.sender();
+    let message = query.load_message().await?;
+
+    let splitted = utils::split_query(query.data());
+
+    let Some(item) = config
+        .shop
+        .items
+        .iter()
+        .find(|item| item.id == splitted[2])
+        .cloned()
+    else {
+        return Ok(());
+    };
+
+    let conn = db.get_conn();
+    let user_id = sender.id();
+    let reason = format!("shop {}", item.id);
+
+    // Reroll and summon act on the group collection
+    if matches!(item.effect, ItemEffect::Reroll | ItemEffect::SummonSeries)
+        && !matches!(chat, Chat::Group(_))
+    {
+        query.answer().alert(t("not_a_group")).send().await?;
+
+        return Ok(());
+    }
+
+    let key = match item.effect {
+        ItemEffect::ExtraSlot | ItemEffect::HintToken => {
+            let tx = conn.acquire_begin().await?;
+            let key = match buy_inventory_item(&tx, user_id, &item).await {
+                Ok(key) => key,
+                Err(e) => {
+                    tx.rollback().await?;
+                    return Err(e.into());
+                }
+            };
+
+            if key == "item_bought" {
+                tx.commit().await?;
+            } else {
+                tx.rollback().await?;
+            }
+
+            key
+        }
+        ItemEffect::Reroll => {
+            let mut active_characters =
+                GroupCharacter::select_available_by_group(conn, chat.id()).await?;
+            active_characters.retain(|group_character| {
+                !group_character.has_escaped(message.id(), config.spawn.escape_after)
+            });
+
+            if active_characters.len() >= config.spawn.max_active {
+                "reroll_unavailable"
+            } else if !Wallet::apply(conn, user_id, -item.price, &reason).await? {
+                "not_enough_coins"
+            } else {
+                // The spawn can't be undone, so the coins are taken first and given back when
+                // nothing was spawned or it failed
+                match SendCharacter::spawn_character(
+                    client,
+                    conn,
+                    &i18n,
+                    &config,
+                    &message,
+                    &active_characters,
+                )
+                .await
+                {
+                    Ok(true) => "item_bought",
+                    Ok(false) => {
+                        Wallet::apply(conn, user_id, item.price, "shop refund").await?;
+
+                        "reroll_unavailable"
+                    }
+                    Err(e) => {
+                        Wallet::apply(conn, user_id, item.price, "shop refund").await?;
+
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        ItemEffect::SummonSeries => {
+            // Only spares asking for the series, the coins are taken by `buy_summon` in the
+            // same transaction that grants the character
+            if Wallet::select_by_id(conn, user_id)
+                .await?
+                .map_or(0, |wallet| wallet.balance)
+                < item.price
+            {
+                "not_enough_coins"
+            } else {
+                let timeout = 30;
+
+                match conv
+                    .ask_message(
+                        chat.clone(),
+                        sender,
+                        InputMessage::html(
+                            t("ask_field")
+                                .replace("{field}", &t("series"))
+                                .replace("{timeout}", &timeout.to_string()),
+                        ),
+                        filters::reply(),
+                        Duration::from_secs(timeout),
+                    )
+                    .await?
+                {
+                    (sent, Some(response)) => {
+                        sent.delete().await?;
+
+                        let text = response.text().trim().to_string();
+                        let series = match text.parse::<i64>() {
+                            Ok(id) => Series::select_by_id(conn, id).await?,
+                            Err(_) => Series::search(conn, &text, 1, 1).await?.into_iter().next(),
+                        };
+
+                        if let Some(series) = series {
+                            if let Some((character, _)) =
+                                SendCharacter::pick_character(conn, None, 0, Some(series.id))
+                                    .await?
+                            {
+                                let tx = conn.acquire_begin().await?;
+                                let key =
+                                    match buy_summon(&tx, user_id, chat.id(), &item, &character)
+                                        .await
+                                    {
+                                        Ok(key) => key,
+                                        Err(e) => {
+                                            tx.rollback().await?;
+                                            return Err(e.into());
+                                        }
+                                    };
+
+                                if key == "item_bought" {
+                                    tx.commit().await?;
+
+                                    response
+                                        .reply(InputMessage::html(
+                                            t("character_summoned")
+                                                .replace("{name}", &character.name)
+                                                .replace("{series}", &series.title),
+                                        ))
+                                        .await?;
+                                } else {
+                                    tx.rollback().await?;
+                                }
+
+                                key
+                            } else {
+                                "no_series_characters"
+                            }
+                        } else {
+                            "unknown_series"
+                        }
+                    }
+                    (sent, None) => {
+                        sent.edit(InputMessage::html(
+                            t("operation_cancelled").replace("{reason}", &t("timeout")),
+                        ))
+                        .await?;
+
+                        tokio::time::sleep(Duration::from_secs(2)).await;
+                        sent.delete().await?;
+
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    };
+
+    query.answer().alert(t(key)).send().await?;
+
+    Ok(())
+}
+
+async fn use_hint(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+
+    let chat = update.get_chat().unwrap();
+    let sender = update.get_sender().unwrap();
+    let message = update.get_message().unwrap();
+
+    let Chat::Group(group) = chat else {
+        message.reply(InputMessage::html(t("not_a_group"))).await?;
+
+        return Ok(());
+    };
+
+    if let Ok(Some(reply_message)) = message.get_reply().await {
+        let conn = db.get_conn();
+
+        let Some(group_character) =
+            GroupCharacter::select_by_message_id(conn, group.id(), reply_message.id()).await?
+        else {
+            message
+                .reply(InputMessage::html(t("not_a_character")))
+                .await?;
+
+            return Ok(());
+        };
+
+        if !group_character.available {
+            message
+                .reply(InputMessage::html(t("expired_character")))
+                .await?;
+
+            return Ok(());
+        }
+
+        if let Some(character) = Character::select_by_id(conn, group_character.character_id).await?
+        {
+            if !Inventory::use_hint_token(conn, sender.id()).await? {
+                message
+                    .reply(InputMessage::html(t("no_hint_tokens")))
+                    .await?;
+
+                return Ok(());
+            }
+
+            let inventory = Inventory::select_or_default(conn, sender.id()).await?;
+            message
+                .reply(InputMessage::html(
+                    t("character_hint")
+                        .replace("{hint}", &crate::utils::mask_name(&character.name))
+                        .replace("{tokens}", &inventory.hint_tokens.to_string()),
+                ))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn buy_inventory_item(
+    executor: &dyn Executor,
+    user_id: i64,
+    item: &ShopItem,
+) -> rbatis::Result<&'static str> {
+    if !Wallet::apply(executor, user_id, -item.price, &format!("shop {}", item.id)).await? {
+        return Ok("not_enough_coins");
+    }
+
+    let mut inventory = Inventory::select_or_default(executor, user_id).await?;
+    match item.effect {
+        ItemEffect::ExtraSlot => inventory.extra_slots += 1,
+        ItemEffect::HintToken => inventory.hint_tokens += 1,
+        _ => {}
+    }
+    inventory.save(executor).await?;
+
+    Ok("item_bought")
+}
+
+async fn buy_summon(
+    executor: &dyn Executor,
+    user_id: i64,
+    group_id: i64,
+    item: &ShopItem,
+    character: &Character,
+) -> rbatis::Result<&'static str> {
+    if !Wallet::apply(executor, user_id, -item.price, &format!("shop {}", item.id)).await? {
+        return Ok("not_enough_coins");
+    }
+
+    if !crate::utils::grant_character(executor, user_id, group_id, character, None).await? {
+        return Ok("collection_full");
+    }
+
+    Ok("item_bought")
+}
@@ -0,0 +1,246 @@
+use grammers_client::{button, reply_markup, types::Chat, Client, InputMessage, Update};
+use grammers_friendly::prelude::*;
+use rbatis::RBatis;
+
+use crate::{
+    database::models::{Character, GroupTag, Tag},
+    modules::{Database, I18n},
+    Result,
+};
+
+pub fn router() -> Router {
+    Router::default()
+        .add_handler(Handler::new_message(
+            see_tag,
+            macros::command!("/!.", "tag"),
+        ))
+        .add_handler(Handler::callback_query(
+            see_tag_page,
+            filters::query("tag id:int page:int"),
+        ))
+        .add_handler(Handler::new_message(
+            spawn_tags,
+            macros::command!("/!.", "spawntags")
+                .and(filters::private().not().and(filters::admin())),
+        ))
+}
+
+async fn see_tag(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+    let conn = db.get_conn();
+
+    let message = update.get_message().unwrap();
+
+    let splitted = message.text().split_whitespace().collect::<Vec<&str>>();
+
+    // Without a name every tag is listed
+    if splitted.len() == 1 {
+        let tags = Tag::select_ordered(conn).await?;
+        if tags.is_empty() {
+            message.reply(InputMessage::html(t("no_tags"))).await?;
+
+            return Ok(());
+        }
+
+        let mut text = String::new();
+        for tag in tags.iter() {
+            text += &format!(
+                "🏷 <code>{0}</code> — <b>{1}</b> | 👥 <code>{2}</code>\n",
+                tag.name,
+                crate::utils::escape_html(tag.display_name(conn, &i18n.locale()).await?),
+                Character::count_by_tags(conn, &[tag.id]).await?
+            );
+        }
+
+        message
+            .reply(InputMessage::html(t("tag_list").replace("{tags}", &text)))
+            .await?;
+
+        return Ok(());
+    }
+
+    let name = splitted[1..].join(" ");
+    match Tag::find(conn, &name).await? {
+        Some(tag) => {
+            message
+                .reply(construct_tag_page(conn, &i18n, &tag, 1).await?)
+                .await?;
+        }
+        None => {
+            message
+                .reply(InputMessage::html(
+                    t("unknown_tag").replace("{tag}", &crate::utils::escape_html(name)),
+                ))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn see_tag_page(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let conn = db.get_conn();
+
+    let query = update.get_query().unwrap();
+    let message = query.load_message().await?;
+
+    let splitted = utils::split_query(query.data());
+    let tag_id = splitted[1].parse::<i64>().unwrap();
+    let page = splitted[2].parse::<u16>().unwrap_or(1).max(1);
+
+    if let Some(tag) = Tag::select_by_id(conn, tag_id).await? {
+        message
+            .edit(construct_tag_page(conn, &i18n, &tag, page).await?)
+            .await?;
+    }
+    query.answer().send().await?;
+
+    Ok(())
+}
+
+async fn construct_tag_page(
+    conn: &mut RBatis,
+    i18n: &I18n,
+    tag: &Tag,
+    page: u16,
+) -> Result<InputMessage> {
+    let t = |key| i18n.get(key);
+
+    let per_page = 15;
+    let display_name = crate::utils::escape_html(tag.display_name(conn, &i18n.locale()).await?);
+
+    let total = Character::count_by_tags(conn, &[tag.id]).await?;
+    if total == 0 {
+        return Ok(InputMessage::html(
+            t("no_tag_characters").replace("{tag}", &display_name),
+        ));
+    }
+
+    let total_pages = total.div_ceil(per_page as usize);
+    let page = page.min(total_pages as u16);
+
+    let characters = Character::select_page_by_tag(conn, tag.id, page, per_page).await?;
+    let space_count = characters
+        .iter()
+        .map(|character| character.id.to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    let mut text = t("tag_characters").replace("{tag}", &display_name) + "\n\n";
+    for character in characters.iter() {
+        text += &crate::utils::construct_character_partial_info(character, false, space_count);
+    }
+    text += &format!("\n🔖 | {}/{}", page, total_pages);
+
+    let mut buttons = Vec::new();
+    if page > 1 {
+        buttons.push(button::inline("⬅", format!("tag {} {}", tag.id, page - 1)));
+    }
+    if (page as usize) < total_pages {
+        buttons.push(button::inline("➡", format!("tag {} {}", tag.id, page + 1)));
+    }
+
+    let mut input_message = InputMessage::html(text);
+    if !buttons.is_empty() {
+        input_message = input_message.reply_markup(&reply_markup::inline(vec![buttons]));
+    }
+
+    Ok(input_message)
+}
+
+// Group admins can restrict the spawns to characters with some tags
+async fn spawn_tags(_client: &mut Client, update: &mut Update, data: &mut Data) -> Result<()> {
+    let mut db = data.get_module::<Database>().unwrap();
+    let i18n = data.get_module::<I18n>().unwrap();
+
+    let t = |key| i18n.get(key);
+    let conn = db.get_conn();
+
+    let chat = update.get_chat().unwrap();
+    let message = update.get_message().unwrap();
+
+    let Chat::Group(group) = chat else {
+        message.reply(InputMessage::html(t("not_a_group"))).await?;
+
+        return Ok(());
+    };
+
+    let splitted = message.text().split_whitespace().collect::<Vec<&str>>();
+
+    if splitted.len() == 1 {
+        let tags = Tag::select_by_group(conn, group.id()).await?;
+        let text = if tags.is_empty() {
+            t("spawn_tags_none")
+        } else {
+            t("spawn_tags").replace("{tags}", &tag_names(conn, &i18n, &tags).await?)
+        };
+        message.reply(InputMessage::html(text)).await?;
+
+        return Ok(());
+    }
+
+    if splitted[1] == "0" {
+        GroupTag::delete_by_group(conn, group.id()).await?;
+        message
+            .reply(InputMessage::html(t("spawn_tags_cleared")))
+            .await?;
+
+        return Ok(());
+    }
+
+    let mut tags = Vec::new();
+    for name in splitted[1..].iter() {
+        match Tag::find(conn, name).await? {
+            Some(tag) => tags.push(tag),
+            None => {
+                message
+                    .reply(InputMessage::html(
+                        t("unknown_tag").replace("{tag}", &crate::utils::escape_html(*name)),
+                    ))
+                    .await?;
+
+                return Ok(());
+            }
+        }
+    }
+
+    GroupTag::delete_by_group(conn, group.id()).await?;
+    for tag in tags.iter() {
+        let group_tag = GroupTag {
+            group_id: group.id(),
+            tag_id: tag.id,
+        };
+        GroupTag::insert(conn, &group_tag).await?;
+    }
+
+    let tag_ids = tags.iter().map(|tag| tag.id).collect::<Vec<i64>>();
+    let count = Character::count_by_tags(conn, &tag_ids).await?;
+
+    message
+        .reply(InputMessage::html(
+            t("spawn_tags_set")
+                .replace("{tags}", &tag_names(conn, &i18n, &tags).await?)
+                .replace("{count}", &count.to_string()),
+        ))
+        .await?;
+
+    Ok(())
+}
+
+async fn tag_names(conn: &mut RBatis, i18n: &I18n, tags: &[Tag]) -> Result<String> {
+    let mut names = Vec::new();
+    for tag in tags.iter() {
+        names.push(format!(
+            "<b>{}</b>",
+            crate::utils::escape_html(tag.display_name(conn, &i18n.locale()).await?)
+        ));
+    }
+
+    Ok(names.join(", "))
+}
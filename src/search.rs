@@ -0,0 +1,488 @@
+use crate::database::models::{Gender, Media};
+
+pub const FIELDS: [&str; 7] = [
+    "stars",
+    "gender",
+    "series",
+    "franchise",
+    "media",
+    "artist",
+    "tag",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn as_sql(&self) -> &str {
+        match self {
+            Self::Equal => "=",
+            Self::Less => "<",
+            Self::LessOrEqual => "<=",
+            Self::Greater => ">",
+            Self::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+// A parsed `/cs` query, e.g. `rem stars:>=4 gender:female series:12 media:anime artist:foo tag:villain`,
+// `franchise:12` also matches the sequels, spin-offs and adaptations of the series
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CharacterFilter {
+    pub text: String,
+    pub stars: Vec<(Comparison, u8)>,
+    pub gender: Option<Gender>,
+    pub series_id: Option<i64>,
+    pub franchise_id: Option<i64>,
+    pub media: Option<Media>,
+    pub artist: Option<String>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FilterError {
+    Empty,
+    UnknownField {
+        field: String,
+        suggestion: &'static str,
+    },
+    InvalidValue {
+        field: String,
+        value: String,
+    },
+}
+
+impl FilterError {
+    // The locale key of the message, its placeholders are `{field}` and `{value}`
+    pub fn locale_key(&self) -> String {
+        match self {
+            Self::Empty => "search_empty".to_string(),
+            Self::UnknownField { .. } => "search_unknown_field".to_string(),
+            Self::InvalidValue { field, .. } => format!("search_invalid_{}", field),
+        }
+    }
+
+    pub fn field(&self) -> &str {
+        match self {
+            Self::Empty => "",
+            Self::UnknownField { field, .. } => field,
+            Self::InvalidValue { field, .. } => field,
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        match self {
+            Self::Empty => "",
+            Self::UnknownField { suggestion, .. } => suggestion,
+            Self::InvalidValue { value, .. } => value,
+        }
+    }
+}
+
+impl CharacterFilter {
+    pub fn parse(query: &str) -> Result<Self, FilterError> {
+        let mut filter = Self::default();
+        let mut words = Vec::new();
+
+        for part in query.split_whitespace() {
+            let Some((field, value)) = part.split_once(':') else {
+                words.push(part);
+                continue;
+            };
+
+            // Words like `re:zero` are text, unless they look like a misspelled filter
+            let field = field.to_lowercase();
+            if !FIELDS.contains(&field.as_str()) {
+                match suggest_field(&field) {
+                    Some(suggestion) => {
+                        return Err(FilterError::UnknownField { field, suggestion })
+                    }
+                    None => {
+                        words.push(part);
+                        continue;
+                    }
+                }
+            }
+
+            let invalid = || FilterError::InvalidValue {
+                field: field.clone(),
+                value: value.to_string(),
+            };
+
+            match field.as_str() {
+                "stars" => filter.stars.push(parse_stars(value).ok_or_else(invalid)?),
+                "gender" => filter.gender = Some(parse_enum(value).ok_or_else(invalid)?),
+                "series" => filter.series_id = Some(value.parse().map_err(|_| invalid())?),
+                "franchise" => filter.franchise_id = Some(value.parse().map_err(|_| invalid())?),
+                "media" => filter.media = Some(parse_enum(value).ok_or_else(invalid)?),
+                "artist" if !value.is_empty() => filter.artist = Some(value.replace('_', " ")),
+                "artist" => return Err(invalid()),
+                "tag" if !value.is_empty() => filter.tags.push(value.to_lowercase()),
+                "tag" => return Err(invalid()),
+                _ => unreachable!(),
+            }
+        }
+
+        filter.text = words.join(" ");
+
+        if filter == Self::default() {
+            return Err(FilterError::Empty);
+        }
+
+        Ok(filter)
+    }
+
+    // Builds the `from ... where ...` part of the query and its parameters, the full-text
+    // index is only joined when there's text to match
+    pub fn to_sql(&self) -> (String, Vec<rbs::Value>) {
+        let mut sql = String::from("from characters");
+        let mut conditions = Vec::new();
+        let mut args = Vec::new();
+
+        if self.has_text() {
+            sql += " join characters_search on characters_search.rowid = characters.id";
+            conditions.push("characters_search match ?".to_string());
+            args.push(rbs::to_value!(crate::database::models::search_query(
+                &self.text
+            )));
+        }
+
+        for (comparison, stars) in self.stars.iter() {
+            conditions.push(format!("characters.stars {} ?", comparison.as_sql()));
+            args.push(rbs::to_value!(stars));
+        }
+
+        if let Some(ref gender) = self.gender {
+            conditions.push("characters.gender = ?".to_string());
+            args.push(rbs::to_value!(gender));
+        }
+
+        if let Some(series_id) = self.series_id {
+            conditions.push(
+                "characters.id in (select character_id from characters_series where series_id = ?)"
+                    .to_string(),
+            );
+            args.push(rbs::to_value!(series_id));
+        }
+
+        if let Some(franchise_id) = self.franchise_id {
+            conditions.push(format!(
+                "characters.id in (select character_id from characters_series where series_id in ({}))",
+                crate::database::models::FRANCHISE_IDS
+            ));
+            args.push(rbs::to_value!(franchise_id));
+        }
+
+        if let Some(ref media) = self.media {
+            conditions.push(
+                "characters.id in (select character_id from characters_series where series_id in (select id from series where media_type = ?))".to_string(),
+            );
+            args.push(rbs::to_value!(media));
+        }
+
+        if let Some(ref artist) = self.artist {
+            conditions.push("characters.artist like ?".to_string());
+            args.push(rbs::to_value!(format!("%{}%", artist)));
+        }
+
+        // Tags match by name or by any of their display names
+        for tag in self.tags.iter() {
+            conditions.push("characters.id in (select character_id from characters_tags where tag_id in (select id from tags where name = ? union select tag_id from tag_names where lower(name) = ?))".to_string());
+            args.push(rbs::to_value!(tag));
+            args.push(rbs::to_value!(tag.replace('_', " ")));
+        }
+
+        if !conditions.is_empty() {
+            sql += " where ";
+            sql += &conditions.join(" and ");
+        }
+
+        (sql, args)
+    }
+
+    // Relevance first when matching text, the name and ID keep the order stable
+    pub fn order_by(&self) -> &str {
+        if self.has_text() {
+            "order by bm25(characters_search, 10.0, 5.0, 1.0), characters.name, characters.id"
+        } else {
+            "order by characters.name, characters.id"
+        }
+    }
+
+    fn has_text(&self) -> bool {
+        crate::database::models::search_query(&self.text).is_some()
+    }
+}
+
+// Names that only differ in case, punctuation, word order, a few typos or extra words,
+// e.g. `Uzumaki Naruto` and `naruto`
+pub fn is_similar_name(name: &str, other: &str) -> bool {
+    let words = name_words(name);
+    let other_words = name_words(other);
+    if words.is_empty() || other_words.is_empty() {
+        return false;
+    }
+
+    let (shorter, longer) = if words.len() <= other_words.len() {
+        (&words, &other_words)
+    } else {
+        (&other_words, &words)
+    };
+    if shorter.concat().chars().count() >= 3 && shorter.iter().all(|word| longer.contains(word)) {
+        return true;
+    }
+
+    let joined = words.concat();
+    let other_joined = other_words.concat();
+    let length = joined.chars().count().max(other_joined.chars().count());
+
+    // Up to one edit every five characters
+    edit_distance(&joined, &other_joined) * 5 <= length
+}
+
+fn name_words(name: &str) -> Vec<String> {
+    let mut words = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<String>>();
+    words.sort();
+
+    words
+}
+
+// Levenshtein distance, counted in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut previous = (0..=b.len()).collect::<Vec<usize>>();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let cost = if a_char == *b_char { 0 } else { 1 };
+            current[j + 1] = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+fn suggest_field(field: &str) -> Option<&'static str> {
+    FIELDS
+        .into_iter()
+        .find(|known| field.len() >= 3 && field.get(..3) == known.get(..3))
+}
+
+// Accepts `4`, `=4`, `>3`, `>=3`, `<5` and `<=5`
+fn parse_stars(value: &str) -> Option<(Comparison, u8)> {
+    let (comparison, number) = if let Some(number) = value.strip_prefix(">=") {
+        (Comparison::GreaterOrEqual, number)
+    } else if let Some(number) = value.strip_prefix("<=") {
+        (Comparison::LessOrEqual, number)
+    } else if let Some(number) = value.strip_prefix('>') {
+        (Comparison::Greater, number)
+    } else if let Some(number) = value.strip_prefix('<') {
+        (Comparison::Less, number)
+    } else {
+        (Comparison::Equal, value.strip_prefix('=').unwrap_or(value))
+    };
+
+    number
+        .parse::<u8>()
+        .ok()
+        .filter(|stars| (1..=6).contains(stars))
+        .map(|stars| (comparison, stars))
+}
+
+// Gender and media values use the same names as the database, e.g. `light_novel`
+pub fn parse_enum<T: serde::de::DeserializeOwned>(value: &str) -> Option<T> {
+    serde_json::from_value(serde_json::Value::String(value.to_lowercase())).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_query(text: &str) -> rbs::Value {
+        rbs::to_value!(crate::database::models::search_query(text))
+    }
+
+    #[test]
+    fn parses_every_field() {
+        let filter = CharacterFilter::parse(
+            "rem stars:>=4 gender:Female series:12 franchise:3 media:light_novel artist:foo_bar tag:Villain",
+        )
+        .unwrap();
+
+        assert_eq!(
+            filter,
+            CharacterFilter {
+                text: "rem".to_string(),
+                stars: vec![(Comparison::GreaterOrEqual, 4)],
+                gender: Some(Gender::Female),
+                series_id: Some(12),
+                franchise_id: Some(3),
+                media: Some(Media::LightNovel),
+                artist: Some("foo bar".to_string()),
+                tags: vec!["villain".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn parses_star_comparisons() {
+        let filter =
+            CharacterFilter::parse("stars:4 stars:=4 stars:>1 stars:<6 stars:<=5").unwrap();
+
+        assert_eq!(
+            filter.stars,
+            vec![
+                (Comparison::Equal, 4),
+                (Comparison::Equal, 4),
+                (Comparison::Greater, 1),
+                (Comparison::Less, 6),
+                (Comparison::LessOrEqual, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_text() {
+        let filter = CharacterFilter::parse("re:zero emilia").unwrap();
+
+        assert_eq!(filter.text, "re:zero emilia");
+        assert_eq!(filter.series_id, None);
+    }
+
+    #[test]
+    fn misspelled_fields_are_suggested() {
+        assert_eq!(
+            CharacterFilter::parse("rem star:4"),
+            Err(FilterError::UnknownField {
+                field: "star".to_string(),
+                suggestion: "stars",
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_values_are_refused() {
+        for (query, field, value) in [
+            ("stars:7", "stars", "7"),
+            ("stars:>>3", "stars", ">>3"),
+            ("gender:robot", "gender", "robot"),
+            ("series:abc", "series", "abc"),
+            ("media:radio", "media", "radio"),
+            ("artist:", "artist", ""),
+            ("tag:", "tag", ""),
+        ] {
+            assert_eq!(
+                CharacterFilter::parse(query),
+                Err(FilterError::InvalidValue {
+                    field: field.to_string(),
+                    value: value.to_string(),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn empty_queries_are_refused() {
+        assert_eq!(CharacterFilter::parse(""), Err(FilterError::Empty));
+        assert_eq!(CharacterFilter::parse("   "), Err(FilterError::Empty));
+    }
+
+    #[test]
+    fn text_joins_the_search_index() {
+        let filter = CharacterFilter::parse("rem").unwrap();
+        let (sql, args) = filter.to_sql();
+
+        assert_eq!(
+            sql,
+            "from characters join characters_search on characters_search.rowid = characters.id where characters_search match ?"
+        );
+        assert_eq!(args, vec![text_query("rem")]);
+        assert!(filter.order_by().starts_with("order by bm25"));
+    }
+
+    #[test]
+    fn filters_without_text_skip_the_search_index() {
+        let filter = CharacterFilter::parse("stars:>=4 artist:foo tag:big_bad").unwrap();
+        let (sql, args) = filter.to_sql();
+
+        assert_eq!(
+            sql,
+            "from characters where characters.stars >= ? and characters.artist like ? and characters.id in (select character_id from characters_tags where tag_id in (select id from tags where name = ? union select tag_id from tag_names where lower(name) = ?))"
+        );
+        assert_eq!(
+            args,
+            vec![
+                rbs::to_value!(4u8),
+                rbs::to_value!("%foo%"),
+                rbs::to_value!("big_bad"),
+                rbs::to_value!("big bad"),
+            ]
+        );
+        assert_eq!(filter.order_by(), "order by characters.name, characters.id");
+    }
+
+    #[test]
+    fn punctuation_is_not_text() {
+        let filter = CharacterFilter {
+            text: "!!".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(filter.to_sql(), ("from characters".to_string(), Vec::new()));
+    }
+
+    #[test]
+    fn series_filters_keep_their_parameters_in_order() {
+        let filter =
+            CharacterFilter::parse("gender:male series:1 franchise:2 media:anime").unwrap();
+        let (sql, args) = filter.to_sql();
+
+        assert_eq!(sql.matches('?').count(), args.len());
+        assert_eq!(
+            args,
+            vec![
+                rbs::to_value!(Gender::Male),
+                rbs::to_value!(1i64),
+                rbs::to_value!(2i64),
+                rbs::to_value!(Media::Anime),
+            ]
+        );
+    }
+
+    #[test]
+    fn similar_names() {
+        assert!(is_similar_name("Uzumaki Naruto", "naruto"));
+        assert!(is_similar_name("Naruto Uzumaki", "uzumaki, naruto"));
+        assert!(is_similar_name("Mikasa Ackerman", "Mikasa Akerman"));
+    }
+
+    #[test]
+    fn different_names() {
+        assert!(!is_similar_name("Rem", "Ram"));
+        assert!(!is_similar_name("Al", "Al Elric"));
+        assert!(!is_similar_name("", "Rem"));
+        assert!(!is_similar_name("Light Yagami", "Misa Amane"));
+    }
+
+    #[test]
+    fn edit_distance_counts_characters() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("abc", "abc"), 0);
+        assert_eq!(edit_distance("café", "cafe"), 1);
+    }
+}
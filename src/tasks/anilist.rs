@@ -0,0 +1,166 @@
+use std::time::Duration;
+
+use rbatis::RBatis;
+
+use crate::{
+    anilist::FieldChange,
+    database::models::{Character, Series, SyncChange, SyncKind},
+    modules::Database,
+    Config, Result,
+};
+
+// Re-syncs the linked entries every `sync_interval` hours, does nothing when it's zero
+pub async fn sync_anilist(mut db: Database, config: Config) {
+    if config.anilist.sync_interval == 0 {
+        return;
+    }
+
+    loop {
+        tokio::time::sleep(Duration::from_secs(config.anilist.sync_interval * 3600)).await;
+
+        match sync_entries(db.get_conn(), &config).await {
+            Ok(changes) => log::info!("anilist sync found {} changes", changes),
+            Err(e) => log::error!("failed to sync with anilist: {}", e),
+        }
+    }
+}
+
+// Compares every linked character and series with AniList, the changes are applied right away
+// or queued for review depending on `auto_apply`; returns how many changes were found
+pub async fn sync_entries(conn: &mut RBatis, config: &Config) -> Result<usize> {
+    let url = &config.anilist.url;
+    let delay = Duration::from_millis(config.anilist.request_delay);
+
+    let mut found = 0;
+
+    for character in Character::select_linked(conn).await?.iter() {
+        let Some(anilist_id) = character.anilist_id else {
+            continue;
+        };
+
+        match crate::anilist::fetch_character(url, anilist_id).await {
+            Ok(ani_character) => {
+                let changes = ani_character.diff(character);
+                found += changes.len();
+
+                save_changes(conn, config, SyncKind::Character, character.id, changes).await?;
+            }
+            Err(e) => log::warn!("failed to fetch anilist character {}: {}", anilist_id, e),
+        }
+
+        tokio::time::sleep(delay).await;
+    }
+
+    for series in Series::select_linked(conn).await?.iter() {
+        let Some(anilist_id) = series.anilist_id else {
+            continue;
+        };
+
+        match crate::anilist::fetch_series(url, anilist_id).await {
+            Ok(media) => {
+                let changes = media.diff(series);
+                found += changes.len();
+
+                save_changes(conn, config, SyncKind::Series, series.id, changes).await?;
+            }
+            Err(e) => log::warn!("failed to fetch anilist media {}: {}", anilist_id, e),
+        }
+
+        tokio::time::sleep(delay).await;
+    }
+
+    Ok(found)
+}
+
+async fn save_changes(
+    conn: &mut RBatis,
+    config: &Config,
+    kind: SyncKind,
+    target_id: i64,
+    changes: Vec<FieldChange>,
+) -> Result<()> {
+    for change in changes {
+        // A newer change replaces the one still waiting for review
+        SyncChange::delete_by_field(conn, &kind, target_id, change.field).await?;
+
+        let last_id = SyncChange::select_last(conn)
+            .await?
+            .map_or(0, |sync_change| sync_change.id);
+        let sync_change = SyncChange {
+            id: last_id + 1,
+            kind: kind.clone(),
+            target_id,
+            field: change.field.to_string(),
+            old_value: change.old_value,
+            new_value: change.new_value,
+            created_at: chrono::Utc::now().timestamp(),
+        };
+
+        if config.anilist.auto_apply {
+            apply_change(conn, &sync_change).await?;
+        } else {
+            SyncChange::insert(conn, &sync_change).await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Writes a change to its entry, returns `false` when the entry no longer exists
+pub async fn apply_change(conn: &mut RBatis, sync_change: &SyncChange) -> Result<bool> {
+    let new_value = sync_change.new_value.clone();
+
+    match sync_change.kind {
+        SyncKind::Character => {
+            let Some(mut character) = Character::select_by_id(conn, sync_change.target_id).await?
+            else {
+                return Ok(false);
+            };
+
+            match sync_change.field.as_str() {
+                "name" => character.name = new_value,
+                "aliases" => character.aliases = serde_json::from_str(&new_value)?,
+                "image" => {
+                    let bytes =
+                        crate::images::normalize(&crate::utils::download_photo(&new_value).await?)?;
+                    let image_hash = crate::images::store(conn, &bytes).await?;
+                    for similar in
+                        crate::images::similar_characters(conn, &image_hash, character.id).await?
+                    {
+                        log::warn!(
+                            "the image of character {} looks like the one of {}",
+                            character.id,
+                            similar.id
+                        );
+                    }
+                    character.image_hash = Some(image_hash);
+                    character.image_link = new_value;
+                }
+                _ => return Ok(false),
+            }
+
+            Character::update_by_id(conn, &character, character.id).await?;
+        }
+        SyncKind::Series => {
+            let Some(mut series) = Series::select_by_id(conn, sync_change.target_id).await? else {
+                return Ok(false);
+            };
+
+            match sync_change.field.as_str() {
+                "name" => series.title = new_value,
+                "aliases" => series.aliases = serde_json::from_str(&new_value)?,
+                "image" => {
+                    let bytes =
+                        crate::images::normalize(&crate::utils::download_photo(&new_value).await?)?;
+                    series.banner_hash = Some(crate::images::store(conn, &bytes).await?);
+                    series.image_link = new_value;
+                }
+                _ => return Ok(false),
+            }
+
+            Series::update_by_id(conn, &series, series.id).await?;
+        }
+    }
+
+    Ok(true)
+}
@@ -0,0 +1,162 @@
+use std::time::Duration;
+
+use grammers_client::{session::PackedChat, Client, InputMessage};
+use rbatis::executor::Executor;
+
+use crate::{
+    database::models::{Auction, Character, Group, OwnedCharacter, Wallet},
+    modules::{Database, I18n},
+    Result,
+};
+
+// Periodically closes the auctions whose deadline has passed, since they are stored
+// in the database the pending ones are also closed after a restart
+pub async fn close_auctions(client: Client, mut db: Database, i18n: I18n, interval: u64) {
+    loop {
+        let now = chrono::Utc::now().timestamp();
+
+        match Auction::select_ended(db.get_conn(), now).await {
+            Ok(auctions) => {
+                for auction in auctions.iter() {
+                    if let Err(e) = close_auction(&client, &mut db, &i18n, auction).await {
+                        log::error!("failed to close auction {}: {}", auction.id, e);
+                    }
+                }
+            }
+            Err(e) => log::error!("failed to load ended auctions: {}", e),
+        }
+
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+async fn close_auction(
+    client: &Client,
+    db: &mut Database,
+    i18n: &I18n,
+    auction: &Auction,
+) -> Result<()> {
+    let conn = db.get_conn();
+
+    let character = Character::select_by_id(conn, auction.character_id).await?;
+
+    let tx = conn.acquire_begin().await?;
+    let (key, auction) = match settle_auction(&tx, auction.id, character.as_ref()).await {
+        Ok(Some(settled)) => settled,
+        Ok(None) => {
+            tx.rollback().await?;
+            return Ok(());
+        }
+        Err(e) => {
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+    tx.commit().await?;
+
+    let locale = Group::select_by_id(conn, auction.group_id)
+        .await?
+        .map(|group| group.language_code)
+        .unwrap_or_else(|| i18n.locale());
+    let name = character
+        .map(|character| crate::utils::escape_html(character.name))
+        .unwrap_or_else(|| auction.character_id.to_string());
+    let bidder = match auction.bidder_id {
+        Some(bidder_id) => crate::utils::mention_user(conn, bidder_id).await?,
+        None => String::new(),
+    };
+
+    if let Ok(chat) = PackedChat::from_bytes(&auction.chat) {
+        client
+            .send_message(
+                chat,
+                InputMessage::html(
+                    i18n.get_from_locale(&locale, key)
+                        .replace("{name}", &name)
+                        .replace(
+                            "{seller}",
+                            &crate::utils::mention_user(conn, auction.seller_id).await?,
+                        )
+                        .replace("{bidder}", &bidder)
+                        .replace("{bid}", &auction.bid.to_string()),
+                )
+                .reply_to(Some(auction.message_id)),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+// Moves the character to the highest bidder and the coins to the seller, the bid is
+// refunded when the seller no longer has the character or the bidder has no room for it.
+// The auction is read again so a bid placed since it was listed as ended is honoured,
+// returns `None` when it was already closed
+async fn settle_auction(
+    executor: &dyn Executor,
+    auction_id: i64,
+    character: Option<&Character>,
+) -> rbatis::Result<Option<(&'static str, Auction)>> {
+    let Some(auction) = Auction::select_by_id(executor, auction_id).await? else {
+        return Ok(None);
+    };
+    if !Auction::remove(executor, auction.id).await? {
+        return Ok(None);
+    }
+
+    let Some(bidder_id) = auction.bidder_id else {
+        return Ok(Some(("auction_no_bids", auction)));
+    };
+
+    // The art the seller had goes along with the character
+    let seller_character = OwnedCharacter::select_by_id(
+        executor,
+        auction.seller_id,
+        auction.group_id,
+        auction.character_id,
+    )
+    .await?;
+
+    let sold = match (character, seller_character) {
+        (Some(character), Some(seller_character)) => {
+            crate::utils::grant_character(
+                executor,
+                bidder_id,
+                auction.group_id,
+                character,
+                seller_character.variant_id,
+            )
+            .await?
+        }
+        _ => false,
+    };
+
+    if sold {
+        OwnedCharacter::delete_by_id(
+            executor,
+            auction.seller_id,
+            auction.group_id,
+            auction.character_id,
+        )
+        .await?;
+        Wallet::apply(
+            executor,
+            auction.seller_id,
+            auction.bid,
+            &format!("auction sale {}", auction.id),
+        )
+        .await?;
+
+        Ok(Some(("auction_sold", auction)))
+    } else {
+        Wallet::apply(
+            executor,
+            bidder_id,
+            auction.bid,
+            &format!("auction refund {}", auction.id),
+        )
+        .await?;
+
+        Ok(Some(("auction_cancelled", auction)))
+    }
+}
@@ -0,0 +1,5 @@
+mod anilist;
+mod auctions;
+
+pub use anilist::{apply_change, sync_anilist, sync_entries};
+pub use auctions::close_auctions;
@@ -1,13 +1,18 @@
-use std::io::Cursor;
+use std::{future::Future, io::Cursor};
 
 use grammers_client::{
-    types::{media::Uploaded, photo_sizes::VecExt, Downloadable, Photo},
-    Client,
+    grammers_tl_types as tl,
+    types::{photo_sizes::VecExt, Downloadable, Media as TeleMedia, Photo},
+    Client, InvocationError,
 };
-use rbatis::RBatis;
+use rbatis::{executor::Executor, RBatis};
 
 use crate::{
-    database::models::{Character, Gender, Media, Series},
+    database::models::{
+        Character, CharacterVariant, Gender, GroupCharacter, Inventory, Media, OwnedCharacter,
+        PhotoReference, Series, User,
+    },
+    modules::I18n,
     Result,
 };
 
@@ -106,6 +111,36 @@ pub fn construct_character_partial_info(
         .replace("{name}", &name)
 }
 
+pub fn construct_owned_info(owned_character: &OwnedCharacter) -> String {
+    if owned_character.level >= OwnedCharacter::MAX_LEVEL {
+        format!("🎖: Lv. <b>{}</b> (MAX)", owned_character.level)
+    } else {
+        format!(
+            "🎖: Lv. <b>{0}</b> | <code>{1}/{2}</code> XP",
+            owned_character.level,
+            owned_character.xp,
+            owned_character.xp_to_next_level()
+        )
+    }
+}
+
+pub fn construct_raid_info(
+    i18n: &I18n,
+    group_character: &GroupCharacter,
+    participants: usize,
+) -> String {
+    let remaining = (group_character.expires_at - chrono::Utc::now().timestamp()).max(0);
+
+    i18n.get("new_raid")
+        .replace("{required}", &group_character.required.to_string())
+        .replace("{remaining}", &remaining.to_string())
+        .replace("{progress}", &participants.to_string())
+}
+
+pub fn duplicate_xp(character: &Character) -> u32 {
+    25 * character.stars.max(1) as u32
+}
+
 pub fn construct_series_info(
     series: &Series,
     total_characters: usize,
@@ -137,6 +172,79 @@ pub fn construct_series_info(
         .replace("{media_type}", &series.media_type.to_string())
 }
 
+pub fn format_timestamp(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+pub fn parse_timestamp(text: &str) -> Option<i64> {
+    chrono::NaiveDateTime::parse_from_str(text.trim(), "%Y-%m-%d %H:%M")
+        .ok()
+        .map(|date| date.and_utc().timestamp())
+}
+
+pub fn format_duration(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+
+    format!("{}h {:02}m", seconds / 3600, (seconds % 3600) / 60)
+}
+
+// Parses durations like `30m`, `2h` or `1d`, plain numbers are minutes
+pub fn parse_duration(text: &str) -> Option<i64> {
+    let text = text.trim().to_lowercase();
+    let (number, unit) = match text.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((index, _)) => text.split_at(index),
+        None => (text.as_str(), "m"),
+    };
+    let number = number.parse::<i64>().ok()?;
+
+    match unit {
+        "s" => Some(number),
+        "m" | "min" => Some(number * 60),
+        "h" => Some(number * 3600),
+        "d" => Some(number * 86400),
+        _ => None,
+    }
+}
+
+pub fn parse_utc_offset(text: &str) -> Option<i32> {
+    let text = text
+        .trim()
+        .trim_start_matches("UTC")
+        .trim_start_matches("utc");
+    let (sign, rest) = match text.chars().next()? {
+        '+' => (1, &text[1..]),
+        '-' => (-1, &text[1..]),
+        _ => (1, text),
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((hours, minutes)) => (hours.parse::<i32>().ok()?, minutes.parse::<i32>().ok()?),
+        None => (rest.parse::<i32>().ok()?, 0),
+    };
+
+    if !(0..=14).contains(&hours) || !(0..60).contains(&minutes) {
+        return None;
+    }
+
+    Some(sign * (hours * 60 + minutes))
+}
+
+pub fn format_utc_offset(minutes: i32) -> String {
+    format!(
+        "UTC{0}{1:02}:{2:02}",
+        if minutes < 0 { '-' } else { '+' },
+        minutes.abs() / 60,
+        minutes.abs() % 60
+    )
+}
+
+pub fn parse_ids(text: &str) -> Vec<i64> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter_map(|part| part.trim().parse::<i64>().ok())
+        .collect()
+}
+
 pub fn media_type_symbol(media: &Media) -> &str {
     match media {
         Media::Anime => "📺",
@@ -158,64 +266,426 @@ pub fn gender_symbol(gender: &Gender) -> &str {
     }
 }
 
-pub async fn upload_banner(
+// What a sent photo shows, to find its stored reference or upload it again
+#[derive(Clone, Copy)]
+pub enum PhotoSource<'a> {
+    Character(&'a Character, Option<&'a CharacterVariant>),
+    Series(&'a Series),
+}
+
+impl PhotoSource<'_> {
+    fn key(&self) -> String {
+        match self {
+            Self::Character(character, variant) => photo_key(character, *variant),
+            Self::Series(series) => format!("series_{}", series.id),
+        }
+    }
+
+    async fn reference(
+        &self,
+        client: &mut Client,
+        conn: &mut RBatis,
+    ) -> Result<Option<PhotoReference>> {
+        match self {
+            Self::Character(character, variant) => {
+                character_reference(client, (*character).clone(), *variant, conn).await
+            }
+            Self::Series(series) => series_reference(client, series, conn).await,
+        }
+    }
+}
+
+// Telegram keeps every photo sent once, so the bytes are uploaded the first time only
+// and later sends reuse the stored reference. Those references expire, when Telegram
+// refuses one they are dropped and the photos uploaded again before sending once more
+pub async fn send_with_references<T, F, Fut>(
     client: &mut Client,
-    mut series: Series,
     conn: &mut RBatis,
-) -> Result<Option<Uploaded>> {
-    if let Some(bytes) = series.banner {
-        // Update series's banner bytes
-        series.banner = Some(bytes.clone());
-        Series::update_by_id(conn, &series, series.id).await?;
-
-        let mut stream = Cursor::new(&bytes);
-
-        Ok(Some(
-            client
-                .upload_stream(
-                    &mut stream,
-                    bytes.len(),
-                    format!("series_{}-{}.jpg", series.id, series.title),
-                )
-                .await?,
-        ))
-    } else {
-        Ok(None)
+    sources: &[PhotoSource<'_>],
+    mut send: F,
+) -> Result<T>
+where
+    F: FnMut(Vec<Option<PhotoReference>>) -> Fut,
+    Fut: Future<Output = std::result::Result<T, InvocationError>>,
+{
+    let mut references = Vec::with_capacity(sources.len());
+    for source in sources {
+        references.push(source.reference(client, conn).await?);
+    }
+
+    match send(references).await {
+        Err(e) if e.is("FILE_REFERENCE_*") => {
+            let mut references = Vec::with_capacity(sources.len());
+            for source in sources {
+                PhotoReference::delete_by_key(conn, &source.key()).await?;
+                references.push(source.reference(client, conn).await?);
+            }
+
+            Ok(send(references).await?)
+        }
+        result => Ok(result?),
     }
 }
 
-pub async fn upload_photo(
+// Like `send_with_references`, with the photos ready to be copied into the messages
+pub async fn send_photos<T, F, Fut>(
     client: &mut Client,
-    mut character: Character,
     conn: &mut RBatis,
-) -> Result<Option<Uploaded>> {
-    let bytes = character.image.unwrap_or({
-        let bytes = if let Some(id) = character.anilist_id {
-            download_ani_image(id).await?
-        } else {
-            Vec::new()
-        };
+    sources: &[PhotoSource<'_>],
+    mut send: F,
+) -> Result<T>
+where
+    F: FnMut(Vec<Option<TeleMedia>>) -> Fut,
+    Fut: Future<Output = std::result::Result<T, InvocationError>>,
+{
+    let photo_client = client.clone();
+    send_with_references(client, conn, sources, |references| {
+        send(
+            references
+                .iter()
+                .map(|reference| {
+                    reference
+                        .as_ref()
+                        .map(|reference| photo_media(&photo_client, reference))
+                })
+                .collect(),
+        )
+    })
+    .await
+}
 
-        bytes
-    });
+pub async fn send_photo<T, F, Fut>(
+    client: &mut Client,
+    conn: &mut RBatis,
+    source: PhotoSource<'_>,
+    mut send: F,
+) -> Result<T>
+where
+    F: FnMut(Option<TeleMedia>) -> Fut,
+    Fut: Future<Output = std::result::Result<T, InvocationError>>,
+{
+    send_photos(client, conn, &[source], |mut files| {
+        send(files.pop().flatten())
+    })
+    .await
+}
 
-    // Update character's image bytes
-    character.image = Some(bytes.clone());
-    Character::update_by_id(conn, &character, character.id).await?;
+pub async fn series_reference(
+    client: &mut Client,
+    series: &Series,
+    conn: &mut RBatis,
+) -> Result<Option<PhotoReference>> {
+    let key = format!("series_{}", series.id);
+    if let Some(reference) = PhotoReference::select_by_key(conn, &key).await? {
+        return Ok(Some(reference));
+    }
 
-    let mut stream = Cursor::new(&bytes);
+    let Some(ref hash) = series.banner_hash else {
+        return Ok(None);
+    };
+    let bytes = crate::images::load(hash)?;
 
     Ok(Some(
-        client
-            .upload_stream(
-                &mut stream,
-                bytes.len(),
-                format!("char_{}-{}.jpg", character.id, character.name),
+        store_photo(
+            client,
+            conn,
+            key,
+            &bytes,
+            format!("series_{}-{}.jpg", series.id, series.title),
+        )
+        .await?,
+    ))
+}
+
+pub async fn character_reference(
+    client: &mut Client,
+    mut character: Character,
+    variant: Option<&CharacterVariant>,
+    conn: &mut RBatis,
+) -> Result<Option<PhotoReference>> {
+    let key = photo_key(&character, variant);
+    if let Some(reference) = PhotoReference::select_by_key(conn, &key).await? {
+        return Ok(Some(reference));
+    }
+
+    if let Some((variant_id, image_hash)) = variant.and_then(|variant| {
+        variant
+            .image_hash
+            .as_ref()
+            .map(|image_hash| (variant.id, image_hash))
+    }) {
+        let bytes = crate::images::load(image_hash)?;
+
+        return Ok(Some(
+            store_photo(
+                client,
+                conn,
+                key,
+                &bytes,
+                format!(
+                    "char_{}_{}-{}.jpg",
+                    character.id, variant_id, character.name
+                ),
             )
             .await?,
+        ));
+    }
+
+    // Characters without an image get the one from AniList, when they're linked to it
+    let bytes = match character.image_hash.as_deref().map(crate::images::load) {
+        Some(Ok(bytes)) => bytes,
+        _ => {
+            let bytes = if let Some(id) = character.anilist_id {
+                crate::images::normalize(&download_ani_image(id).await?)?
+            } else {
+                Vec::new()
+            };
+
+            if !bytes.is_empty() {
+                character.image_hash = Some(crate::images::store(conn, &bytes).await?);
+                Character::update_by_id(conn, &character, character.id).await?;
+            }
+
+            bytes
+        }
+    };
+
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        store_photo(
+            client,
+            conn,
+            key,
+            &bytes,
+            format!("char_{}-{}.jpg", character.id, character.name),
+        )
+        .await?,
     ))
 }
 
+// The key of the stored reference of the character art, dropped when Telegram rejects it
+pub fn photo_key(character: &Character, variant: Option<&CharacterVariant>) -> String {
+    match variant.filter(|variant| variant.image_hash.is_some()) {
+        Some(variant) => format!("variant_{}", variant.id),
+        None => format!("character_{}", character.id),
+    }
+}
+
+// A collage of a few of the series characters, shown when it has no banner; the characters
+// change each time, so it's uploaded without keeping its reference
+pub async fn upload_series_collage(
+    client: &mut Client,
+    series: &Series,
+    conn: &mut RBatis,
+    size: u16,
+) -> Result<Option<TeleMedia>> {
+    let images = Character::select_random_with_image_by_series(conn, series.id, size)
+        .await?
+        .into_iter()
+        .filter_map(|character| character.image_hash)
+        .filter_map(|hash| crate::images::load(&hash).ok())
+        .collect::<Vec<_>>();
+    if images.is_empty() {
+        return Ok(None);
+    }
+
+    let bytes = crate::images::collage(&images)?;
+    let photo =
+        upload_photo_bytes(client, &bytes, format!("series_{}-collage.jpg", series.id)).await?;
+
+    Ok(Some(TeleMedia::Photo(Photo::from_raw(
+        photo.into(),
+        client.clone(),
+    ))))
+}
+
+// Uploads the photo and keeps the reference of the copy Telegram stores
+async fn store_photo(
+    client: &mut Client,
+    conn: &mut RBatis,
+    key: String,
+    bytes: &[u8],
+    name: String,
+) -> Result<PhotoReference> {
+    let photo = upload_photo_bytes(client, bytes, name).await?;
+
+    let reference = PhotoReference {
+        key,
+        photo_id: photo.id,
+        access_hash: photo.access_hash,
+        file_reference: photo.file_reference,
+    };
+    PhotoReference::delete_by_key(conn, &reference.key).await?;
+    PhotoReference::insert(conn, &reference).await?;
+
+    Ok(reference)
+}
+
+async fn upload_photo_bytes(
+    client: &mut Client,
+    bytes: &[u8],
+    name: String,
+) -> Result<tl::types::Photo> {
+    let mut stream = Cursor::new(bytes);
+    let uploaded = client.upload_stream(&mut stream, bytes.len(), name).await?;
+
+    let media = client
+        .invoke(&tl::functions::messages::UploadMedia {
+            business_connection_id: None,
+            peer: tl::enums::InputPeer::PeerSelf,
+            media: tl::types::InputMediaUploadedPhoto {
+                spoiler: false,
+                file: uploaded.raw,
+                stickers: None,
+                ttl_seconds: None,
+            }
+            .into(),
+        })
+        .await?;
+
+    let tl::enums::MessageMedia::Photo(tl::types::MessageMediaPhoto {
+        photo: Some(tl::enums::Photo::Photo(photo)),
+        ..
+    }) = media
+    else {
+        return Err("telegram didn't return a photo".into());
+    };
+
+    Ok(photo)
+}
+
+// Only the id, the access hash and the file reference are needed to send the photo again
+pub fn photo_media(client: &Client, reference: &PhotoReference) -> TeleMedia {
+    TeleMedia::Photo(Photo::from_raw(
+        tl::types::Photo {
+            has_stickers: false,
+            id: reference.photo_id,
+            access_hash: reference.access_hash,
+            file_reference: reference.file_reference.clone(),
+            date: 0,
+            sizes: Vec::new(),
+            video_sizes: None,
+            dc_id: 0,
+        }
+        .into(),
+        client.clone(),
+    ))
+}
+
+// Gives a character to the user, or XP when they already own it,
+// returns `false` when there's no room left in the collection
+pub async fn grant_character(
+    executor: &dyn Executor,
+    user_id: i64,
+    group_id: i64,
+    character: &Character,
+    variant_id: Option<i64>,
+) -> rbatis::Result<bool> {
+    if OwnedCharacter::select_by_id(executor, user_id, group_id, character.id)
+        .await?
+        .is_none()
+    {
+        if OwnedCharacter::select_by_user(executor, user_id, group_id)
+            .await?
+            .len()
+            >= Inventory::select_or_default(executor, user_id)
+                .await?
+                .capacity()
+        {
+            return Ok(false);
+        }
+
+        // A copy given at the same moment is already there, it's turned into XP below
+        if OwnedCharacter::insert_new(
+            executor,
+            &OwnedCharacter::new(user_id, group_id, character.id, variant_id),
+        )
+        .await?
+        {
+            return Ok(true);
+        }
+    }
+
+    add_duplicate_xp(executor, user_id, group_id, character, variant_id).await?;
+
+    Ok(true)
+}
+
+// Turns a duplicate into XP for the owned copy, returns it and whether it leveled up
+pub async fn add_duplicate_xp(
+    executor: &dyn Executor,
+    user_id: i64,
+    group_id: i64,
+    character: &Character,
+    variant_id: Option<i64>,
+) -> rbatis::Result<Option<(OwnedCharacter, bool)>> {
+    let Some(mut owned_character) =
+        OwnedCharacter::select_by_id(executor, user_id, group_id, character.id).await?
+    else {
+        return Ok(None);
+    };
+
+    // A duplicate with another art replaces the owned one, the default art never does
+    if variant_id.is_some() {
+        owned_character.variant_id = variant_id;
+    }
+    let leveled_up = owned_character.add_xp(duplicate_xp(character));
+    OwnedCharacter::update_by_id(executor, &owned_character, user_id, group_id, character.id)
+        .await?;
+
+    Ok(Some((owned_character, leveled_up)))
+}
+
+pub async fn mention_user(executor: &dyn Executor, user_id: i64) -> rbatis::Result<String> {
+    let name = match User::select_by_id(executor, user_id).await? {
+        Some(user) => escape_html(user.full_name),
+        None => user_id.to_string(),
+    };
+
+    Ok(format!("<a href='tg://user?id={0}'>{1}</a>", user_id, name))
+}
+
+pub fn guess_matches(guess: &str, names: Vec<String>) -> bool {
+    let guess_splitted = guess.split_whitespace().collect::<Vec<&str>>();
+
+    if guess_splitted.len() == 1 {
+        for name in names.iter() {
+            if name.len() == 1 {
+                return guess == *name;
+            } else {
+                for part in name.split_whitespace() {
+                    if guess == part {
+                        return true;
+                    }
+                }
+            }
+        }
+    } else {
+        for name in names.iter() {
+            if name.contains(guess) {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+pub fn mask_name(name: &str) -> String {
+    name.split_whitespace()
+        .map(|part| {
+            part.chars()
+                .enumerate()
+                .map(|(index, c)| if index == 0 { c } else { '_' })
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
 pub async fn download_photo(url: &str) -> Result<Vec<u8>> {
     let response = reqwest::get(url).await?;
     let content = response.bytes().await?;
@@ -245,3 +715,15 @@ pub async fn download_tele_photo(client: &mut Client, photo: Photo) -> Result<Ve
 
     Ok(bytes)
 }
+
+pub async fn download_tele_media(client: &mut Client, media: TeleMedia) -> Result<Vec<u8>> {
+    let downloadable = Downloadable::Media(media);
+    let mut download = client.iter_download(&downloadable);
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = download.next().await? {
+        bytes.extend(chunk);
+    }
+
+    Ok(bytes)
+}